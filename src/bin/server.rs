@@ -0,0 +1,27 @@
+use bevy::prelude::*;
+use sky_quest::config::Config;
+use sky_quest::net::dedicated::{ServerConfig, ServerOptions, USAGE};
+use sky_quest::{add_game_plugins, add_headless_plugins};
+
+fn log_settings(config: Res<ServerConfig>) {
+    info!(
+        "Dedicated server for up to {} players at {} ticks a second, terrain {} blocks tall",
+        config.max_players, config.tick_rate, config.terrain_height
+    );
+}
+
+fn main() {
+    let options = match ServerOptions::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => std::process::exit(err.report(USAGE)),
+    };
+    let config = ServerConfig::load(&options.config).with_overrides(&options);
+
+    let mut app = App::new();
+    add_headless_plugins(&mut app, config.tick_rate);
+    // The server plays no part itself, so the player's own config is left alone.
+    add_game_plugins(&mut app, Config::default(), config.launch_options());
+    app.insert_resource(config)
+        .add_startup_system(log_settings)
+        .run();
+}
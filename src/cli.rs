@@ -0,0 +1,280 @@
+use crate::prelude::*;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: sky-quest [OPTIONS]
+
+Options:
+  --windowed              Run in a window
+  --fullscreen            Run borderless fullscreen
+  --resolution <WxH>      Window resolution, e.g. 1920x1080
+  --vsync / --no-vsync    Enable or disable vsync
+  --skip-splash           Start at the main menu
+  --seed <n>              Start a game on the given map seed
+  --mode <mode>           Start a game in classic or zen mode
+  --difficulty <level>    Start a game on easy, normal or hard
+  --players <n>           Start a split-screen game for 1 to 4 players
+  --bots <n>              Start a game against 0 to 3 bots
+  --bot-skill <level>     Set the bots to easy, normal or hard
+  --play                  Start a game straight away
+  --load <file>           Start a game from a save file
+  --replay <file>         Start a game racing the given ghost file
+  --spectate <view>       Start a game watching in free, follow or overview
+  --headless <frames>     Run without a window for the given number of frames
+  --host <port>           Host a multiplayer game on the given UDP port
+  --max-players <n>       Limit how many players can join a hosted game
+  --connect <addr>        Join a multiplayer game, e.g. 127.0.0.1:7777
+  -h, --help              Print this help";
+
+/// Options given on the command line. They apply to this session only and are
+/// never written back to the config file.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct LaunchOptions {
+    pub fullscreen: Option<bool>,
+    pub resolution: Option<(f32, f32)>,
+    pub vsync: Option<bool>,
+    pub skip_splash: bool,
+    pub seed: Option<u32>,
+    pub mode: Option<GameMode>,
+    pub difficulty: Option<Difficulty>,
+    pub players: Option<LocalPlayers>,
+    pub bots: Option<BotCount>,
+    pub bot_skill: Option<BotSkill>,
+    pub play: bool,
+    pub load: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub spectate: Option<Spectator>,
+    pub headless: Option<u32>,
+    pub host: Option<u16>,
+    pub max_players: Option<usize>,
+    pub connect: Option<String>,
+    /// Set by the dedicated server, which hosts without a player of its own.
+    pub dedicated: bool,
+    /// Set by the dedicated server to generate every map this tall, whatever
+    /// the display quality.
+    pub terrain_height: Option<u32>,
+}
+
+pub fn parse_mode(value: &str) -> Result<GameMode, String> {
+    match value {
+        "classic" => Ok(GameMode::Classic),
+        "zen" => Ok(GameMode::Zen),
+        _ => Err(format!("unknown mode `{value}`")),
+    }
+}
+
+pub fn parse_spectator_view(value: &str) -> Result<Spectator, String> {
+    match value {
+        "free" => Ok(Spectator::Free),
+        "follow" => Ok(Spectator::Follow(None)),
+        "overview" => Ok(Spectator::Overview),
+        _ => Err(format!("unknown spectator view `{value}`")),
+    }
+}
+
+pub fn parse_player_limit(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("invalid player limit `{value}`"))
+}
+
+/// Why the command line didn't give a set of options to run with.
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    /// `-h` or `--help` was given.
+    Help,
+    Invalid(String),
+}
+
+impl From<String> for ArgsError {
+    fn from(err: String) -> Self {
+        ArgsError::Invalid(err)
+    }
+}
+
+impl ArgsError {
+    /// Prints the help or the error followed by `usage`, and returns the exit
+    /// code to leave with.
+    pub fn report(&self, usage: &str) -> i32 {
+        match self {
+            ArgsError::Help => {
+                println!("{usage}");
+                0
+            }
+            ArgsError::Invalid(err) => {
+                eprintln!("error: {err}\n\n{usage}");
+                2
+            }
+        }
+    }
+}
+
+/// Command-line arguments, read a flag at a time.
+pub struct Args<I>(pub I);
+
+impl<I: Iterator<Item = String>> Iterator for Args<I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.0.next()
+    }
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    /// The value given after the flag `name`.
+    pub fn value(&mut self, name: &str) -> Result<String, ArgsError> {
+        self.0
+            .next()
+            .ok_or_else(|| ArgsError::Invalid(format!("missing value for `{name}`")))
+    }
+
+    /// The value given after the flag `name`, parsed as a `what`.
+    pub fn parse<T: FromStr>(&mut self, name: &str, what: &str) -> Result<T, ArgsError> {
+        let value = self.value(name)?;
+        value
+            .parse()
+            .map_err(|_| ArgsError::Invalid(format!("invalid {what} `{value}`")))
+    }
+}
+
+impl LaunchOptions {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut options = LaunchOptions::default();
+        let mut args = Args(args.into_iter());
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--windowed" => options.fullscreen = Some(false),
+                "--fullscreen" => options.fullscreen = Some(true),
+                "--resolution" => {
+                    let value = args.value(&arg)?;
+                    let (width, height) = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .ok_or_else(|| format!("invalid resolution `{value}`"))?;
+                    options.resolution = Some((width, height));
+                }
+                "--vsync" => options.vsync = Some(true),
+                "--no-vsync" => options.vsync = Some(false),
+                "--skip-splash" => options.skip_splash = true,
+                "--seed" => options.seed = Some(args.parse(&arg, "seed")?),
+                "--mode" => options.mode = Some(parse_mode(&args.value(&arg)?)?),
+                "--difficulty" => {
+                    let value = args.value(&arg)?;
+                    options.difficulty = Some(match value.as_str() {
+                        "easy" => Difficulty::Easy,
+                        "normal" => Difficulty::Normal,
+                        "hard" => Difficulty::Hard,
+                        _ => return Err(format!("unknown difficulty `{value}`").into()),
+                    });
+                }
+                "--players" => {
+                    let value = args.value(&arg)?;
+                    options.players = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| (1..=LocalPlayers::MAX).contains(count))
+                            .map(LocalPlayers)
+                            .ok_or_else(|| format!("invalid player count `{value}`"))?,
+                    );
+                }
+                "--bots" => {
+                    let value = args.value(&arg)?;
+                    options.bots = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count <= BotCount::MAX)
+                            .map(BotCount)
+                            .ok_or_else(|| format!("invalid bot count `{value}`"))?,
+                    );
+                }
+                "--bot-skill" => {
+                    let value = args.value(&arg)?;
+                    options.bot_skill = Some(match value.as_str() {
+                        "easy" => BotSkill::Easy,
+                        "normal" => BotSkill::Normal,
+                        "hard" => BotSkill::Hard,
+                        _ => return Err(format!("unknown bot skill `{value}`").into()),
+                    });
+                }
+                "--play" => options.play = true,
+                "--load" => options.load = Some(args.value(&arg)?.into()),
+                "--replay" => options.replay = Some(args.value(&arg)?.into()),
+                "--spectate" => options.spectate = Some(parse_spectator_view(&args.value(&arg)?)?),
+                "--headless" => options.headless = Some(args.parse(&arg, "frame count")?),
+                "--host" => options.host = Some(args.parse(&arg, "port")?),
+                "--max-players" => options.max_players = Some(parse_player_limit(&args.value(&arg)?)?),
+                "--connect" => options.connect = Some(args.value(&arg)?),
+                "-h" | "--help" => return Err(ArgsError::Help),
+                _ => return Err(format!("unknown argument `{arg}`").into()),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Whether the game should skip the menus and start a round immediately.
+    pub fn starts_game(&self) -> bool {
+        self.play
+            || self.seed.is_some()
+            || self.mode.is_some()
+            || self.difficulty.is_some()
+            || self.players.is_some()
+            || self.bots.is_some()
+            || self.load.is_some()
+            || self.replay.is_some()
+            || self.spectate.is_some()
+            || self.host.is_some()
+            || self.connect.is_some()
+    }
+
+    pub fn initial_state(&self) -> Option<GameState> {
+        if self.starts_game() {
+            Some(GameState::NewGame)
+        } else if self.skip_splash || self.headless.is_some() {
+            Some(GameState::Menu)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<LaunchOptions, ArgsError> {
+        LaunchOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse(&["--windowed", "--resolution", "800x600", "--seed", "42"]).unwrap();
+        assert_eq!(options.fullscreen, Some(false));
+        assert_eq!(options.resolution, Some((800.0, 600.0)));
+        assert_eq!(options.seed, Some(42));
+        assert!(options.starts_game());
+    }
+
+    #[test]
+    fn asks_for_help() {
+        assert_eq!(parse(&["--play", "--help"]).unwrap_err(), ArgsError::Help);
+        assert_eq!(parse(&["-h"]).unwrap_err(), ArgsError::Help);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let invalid = |args: &[&str]| match parse(args) {
+            Err(ArgsError::Invalid(err)) => err,
+            other => panic!("expected an error, got {other:?}"),
+        };
+        assert_eq!(invalid(&["--seed"]), "missing value for `--seed`");
+        assert_eq!(invalid(&["--seed", "abc"]), "invalid seed `abc`");
+        assert_eq!(invalid(&["--resolution", "800"]), "invalid resolution `800`");
+        assert_eq!(invalid(&["--mode", "hard"]), "unknown mode `hard`");
+        assert_eq!(invalid(&["--fast"]), "unknown argument `--fast`");
+    }
+}
@@ -0,0 +1,168 @@
+use crate::cli::LaunchOptions;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const CONFIG_PATH: &str = "config.ron";
+
+/// Settings persisted between sessions in `config.ron`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub display_quality: DisplayQuality,
+    pub fov: u32,
+    pub camera: CameraView,
+    pub audio: AudioSettings,
+    pub difficulty: Difficulty,
+    pub bots: usize,
+    pub bot_skill: BotSkill,
+    pub hidden_hud: Vec<HudWidget>,
+    pub fullscreen: bool,
+    pub resolution: (f32, f32),
+    pub vsync: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            display_quality: DisplayQuality::Medium,
+            fov: 4,
+            camera: CameraView::FirstPerson,
+            audio: AudioSettings::default(),
+            difficulty: Difficulty::Normal,
+            bots: 0,
+            bot_skill: BotSkill::Normal,
+            hidden_hud: Vec::new(),
+            fullscreen: true,
+            resolution: (1280.0, 720.0),
+            vsync: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        match fs::read_to_string(CONFIG_PATH) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring invalid {}: {}", CONFIG_PATH, err);
+                Config::default()
+            }),
+            Err(_) => Config::default(),
+        }
+    }
+
+    fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|contents| fs::write(CONFIG_PATH, contents).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("Failed to save {}: {}", CONFIG_PATH, err);
+        }
+    }
+
+    /// The settings used for this session, with launch options applied.
+    pub fn with_overrides(&self, options: &LaunchOptions) -> Config {
+        Config {
+            difficulty: options.difficulty.unwrap_or(self.difficulty),
+            bots: options.bots.map_or(self.bots, |bots| bots.0),
+            bot_skill: options.bot_skill.unwrap_or(self.bot_skill),
+            fullscreen: options.fullscreen.unwrap_or(self.fullscreen),
+            resolution: options.resolution.unwrap_or(self.resolution),
+            vsync: options.vsync.unwrap_or(self.vsync),
+            ..self.clone()
+        }
+    }
+}
+
+/// The config as it is on disk, so launch overrides are never saved.
+#[derive(Resource)]
+struct PersistedConfig(Config);
+
+#[allow(clippy::too_many_arguments)]
+fn save_config(
+    mut persisted: ResMut<PersistedConfig>,
+    options: Res<LaunchOptions>,
+    display_quality: Res<DisplayQuality>,
+    fov: Res<Fov>,
+    camera: Res<CameraView>,
+    audio: Res<AudioSettings>,
+    difficulty: Res<Difficulty>,
+    bot_count: Res<BotCount>,
+    bot_skill: Res<BotSkill>,
+    hud_settings: Res<HudSettings>,
+) {
+    if !(display_quality.is_changed()
+        || fov.is_changed()
+        || camera.is_changed()
+        || audio.is_changed()
+        || difficulty.is_changed()
+        || bot_count.is_changed()
+        || bot_skill.is_changed()
+        || hud_settings.is_changed())
+    {
+        return;
+    }
+    let mut hidden_hud: Vec<HudWidget> = hud_settings.hidden.iter().copied().collect();
+    hidden_hud.sort_by_key(|widget| HudWidget::ALL.iter().position(|w| w == widget));
+    let config = Config {
+        display_quality: *display_quality,
+        fov: fov.0,
+        camera: *camera,
+        audio: audio.clone(),
+        difficulty: if options.difficulty == Some(*difficulty) {
+            persisted.0.difficulty
+        } else {
+            *difficulty
+        },
+        bots: if options.bots == Some(*bot_count) {
+            persisted.0.bots
+        } else {
+            bot_count.0
+        },
+        bot_skill: if options.bot_skill == Some(*bot_skill) {
+            persisted.0.bot_skill
+        } else {
+            *bot_skill
+        },
+        hidden_hud,
+        ..persisted.0.clone()
+    };
+    if config != persisted.0 {
+        config.save();
+        persisted.0 = config;
+    }
+}
+
+pub struct ConfigPlugin {
+    pub persisted: Config,
+    pub options: LaunchOptions,
+}
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        let config = self.persisted.with_overrides(&self.options);
+        app.insert_resource(config.display_quality)
+            .insert_resource(Fov(config.fov))
+            .insert_resource(config.camera)
+            .insert_resource(config.audio.clone())
+            .insert_resource(config.difficulty)
+            .insert_resource(BotCount(config.bots))
+            .insert_resource(config.bot_skill)
+            .insert_resource(HudSettings {
+                hidden: config.hidden_hud.iter().copied().collect(),
+            })
+            .insert_resource(self.options.mode.unwrap_or_default())
+            .insert_resource(self.options.players.unwrap_or_default())
+            .insert_resource(WorldSeed {
+                next: self.options.seed,
+                pinned_height: self.options.terrain_height,
+                ..default()
+            })
+            .insert_resource(PersistedConfig(self.persisted.clone()))
+            .insert_resource(self.options.clone())
+            .add_system(save_config);
+        if let Some(state) = self.options.initial_state() {
+            app.insert_resource(NextState(Some(state)));
+        }
+    }
+}
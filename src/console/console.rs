@@ -0,0 +1,399 @@
+use crate::game::sound::parse_bus;
+use crate::player::player::{calculate_fov, MovementSettings};
+use crate::prelude::*;
+use bevy::utils::HashMap;
+use bevy::window::ReceivedCharacter;
+
+const CONSOLE_TOGGLE: KeyCode = KeyCode::Grave;
+const MAX_LOG_LINES: usize = 200;
+const VISIBLE_LOG_LINES: usize = 14;
+
+/// Runs a console command. Receives the arguments after the command name and
+/// returns the text to print, or an error message.
+pub type ConsoleCommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+#[derive(Clone)]
+pub struct ConsoleCommand {
+    pub usage: &'static str,
+    /// Values offered by tab completion for the first argument.
+    pub completions: &'static [&'static str],
+    pub run: ConsoleCommandFn,
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(HashMap<&'static str, ConsoleCommand>);
+
+impl ConsoleCommands {
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.0.get(name)
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.0.keys().copied().collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+pub trait ConsoleAppExt {
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        completions: &'static [&'static str],
+        run: ConsoleCommandFn,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        completions: &'static [&'static str],
+        run: ConsoleCommandFn,
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .0
+            .insert(
+                name,
+                ConsoleCommand {
+                    usage,
+                    completions,
+                    run,
+                },
+            );
+        self
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleState {
+    pub open: bool,
+    input: String,
+    log: Vec<String>,
+    history: Vec<String>,
+    history_index: Option<usize>,
+    pending: Vec<String>,
+}
+
+impl ConsoleState {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.log.extend(line.into().lines().map(String::from));
+        let overflow = self.log.len().saturating_sub(MAX_LOG_LINES);
+        self.log.drain(..overflow);
+    }
+}
+
+/// Run condition for systems that read gameplay input.
+pub fn console_closed(state: Res<ConsoleState>) -> bool {
+    !state.open
+}
+
+#[derive(Component)]
+struct ConsoleRoot;
+
+#[derive(Component)]
+struct ConsoleText;
+
+fn console_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(40.0)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(0.0),
+                        left: Val::Px(0.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::FlexEnd,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    display: Display::None,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            ConsoleRoot,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 18.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                ),
+                ConsoleText,
+            ));
+        });
+}
+
+fn toggle_console(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut state: ResMut<ConsoleState>,
+    mut root: Query<&mut Style, With<ConsoleRoot>>,
+) {
+    if keyboard_input.just_pressed(CONSOLE_TOGGLE) {
+        state.open = !state.open;
+        for mut style in &mut root {
+            style.display = if state.open {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+    }
+}
+
+fn complete(state: &mut ConsoleState, commands: &ConsoleCommands) {
+    let input = state.input.clone();
+    let mut words: Vec<&str> = input.split(' ').collect();
+    let prefix = words.pop().unwrap_or_default();
+    let candidates: Vec<&str> = match words.as_slice() {
+        [] => commands.names(),
+        [name] => commands
+            .get(name)
+            .map(|command| command.completions.to_vec())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    let matches: Vec<&str> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .collect();
+
+    let completed = match matches.as_slice() {
+        [] => return,
+        [single] => format!("{single} "),
+        [first, rest @ ..] => {
+            let common = rest.iter().fold(first.len(), |len, candidate| {
+                first
+                    .bytes()
+                    .zip(candidate.bytes())
+                    .take(len)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            });
+            let line = matches.join("  ");
+            state.print(line);
+            first[..common].to_string()
+        }
+    };
+    words.push(&completed);
+    state.input = words.join(" ");
+}
+
+fn console_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut state: ResMut<ConsoleState>,
+    commands: Res<ConsoleCommands>,
+) {
+    if !state.open {
+        characters.clear();
+        return;
+    }
+    for event in characters.iter() {
+        if !event.char.is_control() && event.char != '`' && event.char != '~' {
+            state.input.push(event.char);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        state.input.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        complete(&mut state, &commands);
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) && !state.history.is_empty() {
+        let index = state
+            .history_index
+            .map_or(state.history.len() - 1, |index| index.saturating_sub(1));
+        state.history_index = Some(index);
+        state.input = state.history[index].clone();
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        if let Some(index) = state.history_index {
+            if index + 1 < state.history.len() {
+                state.history_index = Some(index + 1);
+                state.input = state.history[index + 1].clone();
+            } else {
+                state.history_index = None;
+                state.input.clear();
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut state.input).trim().to_string();
+        state.history_index = None;
+        if !line.is_empty() {
+            state.print(format!("> {line}"));
+            if state.history.last() != Some(&line) {
+                state.history.push(line.clone());
+            }
+            state.pending.push(line);
+        }
+    }
+}
+
+fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<ConsoleState>().pending);
+    for line in pending {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((name, args)) = words.split_first() else {
+            continue;
+        };
+        let command = world.resource::<ConsoleCommands>().get(name).cloned();
+        let output = match command {
+            Some(command) => (command.run)(world, args).unwrap_or_else(|err| {
+                if err.is_empty() {
+                    format!("usage: {}", command.usage)
+                } else {
+                    format!("error: {err}\nusage: {}", command.usage)
+                }
+            }),
+            None => format!("unknown command `{name}`, try `help`"),
+        };
+        if !output.is_empty() {
+            world.resource_mut::<ConsoleState>().print(output);
+        }
+    }
+}
+
+fn update_console_text(state: Res<ConsoleState>, mut text: Query<&mut Text, With<ConsoleText>>) {
+    if !state.is_changed() {
+        return;
+    }
+    let start = state.log.len().saturating_sub(VISIBLE_LOG_LINES);
+    for mut text in &mut text {
+        text.sections[0].value = format!("{}\n> {}_", state.log[start..].join("\n"), state.input);
+    }
+}
+
+/// Parses the single argument of a command, with an empty error for usage.
+pub fn parse_arg<T: std::str::FromStr>(args: &[&str], index: usize) -> Result<T, String> {
+    let arg = args.get(index).ok_or_else(String::new)?;
+    arg.parse().map_err(|_| format!("invalid value `{arg}`"))
+}
+
+fn help_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let commands = world.resource::<ConsoleCommands>();
+    Ok(commands
+        .names()
+        .into_iter()
+        .filter_map(|name| commands.get(name))
+        .map(|command| command.usage)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn clear_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    world.resource_mut::<ConsoleState>().log.clear();
+    Ok(String::new())
+}
+
+fn timescale_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let scale: f32 = parse_arg(args, 0)?;
+    if scale <= 0.0 {
+        return Err("time scale must be positive".into());
+    }
+    world.resource_mut::<Time>().set_relative_speed(scale);
+    Ok(format!("time scale {scale}"))
+}
+
+fn set_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let setting = *args.first().ok_or_else(String::new)?;
+    let value = *args.get(1).ok_or_else(String::new)?;
+    match setting {
+        "volume" | "music" | "sfx" | "ui" => {
+            let level = parse_arg::<u32>(args, 1)?.min(9);
+            if let Some(bus) = parse_bus(setting) {
+                world.resource_mut::<AudioSettings>().channel_mut(bus).level = level;
+            }
+        }
+        "fov" => {
+            let fov = parse_arg::<u32>(args, 1)?.min(9);
+            world.insert_resource(Fov(fov));
+            let mut cameras = world.query_filtered::<&mut Projection, With<Camera3d>>();
+            for mut projection in cameras.iter_mut(world) {
+                if let Projection::Perspective(perspective) = &mut *projection {
+                    perspective.fov = calculate_fov(fov as f32).to_radians();
+                }
+            }
+        }
+        "camera" => world.insert_resource(match value {
+            "first" => CameraView::FirstPerson,
+            "third" => CameraView::ThirdPerson,
+            _ => return Err(format!("unknown camera `{value}`")),
+        }),
+        "quality" => world.insert_resource(match value {
+            "low" => DisplayQuality::Low,
+            "medium" => DisplayQuality::Medium,
+            "high" => DisplayQuality::High,
+            _ => return Err(format!("unknown quality `{value}`")),
+        }),
+        "difficulty" => world.insert_resource(match value {
+            "easy" => Difficulty::Easy,
+            "normal" => Difficulty::Normal,
+            "hard" => Difficulty::Hard,
+            _ => return Err(format!("unknown difficulty `{value}`")),
+        }),
+        "mode" => {
+            // Mods can add modes whose names have spaces in them.
+            let name = args[1..].join(" ");
+            let index = world
+                .resource::<GameModes>()
+                .modes
+                .iter()
+                .position(|mode| mode.name.eq_ignore_ascii_case(&name))
+                .ok_or_else(|| format!("unknown mode `{name}`"))?;
+            world.resource_mut::<ModeChoice>().0 = index;
+        }
+        "sensitivity" => world.resource_mut::<MovementSettings>().sensitivity = parse_arg(args, 1)?,
+        "speed" => world.resource_mut::<MovementSettings>().speed = parse_arg(args, 1)?,
+        _ => return Err(format!("unknown setting `{setting}`")),
+    }
+    Ok(format!("{setting} = {value}"))
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConsoleState>()
+            .init_resource::<ConsoleCommands>()
+            .add_startup_system(console_setup)
+            .add_system(toggle_console)
+            .add_system(console_input.after(toggle_console))
+            .add_system(run_console_commands.after(console_input))
+            .add_system(update_console_text.after(run_console_commands))
+            .add_console_command("help", "help", &[], help_command)
+            .add_console_command("clear", "clear", &[], clear_command)
+            .add_console_command("timescale", "timescale <factor>", &[], timescale_command)
+            .add_console_command(
+                "set",
+                "set <volume|music|sfx|ui|fov|camera|quality|difficulty|mode|sensitivity|speed> <value>",
+                &[
+                    "volume",
+                    "music",
+                    "sfx",
+                    "ui",
+                    "fov",
+                    "camera",
+                    "quality",
+                    "difficulty",
+                    "mode",
+                    "sensitivity",
+                    "speed",
+                ],
+                set_command,
+            );
+    }
+}
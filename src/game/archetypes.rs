@@ -0,0 +1,446 @@
+use crate::prelude::*;
+use crate::game::mods::Mods;
+use bevy::asset::{AssetLoader, FileAssetIo, LoadContext, LoadedAsset};
+use bevy::ecs::system::EntityCommands;
+use bevy::pbr::{CascadeShadowConfig, Cascades, CascadesVisibleEntities, CubemapVisibleEntities};
+use bevy::render::primitives::{CascadesFrusta, CubemapFrusta};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+const ARCHETYPE_DIR: &str = "archetypes";
+const ARCHETYPE_EXTENSION: &str = "archetype.ron";
+
+pub const BLUE_BALL: &str = "blue_ball";
+pub const RED_BALL: &str = "red_ball";
+pub const WALL: &str = "wall";
+pub const SUN: &str = "sun";
+pub const PLAYER_LIGHT: &str = "player_light";
+/// How bouncy red balls are out of water.
+const ENEMY_RESTITUTION: f32 = 2.1;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum Shape {
+    Sphere { radius: f32 },
+    Box { size: (f32, f32, f32) },
+}
+
+impl Shape {
+    fn mesh(self) -> Mesh {
+        match self {
+            Shape::Sphere { radius } => shape::UVSphere {
+                radius,
+                ..default()
+            }
+            .into(),
+            Shape::Box { size: (x, y, z) } => shape::Box::new(x, y, z).into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub enum ColliderShape {
+    #[default]
+    None,
+    Ball { radius: f32 },
+    Cuboid { half_extents: (f32, f32, f32) },
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Body {
+    Fixed,
+    Dynamic,
+}
+
+/// Gameplay markers an archetype can give its entities.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tag {
+    BlueBall,
+    Enemy,
+    Renderable,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LightKind {
+    /// Shines every way from the entity, out to `range`.
+    #[default]
+    Point,
+    /// Shines one way over the whole map, like the sun. `intensity` is its
+    /// illuminance and `range` goes unused.
+    Directional,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct LightDef {
+    pub kind: LightKind,
+    pub color: (f32, f32, f32),
+    pub intensity: f32,
+    pub range: f32,
+    pub shadows: bool,
+}
+
+impl Default for LightDef {
+    fn default() -> Self {
+        LightDef {
+            kind: LightKind::Point,
+            color: (1.0, 1.0, 1.0),
+            intensity: 800.0,
+            range: 20.0,
+            shadows: false,
+        }
+    }
+}
+
+/// A kind of entity, read from `assets/archetypes/<name>.archetype.ron` or the
+/// same path in a mod.
+#[derive(Deserialize, TypeUuid, Clone, Debug)]
+#[uuid = "5b0c8f2e-7d1a-4e63-9c3b-8a4f1e2d6c70"]
+#[serde(default)]
+pub struct Archetype {
+    pub mesh: Option<Shape>,
+    pub color: (f32, f32, f32),
+    pub roughness: Option<f32>,
+    pub emissive: (f32, f32, f32),
+    pub collider: ColliderShape,
+    pub body: Option<Body>,
+    pub restitution: Option<f32>,
+    pub gravity_scale: Option<f32>,
+    pub tags: Vec<Tag>,
+    pub light: Option<LightDef>,
+    /// Chance of one being dropped on each open patch of ground when a map is
+    /// generated, so new kinds of ball can be added without code.
+    pub scatter: f32,
+}
+
+impl Default for Archetype {
+    fn default() -> Self {
+        Archetype {
+            mesh: None,
+            color: (1.0, 1.0, 1.0),
+            roughness: None,
+            emissive: (0.0, 0.0, 0.0),
+            collider: ColliderShape::None,
+            body: None,
+            restitution: None,
+            gravity_scale: None,
+            tags: Vec::new(),
+            light: None,
+            scatter: 0.0,
+        }
+    }
+}
+
+impl Archetype {
+    /// The archetypes the game relies on, used until their files are loaded
+    /// or if they are missing.
+    fn builtin(name: &str) -> Option<Archetype> {
+        let ball = |color| Archetype {
+            mesh: Some(Shape::Sphere { radius: 1.0 }),
+            color,
+            collider: ColliderShape::Ball { radius: 1.0 },
+            ..default()
+        };
+        match name {
+            BLUE_BALL => Some(Archetype {
+                body: Some(Body::Fixed),
+                tags: vec![Tag::BlueBall, Tag::Renderable],
+                ..ball((0.0, 0.0, 1.0))
+            }),
+            RED_BALL => Some(Archetype {
+                body: Some(Body::Dynamic),
+                restitution: Some(ENEMY_RESTITUTION),
+                tags: vec![Tag::Enemy],
+                ..ball((1.0, 0.0, 0.0))
+            }),
+            WALL => Some(Archetype {
+                mesh: Some(Shape::Box {
+                    size: (64.0, 0.9, 100.0),
+                }),
+                color: (0.5, 0.5, 0.5),
+                roughness: Some(1.0),
+                collider: ColliderShape::Cuboid {
+                    half_extents: (32.0, 0.45, 50.0),
+                },
+                body: Some(Body::Fixed),
+                ..default()
+            }),
+            SUN => Some(Archetype {
+                light: Some(LightDef {
+                    kind: LightKind::Directional,
+                    intensity: 3200.0,
+                    ..default()
+                }),
+                ..default()
+            }),
+            PLAYER_LIGHT => Some(Archetype {
+                light: Some(LightDef {
+                    intensity: 3000.0,
+                    shadows: true,
+                    ..default()
+                }),
+                ..default()
+            }),
+            _ => None,
+        }
+    }
+
+    fn material(&self) -> StandardMaterial {
+        let (r, g, b) = self.color;
+        let (er, eg, eb) = self.emissive;
+        let mut material = StandardMaterial {
+            base_color: Color::rgb(r, g, b),
+            emissive: Color::rgb(er, eg, eb),
+            ..default()
+        };
+        if let Some(roughness) = self.roughness {
+            material.perceptual_roughness = roughness;
+        }
+        material
+    }
+}
+
+#[derive(Default)]
+struct ArchetypeLoader;
+
+impl AssetLoader for ArchetypeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let archetype: Archetype = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(archetype));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[ARCHETYPE_EXTENSION]
+    }
+}
+
+/// Every archetype file found at startup, by name.
+#[derive(Resource, Default)]
+pub struct Archetypes(HashMap<String, Handle<Archetype>>);
+
+impl Archetypes {
+    /// The named archetype as it was last loaded, falling back to the
+    /// built-in one.
+    pub fn get(&self, name: &str, assets: &Assets<Archetype>) -> Option<Archetype> {
+        self.0
+            .get(name)
+            .and_then(|handle| assets.get(handle))
+            .cloned()
+            .or_else(|| Archetype::builtin(name))
+    }
+
+    /// The loaded archetypes that are scattered over new maps, sorted by name
+    /// so a seed always places the same ones.
+    pub fn scattered(&self, assets: &Assets<Archetype>) -> Vec<(String, Archetype)> {
+        let mut scattered: Vec<(String, Archetype)> = self
+            .0
+            .iter()
+            .filter_map(|(name, handle)| Some((name.clone(), assets.get(handle)?.clone())))
+            .filter(|(_, archetype)| archetype.scatter > 0.0)
+            .collect();
+        scattered.sort_by(|a, b| a.0.cmp(&b.0));
+        scattered
+    }
+
+    fn name_of(&self, handle: &Handle<Archetype>) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, known)| *known == handle)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Which archetype an entity was built from, so it can be rebuilt when the
+/// file changes.
+#[derive(Component)]
+pub struct FromArchetype(pub String);
+
+fn load_archetypes(asset_server: Res<AssetServer>, mods: Res<Mods>, mut archetypes: ResMut<Archetypes>) {
+    let dir = FileAssetIo::get_base_path().join("assets").join(ARCHETYPE_DIR);
+    let mut file_names: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    // Mods can add archetypes as well as replace the game's own.
+    file_names.extend(
+        mods.files_in(ARCHETYPE_DIR)
+            .filter_map(|file| Some(file.file_name()?.to_string_lossy().into_owned())),
+    );
+    let suffix = format!(".{ARCHETYPE_EXTENSION}");
+    for file_name in file_names {
+        if let Some(name) = file_name.strip_suffix(&suffix) {
+            let handle = asset_server.load(format!("{ARCHETYPE_DIR}/{file_name}"));
+            archetypes.0.insert(name.to_string(), handle);
+        }
+    }
+}
+
+/// Gives an entity everything its archetype describes, replacing whatever an
+/// earlier version of it gave.
+fn apply_archetype(
+    entity: &mut EntityCommands,
+    archetype: &Archetype,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    entity.remove::<(BlueBall, Enemy, Renderable, ActiveEvents)>();
+    match archetype.mesh {
+        Some(shape) => {
+            entity.insert((meshes.add(shape.mesh()), materials.add(archetype.material())));
+        }
+        None => {
+            entity.remove::<(Handle<Mesh>, Handle<StandardMaterial>)>();
+        }
+    }
+    match archetype.collider {
+        ColliderShape::None => {
+            entity.remove::<Collider>();
+        }
+        ColliderShape::Ball { radius } => {
+            entity.insert(Collider::ball(radius));
+        }
+        ColliderShape::Cuboid {
+            half_extents: (x, y, z),
+        } => {
+            entity.insert(Collider::cuboid(x, y, z));
+        }
+    }
+    match archetype.restitution {
+        Some(restitution) => {
+            entity.insert((Restitution::coefficient(restitution), Bounciness(restitution)));
+        }
+        None => {
+            entity.remove::<(Restitution, Bounciness)>();
+        }
+    }
+    match archetype.gravity_scale {
+        Some(scale) => {
+            entity.insert(GravityScale(scale));
+        }
+        None => {
+            entity.remove::<GravityScale>();
+        }
+    }
+    match archetype.light {
+        Some(light) => {
+            let (r, g, b) = light.color;
+            match light.kind {
+                LightKind::Point => {
+                    entity.remove::<DirectionalLight>().insert((
+                        PointLight {
+                            color: Color::rgb(r, g, b),
+                            intensity: light.intensity,
+                            range: light.range,
+                            shadows_enabled: light.shadows,
+                            ..default()
+                        },
+                        CubemapVisibleEntities::default(),
+                        CubemapFrusta::default(),
+                    ));
+                }
+                LightKind::Directional => {
+                    entity.remove::<PointLight>().insert((
+                        DirectionalLight {
+                            color: Color::rgb(r, g, b),
+                            illuminance: light.intensity,
+                            shadows_enabled: light.shadows,
+                            ..default()
+                        },
+                        CascadesFrusta::default(),
+                        Cascades::default(),
+                        CascadeShadowConfig::default(),
+                        CascadesVisibleEntities::default(),
+                    ));
+                }
+            }
+        }
+        None => {
+            entity.remove::<(PointLight, DirectionalLight)>();
+        }
+    }
+    for tag in &archetype.tags {
+        match tag {
+            Tag::BlueBall => entity.insert(BlueBall),
+            Tag::Enemy => entity.insert((Enemy, ActiveEvents::COLLISION_EVENTS)),
+            Tag::Renderable => entity.insert(Renderable),
+        };
+    }
+}
+
+/// Spawns an entity built from the named archetype.
+pub fn spawn_archetype<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    name: &str,
+    archetype: &Archetype,
+    transform: Transform,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut entity = commands.spawn((
+        SpatialBundle::from_transform(transform),
+        FromArchetype(name.to_string()),
+    ));
+    if let Some(body) = archetype.body {
+        entity.insert(match body {
+            Body::Fixed => RigidBody::Fixed,
+            Body::Dynamic => RigidBody::Dynamic,
+        });
+        if body == Body::Dynamic {
+            entity.insert((Velocity::default(), InLiquid::default()));
+        }
+    }
+    apply_archetype(&mut entity, archetype, meshes, materials);
+    entity
+}
+
+/// Rebuilds everything made from an archetype once its file has loaded or
+/// been edited. Bodies keep their type, since mirrored balls move kinematically.
+fn reload_archetypes(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Archetype>>,
+    archetypes: Res<Archetypes>,
+    assets: Res<Assets<Archetype>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    entities: Query<(Entity, &FromArchetype)>,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        let (Some(name), Some(archetype)) = (archetypes.name_of(handle), assets.get(handle)) else {
+            continue;
+        };
+        if matches!(event, AssetEvent::Modified { .. }) {
+            info!("Reloaded archetype {name}");
+        }
+        for (entity, from) in &entities {
+            if from.0 == name {
+                apply_archetype(&mut commands.entity(entity), archetype, &mut meshes, &mut materials);
+            }
+        }
+    }
+}
+
+pub struct ArchetypePlugin;
+impl Plugin for ArchetypePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Archetype>()
+            .init_asset_loader::<ArchetypeLoader>()
+            .init_resource::<Archetypes>()
+            .add_startup_system(load_archetypes)
+            .add_system(reload_archetypes);
+    }
+}
@@ -0,0 +1,690 @@
+use crate::game::load::voxel_position;
+use crate::game::physics::charge_hit;
+use crate::player::player::{apply_movement, Mobility, MoveInput, MovementSettings};
+use crate::prelude::*;
+use crate::game::features::{JumpPad, WindZone};
+use bevy::math::Vec3Swizzles;
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// The highest step, in voxels, a bot plans to jump up.
+const MAX_CLIMB: usize = 1;
+/// Cells a route search looks at before giving up.
+const MAX_SEARCH: usize = 20_000;
+/// A bot moves on to its next waypoint once it is this close.
+const WAYPOINT_REACH: f32 = 0.8;
+/// Players closer than this can be picked on.
+const CHASE_RADIUS: f32 = 12.0;
+/// How close a bot gets before it swings at a player or a red ball.
+const SWING_RANGE: f32 = 6.0;
+/// Updrafts this close to a ball on a sky island are used to reach it.
+const UPDRAFT_REACH: f32 = 12.0;
+/// A waypoint not reached after this many seconds is given up on.
+const GIVE_UP_SECONDS: f32 = 4.0;
+/// Below this speed a bot with somewhere to go is taken to be stuck and jumps.
+const STUCK_SPEED: f32 = 0.5;
+const BOT_COLORS: [Color; 3] = [Color::ORANGE, Color::PURPLE, Color::YELLOW_GREEN];
+
+/// How well a bot plays.
+struct SkillProfile {
+    /// Fraction of the player's walking speed.
+    speed: f32,
+    /// Seconds between picking a target and planning a route to it.
+    think_seconds: f32,
+    /// Radians a swing can miss its target by.
+    aim_error: f32,
+    /// Chance per second of going after a nearby player.
+    aggression: f32,
+    /// Red balls closer than this are steered around.
+    avoid_radius: f32,
+}
+
+fn profile(skill: BotSkill) -> SkillProfile {
+    match skill {
+        BotSkill::Easy => SkillProfile {
+            speed: 0.6,
+            think_seconds: 1.5,
+            aim_error: 0.4,
+            aggression: 0.03,
+            avoid_radius: 3.0,
+        },
+        BotSkill::Normal => SkillProfile {
+            speed: 0.8,
+            think_seconds: 0.8,
+            aim_error: 0.2,
+            aggression: 0.08,
+            avoid_radius: 5.0,
+        },
+        BotSkill::Hard => SkillProfile {
+            speed: 0.95,
+            think_seconds: 0.3,
+            aim_error: 0.05,
+            aggression: 0.15,
+            avoid_radius: 7.0,
+        },
+    }
+}
+
+#[derive(Component, Default)]
+struct BotBrain {
+    /// Points on the ground to walk through, nearest first.
+    route: Vec<Vec3>,
+    /// Where the route ends: a blue ball or a player being chased.
+    target: Option<Entity>,
+    /// Seconds until the bot picks its target again.
+    think: f32,
+    /// How far the hit being held should charge.
+    swing: Option<f32>,
+    /// An updraft to ride up to a target out of walking reach.
+    lift: Option<Vec3>,
+    /// Fewest waypoints the bot has had left on its way to the target.
+    closest: Option<usize>,
+    /// Seconds since the bot last got closer to its target.
+    stalled: f32,
+    /// Cells the bot gave up on reaching, which later routes go around.
+    blocked: HashSet<Cell>,
+}
+
+/// Where a bot's chances come from, seeded from the map and the bot so a seed
+/// plays out alike from run to run.
+#[derive(Component)]
+struct BotRng(StdRng);
+
+impl BotRng {
+    fn new(seed: u32, index: usize) -> Self {
+        BotRng(StdRng::seed_from_u64(((seed as u64) << 32) | index as u64))
+    }
+}
+
+fn bot_bundle(
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    index: usize,
+    position: Vec3,
+    seed: u32,
+) -> impl Bundle {
+    (
+        (
+            PbrBundle {
+                mesh: meshes.add(shape::UVSphere::default().into()),
+                material: materials.add(BOT_COLORS[index % BOT_COLORS.len()].into()),
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            RigidBody::Dynamic,
+            LockedAxes::ROTATION_LOCKED,
+            GravityScale(3.0),
+            Velocity::default(),
+            Ccd::enabled(),
+            Collider::ball(1.0),
+        ),
+        (
+            Bot { index },
+            BotBrain::default(),
+            BotRng::new(seed, index),
+            Health::default(),
+            InLiquid::default(),
+            Jump::default(),
+            Hit::default(),
+            HitTimer::default(),
+            Score::default(),
+        ),
+    )
+}
+
+/// Bots join games on this machine, dropping in beside the players.
+#[allow(clippy::too_many_arguments)]
+fn spawn_bots(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    bot_count: Res<BotCount>,
+    local_players: Res<LocalPlayers>,
+    role: Res<NetRole>,
+    spawn: Res<SpawnPoint>,
+    world_seed: Res<WorldSeed>,
+) {
+    if *role != NetRole::Offline {
+        return;
+    }
+    for index in 0..bot_count.0 {
+        let position = spawn.local(local_players.0 + index);
+        commands.spawn(bot_bundle(
+            &mut meshes,
+            &mut materials,
+            index,
+            position,
+            world_seed.current,
+        ));
+    }
+}
+
+/// Sends the bots back to the drop point when a new map is generated.
+fn reset_bots(
+    voxel_map: Res<VoxelMap>,
+    local_players: Res<LocalPlayers>,
+    spawn: Res<SpawnPoint>,
+    world_seed: Res<WorldSeed>,
+    mut bots: Query<(
+        &Bot,
+        &mut BotBrain,
+        &mut BotRng,
+        &mut Transform,
+        &mut Velocity,
+        &mut Health,
+        &mut Score,
+    )>,
+) {
+    if !voxel_map.is_changed() {
+        return;
+    }
+    for (bot, mut brain, mut rng, mut transform, mut velocity, mut health, mut score) in &mut bots {
+        *brain = BotBrain::default();
+        *rng = BotRng::new(world_seed.current, bot.index);
+        transform.translation = spawn.local(local_players.0 + bot.index);
+        *velocity = Velocity::zero();
+        *health = Health::default();
+        score.0 = 0;
+    }
+}
+
+/// A voxel a bot can stand in, as (x, y, z) indices into the voxel map.
+type Cell = (usize, usize, usize);
+
+fn is_solid(voxel_map: &VoxelMap, (x, y, z): Cell) -> bool {
+    voxel_map.0[x].get(y).is_some_and(|layer| layer[z].is_solid())
+}
+
+/// Whether a bot, two voxels across, has room in a cell: it and the one above
+/// are clear along with those of a neighbouring column on either axis.
+fn fits(voxel_map: &VoxelMap, (x, y, z): Cell) -> bool {
+    let width = voxel_map.0.len();
+    let depth = voxel_map.0[0][0].len();
+    let clear = |x: Option<usize>, z: Option<usize>| match (x, z) {
+        (Some(x), Some(z)) if x < width && z < depth => {
+            !is_solid(voxel_map, (x, y, z)) && !is_solid(voxel_map, (x, y + 1, z))
+        }
+        _ => false,
+    };
+    let xs = [x.checked_sub(1), Some(x + 1)];
+    let zs = [z.checked_sub(1), Some(z + 1)];
+    clear(Some(x), Some(z))
+        && xs.into_iter().any(|nx| {
+            clear(nx, Some(z))
+                && zs
+                    .into_iter()
+                    .any(|nz| clear(Some(x), nz) && clear(nx, nz))
+        })
+}
+
+/// Drops a cell onto the ground below it, or lifts it out of the ground.
+fn settle(voxel_map: &VoxelMap, (x, mut y, z): Cell) -> Cell {
+    while is_solid(voxel_map, (x, y, z)) {
+        y += 1;
+    }
+    while y > 0 && !is_solid(voxel_map, (x, y - 1, z)) {
+        y -= 1;
+    }
+    (x, y, z)
+}
+
+/// The cell holding a point, if it is over the map, without settling it.
+fn cell(voxel_map: &VoxelMap, position: Vec3) -> Option<Cell> {
+    let origin = voxel_position(&voxel_map.0, (0, 0, 0));
+    let width = voxel_map.0.len() as i32;
+    let depth = voxel_map.0.first()?.first()?.len() as i32;
+    let offset = (position - origin).round();
+    let (x, z) = (offset.x as i32, offset.z as i32);
+    ((0..width).contains(&x) && (0..depth).contains(&z))
+        .then_some((x as usize, offset.y.max(0.0) as usize, z as usize))
+}
+
+/// The ground a bot stands on in a cell.
+fn floor(voxel_map: &VoxelMap, cell: Cell) -> Vec3 {
+    voxel_position(&voxel_map.0, cell) - Vec3::Y * 0.5
+}
+
+/// Where a bot ends up walking from a cell into the next column over: up a
+/// step low enough to jump, or down to whatever ground is below.
+fn step(voxel_map: &VoxelMap, (from_x, y, from_z): Cell, (x, z): (usize, usize)) -> Option<Cell> {
+    let mut top = y;
+    while is_solid(voxel_map, (x, top, z)) {
+        if top >= y + MAX_CLIMB {
+            return None;
+        }
+        top += 1;
+    }
+    if (y..=top).any(|height| is_solid(voxel_map, (from_x, height, from_z))) {
+        return None;
+    }
+    let next = settle(voxel_map, (x, top, z));
+    if !(next.1..=top).all(|height| fits(voxel_map, (x, height, z))) {
+        return None;
+    }
+    let lava = voxel_map.0[x].get(next.1).map(|layer| layer[z]) == Some(Voxel::Liquid(Liquid::Lava));
+    (!lava).then_some(next)
+}
+
+/// Searches outward from a cell for the nearest goal a bot can walk to,
+/// keeping out of lava and away from steps too high to jump. The route
+/// excludes the starting cell.
+fn plan_route<T: Copy>(
+    voxel_map: &VoxelMap,
+    from: Cell,
+    goals: &HashMap<Cell, T>,
+    blocked: &HashSet<Cell>,
+) -> Option<(T, Vec<Cell>)> {
+    let width = voxel_map.0.len();
+    let depth = voxel_map.0.first()?.first()?.len();
+    let mut came_from: HashMap<Cell, (usize, Option<Cell>)> = HashMap::default();
+    let mut open = BinaryHeap::new();
+    came_from.insert(from, (0, None));
+    open.push(Reverse((0, from)));
+
+    while let Some(Reverse((cost, current))) = open.pop() {
+        if came_from[&current].0 < cost {
+            continue;
+        }
+        if let Some(goal) = goals.get(&current) {
+            let mut route = vec![current];
+            while let Some((_, Some(previous))) = came_from.get(route.last()?) {
+                if *previous == from {
+                    break;
+                }
+                route.push(*previous);
+            }
+            route.reverse();
+            return Some((*goal, route));
+        }
+        if came_from.len() > MAX_SEARCH {
+            break;
+        }
+        for (dx, dz) in [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (-1, 1), (1, -1), (1, 1)] {
+            let (Some(x), Some(z)) = (
+                current.0.checked_add_signed(dx).filter(|x| *x < width),
+                current.2.checked_add_signed(dz).filter(|z| *z < depth),
+            ) else {
+                continue;
+            };
+            // Diagonal steps need both sides clear, as the bot can't cut corners.
+            let corner_clear = dx == 0
+                || dz == 0
+                || (step(voxel_map, current, (x, current.2)).is_some()
+                    && step(voxel_map, current, (current.0, z)).is_some());
+            let Some(next) = step(voxel_map, current, (x, z))
+                .filter(|next| corner_clear && !blocked.contains(next))
+            else {
+                continue;
+            };
+            let next_cost = cost + if dx != 0 && dz != 0 { 14 } else { 10 };
+            if came_from.get(&next).is_none_or(|(known, _)| next_cost < *known) {
+                came_from.insert(next, (next_cost, Some(current)));
+                open.push(Reverse((next_cost, next)));
+            }
+        }
+    }
+    None
+}
+
+/// Picks the nearest blue ball a bot can walk to, or now and then a player to
+/// knock about, and plans the way there.
+#[allow(clippy::too_many_arguments)]
+fn bot_think(
+    time: Res<Time>,
+    skill: Res<BotSkill>,
+    voxel_map: Res<VoxelMap>,
+    mut bots: Query<(&Transform, &mut BotBrain, &mut BotRng), With<Bot>>,
+    balls: Query<(Entity, &Transform), With<BlueBall>>,
+    players: Query<(Entity, &Transform), With<Player>>,
+    updrafts: Query<&Transform, With<WindZone>>,
+    pads: Query<&Transform, With<JumpPad>>,
+) {
+    let profile = profile(*skill);
+    // Jump pads would fling a bot off its route, so it walks around them.
+    let pad_cells: HashSet<Cell> = pads
+        .iter()
+        .filter_map(|pad| cell(&voxel_map, pad.translation + Vec3::Y * 0.5))
+        .flat_map(|(x, y, z)| {
+            (x.saturating_sub(1)..=x + 1)
+                .flat_map(move |x| (z.saturating_sub(1)..=z + 1).map(move |z| (x, y, z)))
+        })
+        .collect();
+    for (transform, mut brain, mut rng) in &mut bots {
+        brain.think -= time.delta_seconds();
+        let target_gone = brain.target.is_some_and(|target| {
+            !balls.contains(target) && !players.contains(target)
+        });
+        if brain.think > 0.0 && !target_gone {
+            continue;
+        }
+        brain.think = profile.think_seconds;
+        let position = transform.translation;
+
+        let victim = players
+            .iter()
+            .filter(|(_, player)| player.translation.distance(position) <= CHASE_RADIUS)
+            .min_by(|(_, a), (_, b)| {
+                a.translation
+                    .distance_squared(position)
+                    .total_cmp(&b.translation.distance_squared(position))
+            });
+        if let Some((victim, _)) = victim {
+            let chasing = brain.target == Some(victim);
+            if chasing || rng.0.gen_bool((profile.aggression * profile.think_seconds).min(1.0) as f64) {
+                brain.target = Some(victim);
+                brain.route.clear();
+                brain.lift = None;
+                continue;
+            }
+        }
+
+        let Some(start) = cell(&voxel_map, position).map(|start| settle(&voxel_map, start)) else {
+            brain.target = None;
+            brain.route.clear();
+            continue;
+        };
+        // Balls up on the sky islands can't be walked to, so they are only
+        // gone after, by way of an updraft, once nothing on the ground is left.
+        let goals: HashMap<Cell, Entity> = balls
+            .iter()
+            .filter_map(|(ball, ball_transform)| {
+                let raw = cell(&voxel_map, ball_transform.translation)?;
+                let goal = settle(&voxel_map, raw);
+                (raw.1.abs_diff(goal.1) <= 2).then_some((goal, ball))
+            })
+            .collect();
+        let avoid: HashSet<Cell> = brain.blocked.union(&pad_cells).copied().collect();
+        let best = plan_route(&voxel_map, start, &goals, &avoid);
+        brain.lift = None;
+        if let Some((ball, route)) = best {
+            if brain.target != Some(ball) {
+                brain.closest = None;
+                brain.stalled = 0.0;
+            }
+            brain.target = Some(ball);
+            brain.route = route.into_iter().map(|cell| floor(&voxel_map, cell)).collect();
+            continue;
+        }
+        let nearest_ball = balls.iter().min_by(|(_, a), (_, b)| {
+            a.translation
+                .distance_squared(position)
+                .total_cmp(&b.translation.distance_squared(position))
+        });
+        brain.target = nearest_ball.map(|(ball, _)| ball);
+        brain.route.clear();
+        let Some((_, ball)) = nearest_ball else {
+            continue;
+        };
+        brain.lift = updrafts
+            .iter()
+            .map(|updraft| updraft.translation)
+            .filter(|updraft| updraft.xz().distance(ball.translation.xz()) < UPDRAFT_REACH)
+            .min_by(|a, b| {
+                a.xz()
+                    .distance_squared(ball.translation.xz())
+                    .total_cmp(&b.xz().distance_squared(ball.translation.xz()))
+            });
+        let foot = brain
+            .lift
+            .and_then(|updraft| cell(&voxel_map, updraft))
+            .map(|foot| settle(&voxel_map, foot));
+        if let Some((_, route)) = foot.and_then(|foot| {
+            plan_route(&voxel_map, start, &HashMap::from_iter([(foot, ())]), &avoid)
+        }) {
+            brain.route = route.into_iter().map(|cell| floor(&voxel_map, cell)).collect();
+        }
+    }
+}
+
+/// Walks each bot along its route with the player's movement, steering
+/// around red balls and swinging at anything that gets close.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn bot_move(
+    time: Res<Time>,
+    skill: Res<BotSkill>,
+    settings: Res<MovementSettings>,
+    voxel_map: Res<VoxelMap>,
+    mut bots: Query<
+        (
+            Entity,
+            &mut Transform,
+            &Velocity,
+            &InLiquid,
+            &mut Jump,
+            &mut Hit,
+            &mut HitTimer,
+            &mut BotBrain,
+            &mut BotRng,
+        ),
+        With<Bot>,
+    >,
+    targets: Query<&Transform, (Or<(With<BlueBall>, With<Player>)>, Without<Bot>)>,
+    players: Query<(), With<Player>>,
+    enemies: Query<&Transform, (With<Enemy>, Without<Bot>)>,
+    mut fired: EventWriter<HitFired>,
+) {
+    let profile = profile(*skill);
+    let mobility = Mobility {
+        speed: settings.speed * profile.speed,
+        noclip: false,
+        double_jump: false,
+    };
+    for (
+        entity,
+        mut transform,
+        velocity,
+        in_liquid,
+        mut jump,
+        mut hit,
+        mut timer,
+        mut brain,
+        mut rng,
+    ) in &mut bots
+    {
+        let position = transform.translation;
+        let target = brain.target.and_then(|target| targets.get(target).ok());
+        while brain
+            .route
+            .first()
+            .is_some_and(|waypoint| waypoint.xz().distance(position.xz()) < WAYPOINT_REACH)
+        {
+            brain.route.remove(0);
+        }
+        if let Some(&waypoint) = brain.route.first() {
+            let left = brain.route.len();
+            if brain.closest.is_none_or(|closest| left < closest) {
+                brain.closest = Some(left);
+                brain.stalled = 0.0;
+            }
+            brain.stalled += time.delta_seconds();
+            if brain.stalled > GIVE_UP_SECONDS {
+                // The step holding the bot up is most likely the first steep one ahead.
+                let mut height = position.y - 1.0;
+                let steep = brain.route.iter().find(|waypoint| {
+                    let climb = (waypoint.y - height).abs();
+                    height = waypoint.y;
+                    climb > 1.0
+                });
+                let stuck_at = steep.copied().unwrap_or(waypoint);
+                brain.blocked.extend(cell(&voxel_map, stuck_at + Vec3::Y * 0.5));
+                brain.route.clear();
+                brain.closest = None;
+                brain.stalled = 0.0;
+                brain.think = 0.0;
+            }
+        }
+        let Some(goal) = brain
+            .route
+            .first()
+            .copied()
+            .or(target.map(|target| match brain.lift {
+                // Hold still in the updraft until it has lifted the bot level with the target.
+                Some(updraft) if target.translation.y > position.y => {
+                    Vec3::new(updraft.x, target.translation.y, updraft.z)
+                }
+                _ => target.translation,
+            }))
+        else {
+            continue;
+        };
+
+        let offset = (goal - position).xz();
+        let mut heading = if offset.length() < WAYPOINT_REACH / 2.0 {
+            Vec2::ZERO
+        } else {
+            offset.normalize()
+        };
+        let nearest_enemy = enemies
+            .iter()
+            .map(|enemy| enemy.translation)
+            .filter(|enemy| enemy.distance(position) < profile.avoid_radius)
+            .min_by(|a, b| a.distance_squared(position).total_cmp(&b.distance_squared(position)));
+        if let Some(enemy) = nearest_enemy {
+            let away = (position - enemy).xz();
+            let closeness = 1.0 - away.length() / profile.avoid_radius;
+            heading = (heading + away.normalize_or_zero() * closeness * 2.0).normalize_or_zero();
+        }
+        if heading != Vec2::ZERO {
+            transform.rotation = Quat::from_rotation_y((-heading.x).atan2(-heading.y));
+        }
+
+        let stuck = velocity.linvel.xz().length() < STUCK_SPEED && heading != Vec2::ZERO;
+        let climb = goal.y > position.y - 0.5;
+        let input = MoveInput {
+            direction: Vec2::new(0.0, heading.length()),
+            ascend: climb || stuck || in_liquid.0.is_some(),
+            ..default()
+        };
+        apply_movement(
+            &mut transform,
+            in_liquid,
+            &mut jump,
+            input,
+            &mobility,
+            time.delta_seconds(),
+        );
+
+        // Swing at a chased player or a red ball about to hit.
+        let victim = target.filter(|_| brain.target.is_some_and(|target| players.contains(target)));
+        let swing_at = nearest_enemy
+            .or(victim.map(|victim| victim.translation))
+            .filter(|aim| aim.distance(position) < SWING_RANGE);
+        if swing_at.is_some() && brain.swing.is_none() && hit.hit && hit.charge == 0.0 {
+            brain.swing = Some(rng.0.gen_range(0.3..=1.0));
+        }
+        let held = brain.swing.filter(|goal| hit.charge < *goal).map(|_| false);
+        if let Some(charge) = charge_hit(&mut hit, &mut timer, held, time.delta_seconds()) {
+            brain.swing = None;
+            let aim = swing_at.map_or(transform.forward(), |aim| {
+                (aim - transform.translation).normalize_or_zero()
+            });
+            let miss = Quat::from_euler(
+                EulerRot::YXZ,
+                rng.0.gen_range(-profile.aim_error..=profile.aim_error),
+                rng.0.gen_range(-profile.aim_error..=profile.aim_error),
+                0.0,
+            );
+            fired.send(HitFired {
+                shooter: entity,
+                origin: transform.translation,
+                direction: miss * aim,
+                charge,
+                shockwave: false,
+            });
+        }
+    }
+}
+
+/// Lands bots on the ground, collects the blue balls they reach and knocks
+/// them out when a red ball gets them.
+#[allow(clippy::too_many_arguments)]
+fn bot_collisions(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut bots: Query<(Entity, &Bot, &mut Jump, &mut Health, &mut Score)>,
+    cubes: Query<(), With<Cube>>,
+    enemies: Query<(), With<Enemy>>,
+    balls: Query<&Transform, With<BlueBall>>,
+    mut game_info: ResMut<GameInfo>,
+    mut notifications: EventWriter<Notification>,
+    mut sounds: EventWriter<SpatialSound>,
+    mut taken: ResMut<TakenBalls>,
+) {
+    for (entity, bot, mut jump, mut health, mut score) in &mut bots {
+        jump.avalible = false;
+        for contact_pair in rapier_context.contacts_with(entity) {
+            let other = if contact_pair.collider1() == entity {
+                contact_pair.collider2()
+            } else {
+                contact_pair.collider1()
+            };
+            if cubes.contains(other) {
+                jump.avalible = true;
+            }
+            if enemies.contains(other) {
+                health.current = 0.0;
+            } else if let Ok(ball) = balls.get(other) {
+                if !taken.0.insert(other) {
+                    continue;
+                }
+                commands.entity(other).despawn();
+                game_info.collected += 1;
+                score.0 += 1;
+                sounds.send(SpatialSound {
+                    effect: SoundEffect::Score,
+                    position: ball.translation,
+                    volume: 0.6,
+                });
+                notifications.send(Notification(format!(
+                    "Bot {} took a blue ball {}/{}",
+                    bot.index + 1,
+                    game_info.collected,
+                    game_info.total
+                )));
+            }
+        }
+    }
+}
+
+/// Knocked out bots start over from the drop point.
+fn respawn_bots(
+    local_players: Res<LocalPlayers>,
+    spawn: Res<SpawnPoint>,
+    mut bots: Query<(&Bot, &mut Health, &mut Transform, &mut Velocity, &mut BotBrain)>,
+) {
+    for (bot, mut health, mut transform, mut velocity, mut brain) in &mut bots {
+        if health.current > 0.0 {
+            continue;
+        }
+        *health = Health::default();
+        transform.translation = spawn.local(local_players.0 + bot.index);
+        *velocity = Velocity::zero();
+        *brain = BotBrain {
+            blocked: std::mem::take(&mut brain.blocked),
+            ..default()
+        };
+    }
+}
+
+pub struct BotPlugin;
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BotCount>()
+            .init_resource::<BotSkill>()
+            .add_system(spawn_bots.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(cleanup::<Bot>.in_schedule(OnExit(GameState::InGame)))
+            .add_systems(
+                (
+                    reset_bots,
+                    bot_think.after(reset_bots),
+                    bot_move.after(bot_think),
+                    bot_collisions.after(bot_move),
+                    respawn_bots.after(bot_collisions),
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
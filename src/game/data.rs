@@ -0,0 +1,17 @@
+use crate::game::mods::Mods;
+use bevy::log::warn;
+use serde::de::DeserializeOwned;
+use std::fs;
+
+/// Reads a RON data file from the assets directory, or the mod replacing it,
+/// falling back to the built-in defaults when it is missing or invalid.
+pub fn load_data_file<T: DeserializeOwned + Default>(mods: &Mods, path: &str) -> T {
+    let path = mods.resolve(path);
+    match fs::read_to_string(&path) {
+        Ok(contents) => ron::from_str(&contents).unwrap_or_else(|err| {
+            warn!("Ignoring invalid {}: {}", path.display(), err);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
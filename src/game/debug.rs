@@ -0,0 +1,106 @@
+use crate::prelude::*;
+use bevy::diagnostic::{
+    Diagnostics, FrameTimeDiagnosticsPlugin, SystemInformationDiagnosticsPlugin,
+};
+
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+}
+
+#[derive(Component)]
+struct DebugText;
+
+fn debug_overlay_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    overlay: Res<DebugOverlay>,
+) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font_size: 20.0,
+                color: Color::BLACK,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(5.0),
+                left: Val::Px(15.0),
+                ..default()
+            },
+            display: if overlay.visible {
+                Display::Flex
+            } else {
+                Display::None
+            },
+            ..default()
+        }),
+        DebugText,
+        Hud,
+    ));
+}
+
+fn toggle_debug_overlay(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut query: Query<&mut Style, With<DebugText>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        overlay.visible = !overlay.visible;
+        for mut style in &mut query {
+            style.display = if overlay.visible {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+    }
+}
+
+fn smoothed(diagnostics: &Diagnostics, id: bevy::diagnostic::DiagnosticId) -> Option<f64> {
+    diagnostics
+        .get(id)
+        .and_then(|diagnostic| diagnostic.smoothed())
+}
+
+fn update_debug_overlay(
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+    mut query: Query<&mut Text, With<DebugText>>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    let fps = smoothed(&diagnostics, FrameTimeDiagnosticsPlugin::FPS).unwrap_or_default();
+    let frame_time =
+        smoothed(&diagnostics, FrameTimeDiagnosticsPlugin::FRAME_TIME).unwrap_or_default();
+    let cpu_usage =
+        smoothed(&diagnostics, SystemInformationDiagnosticsPlugin::CPU_USAGE).unwrap_or_default();
+    let mem_usage =
+        smoothed(&diagnostics, SystemInformationDiagnosticsPlugin::MEM_USAGE).unwrap_or_default();
+    for mut text in &mut query {
+        text.sections[0].value = format!(
+            "{:.1} fps, {:.3} ms/frame\ncpu_usage {}%\nmem_usage {}%",
+            fps,
+            frame_time,
+            cpu_usage.round(),
+            mem_usage.round()
+        );
+    }
+}
+
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugOverlay>()
+            .add_system(debug_overlay_setup.in_schedule(OnEnter(GameState::InGame)))
+            .add_systems(
+                (toggle_debug_overlay, update_debug_overlay).in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
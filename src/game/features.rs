@@ -0,0 +1,420 @@
+use crate::console::console::ConsoleAppExt;
+use crate::game::islands::{island_center, SkyIsland};
+use crate::game::load::{column_height, ground_height, voxel_position};
+use crate::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+const FEATURE_SEED: u64 = 0x0fea_7e25;
+/// Matches the player's `GravityScale` of 3.
+const GRAVITY: f32 = 9.81 * 3.0;
+const JUMP_PAD_COUNT: usize = 5;
+/// How much higher a cliff has to be than the ground in front of it to get a
+/// jump pad.
+const MIN_CLIFF_HEIGHT: usize = 5;
+const SPEED_RING_COUNT: usize = 5;
+const SPEED_RING_PUSH: f32 = 12.0;
+const WIND_ACCELERATION: f32 = 38.0;
+/// Wind lifts the player no faster than this, so they glide up steadily.
+const MAX_WIND_RISE: f32 = 12.0;
+
+/// Launches the player along `launch` when they step on it.
+#[derive(Component)]
+pub struct JumpPad {
+    pub launch: Vec3,
+}
+
+/// A volume that keeps accelerating the player while they are inside it.
+#[derive(Component)]
+pub struct WindZone {
+    pub force: Vec3,
+}
+
+/// Speeds the player up when they pass through it.
+#[derive(Component)]
+pub struct SpeedRing;
+
+/// Marks the points drawn by the trajectory debug view.
+#[derive(Component)]
+struct TrajectoryMarker;
+
+#[derive(Resource, Default)]
+struct ShowTrajectories(bool);
+
+/// Velocity that takes a body from `from` to land on `to`, peaking a little
+/// above the higher of the two.
+fn launch_velocity(from: Vec3, to: Vec3) -> Vec3 {
+    let rise = to.y - from.y;
+    let vertical = (2.0 * GRAVITY * (rise.max(0.0) + 3.0)).sqrt();
+    let flight_time = (vertical + (vertical * vertical - 2.0 * GRAVITY * rise).sqrt()) / GRAVITY;
+    let horizontal = Vec3::new(to.x - from.x, 0.0, to.z - from.z) / flight_time;
+    horizontal + Vec3::Y * vertical
+}
+
+/// Points along the arc of a body launched from `from`, until it has fallen
+/// back below where it started.
+fn trajectory(from: Vec3, launch: Vec3) -> Vec<Vec3> {
+    (1..40)
+        .map(|step| {
+            let t = step as f32 * 0.1;
+            from + launch * t - Vec3::Y * GRAVITY * t * t / 2.0
+        })
+        .take_while(|point| point.y > from.y - 1.0)
+        .collect()
+}
+
+/// Ground spots at the foot of a cliff, each paired with the top of the cliff.
+#[allow(clippy::type_complexity)]
+fn cliff_spots(
+    voxel_data: &[Vec<Vec<Voxel>>],
+) -> Vec<((usize, usize, usize), (usize, usize, usize))> {
+    let width = voxel_data.len() as i32;
+    let depth = voxel_data[0][0].len() as i32;
+    let mut spots = Vec::new();
+    for x in (2..width - 2).step_by(2) {
+        for z in (2..depth - 2).step_by(2) {
+            let ground = column_height(voxel_data, x as usize, z as usize);
+            if voxel_data[x as usize]
+                .get(ground)
+                .is_some_and(|layer| layer[z as usize] != Voxel::Empty)
+            {
+                continue;
+            }
+            let mut best = None;
+            for (dx, dz) in [
+                (1, 0),
+                (-1, 0),
+                (0, 1),
+                (0, -1),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ] {
+                for distance in 3..=6 {
+                    let (tx, tz) = (x + dx * distance, z + dz * distance);
+                    if tx < 0 || tz < 0 || tx >= width || tz >= depth {
+                        break;
+                    }
+                    let top = column_height(voxel_data, tx as usize, tz as usize);
+                    if top >= ground + MIN_CLIFF_HEIGHT
+                        && best.is_none_or(|(_, height)| top > height)
+                    {
+                        best = Some(((tx as usize, tz as usize), top));
+                    }
+                }
+            }
+            if let Some(((tx, tz), top)) = best {
+                spots.push(((x as usize, ground, z as usize), (tx, top, tz)));
+            }
+        }
+    }
+    spots
+}
+
+/// Takes up to `count` items that are at least `spacing` apart.
+fn spread_out<T>(
+    items: impl IntoIterator<Item = T>,
+    position: impl Fn(&T) -> Vec3,
+    spacing: f32,
+    count: usize,
+) -> Vec<T> {
+    let mut chosen: Vec<T> = Vec::new();
+    for item in items {
+        if chosen.len() == count {
+            break;
+        }
+        if chosen
+            .iter()
+            .all(|other| position(other).distance(position(&item)) >= spacing)
+        {
+            chosen.push(item);
+        }
+    }
+    chosen
+}
+
+fn spawn_markers(
+    commands: &mut Commands,
+    mesh: &Handle<Mesh>,
+    material: &Handle<StandardMaterial>,
+    points: &[Vec3],
+) {
+    for point in points {
+        commands.spawn((
+            PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(*point),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            TrajectoryMarker,
+            Reboot,
+        ));
+    }
+}
+
+/// Places jump pads at the foot of cliffs, updrafts beside the sky islands
+/// and speed rings above open ground.
+pub fn spawn_features(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    voxel_data: &[Vec<Vec<Voxel>>],
+    islands: &[SkyIsland],
+    seed: u32,
+) {
+    let mut rng = StdRng::seed_from_u64(seed as u64 ^ FEATURE_SEED);
+    let marker_mesh = meshes.add(
+        shape::UVSphere {
+            radius: 0.15,
+            ..default()
+        }
+        .into(),
+    );
+    let marker_material = materials.add(StandardMaterial {
+        base_color: Color::YELLOW,
+        unlit: true,
+        ..default()
+    });
+
+    let mut cliffs = cliff_spots(voxel_data);
+    cliffs.shuffle(&mut rng);
+    let pads = spread_out(
+        cliffs,
+        |(ground, _)| voxel_position(voxel_data, *ground),
+        8.0,
+        JUMP_PAD_COUNT,
+    );
+    let pad_mesh = meshes.add(
+        shape::Cylinder {
+            radius: 1.0,
+            height: 0.3,
+            ..default()
+        }
+        .into(),
+    );
+    let pad_material = materials.add(StandardMaterial {
+        base_color: Color::ORANGE,
+        emissive: Color::ORANGE * 0.4,
+        ..default()
+    });
+    for (ground, cliff) in pads {
+        let pad = voxel_position(voxel_data, ground) - Vec3::Y * 0.35;
+        let start = pad + Vec3::Y;
+        let launch = launch_velocity(start, voxel_position(voxel_data, cliff) + Vec3::Y * 0.5);
+        commands.spawn((
+            PbrBundle {
+                mesh: pad_mesh.clone(),
+                material: pad_material.clone(),
+                transform: Transform::from_translation(pad),
+                ..default()
+            },
+            RigidBody::Fixed,
+            Collider::cylinder(0.6, 1.0),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            JumpPad { launch },
+            Reboot,
+        ));
+        spawn_markers(
+            commands,
+            &marker_mesh,
+            &marker_material,
+            &trajectory(start, launch),
+        );
+    }
+
+    let wind_material = materials.add(StandardMaterial {
+        base_color: Color::rgba(0.8, 0.9, 1.0, 0.15),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    // The first island already has a lift, so updrafts serve the others.
+    for island in islands.iter().skip(1) {
+        if !rng.gen_bool(0.6) {
+            continue;
+        }
+        let center = island_center(voxel_data, island);
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let outward = Vec3::new(angle.cos(), 0.0, angle.sin());
+        let column = center + outward * (island.radius as f32 + 2.5);
+        let bottom = ground_height(voxel_data, column, 1);
+        let top = center.y + 4.0;
+        let size = Vec3::new(3.0, top - bottom, 3.0);
+        let position = Vec3::new(column.x, (top + bottom) / 2.0, column.z);
+        let force = Vec3::Y * WIND_ACCELERATION - outward * 4.0;
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(shape::Box::new(size.x, size.y, size.z).into()),
+                material: wind_material.clone(),
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            RigidBody::Fixed,
+            Collider::cuboid(size.x / 2.0, size.y / 2.0, size.z / 2.0),
+            Sensor,
+            WindZone { force },
+            Reboot,
+        ));
+        let path: Vec<Vec3> = (0..(size.y as usize))
+            .map(|step| Vec3::new(column.x, bottom + 1.0 + step as f32, column.z))
+            .collect();
+        spawn_markers(commands, &marker_mesh, &marker_material, &path);
+    }
+
+    let mut open_ground = crate::game::load::surface_spots(voxel_data);
+    open_ground.shuffle(&mut rng);
+    let rings = spread_out(
+        open_ground
+            .into_iter()
+            .map(|spot| voxel_position(voxel_data, spot) + Vec3::Y * 2.5),
+        |position| *position,
+        10.0,
+        SPEED_RING_COUNT,
+    );
+    let ring_mesh = meshes.add(
+        shape::Torus {
+            radius: 1.8,
+            ring_radius: 0.2,
+            ..default()
+        }
+        .into(),
+    );
+    let ring_material = materials.add(StandardMaterial {
+        base_color: Color::CYAN,
+        emissive: Color::CYAN * 0.5,
+        ..default()
+    });
+    for position in rings {
+        // The torus and cylinder stand on end, facing along their local Y.
+        let rotation = Quat::from_rotation_y(rng.gen_range(0.0..std::f32::consts::PI))
+            * Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+        let transform = Transform::from_translation(position).with_rotation(rotation);
+        commands.spawn((
+            PbrBundle {
+                mesh: ring_mesh.clone(),
+                material: ring_material.clone(),
+                transform,
+                ..default()
+            },
+            RigidBody::Fixed,
+            Collider::cylinder(0.3, 1.6),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            SpeedRing,
+            Reboot,
+        ));
+        let axis = transform.up();
+        let path: Vec<Vec3> = (-8..=8)
+            .map(|step| position + axis * step as f32 * 0.5)
+            .collect();
+        spawn_markers(commands, &marker_mesh, &marker_material, &path);
+    }
+}
+
+/// Handles a player touching a jump pad or flying through a speed ring.
+#[allow(clippy::type_complexity)]
+fn trigger_features(
+    mut collisions: EventReader<CollisionEvent>,
+    mut players: Query<
+        (&Transform, &mut Velocity, Option<&Player>),
+        Or<(With<Player>, With<RemotePlayer>, With<Bot>)>,
+    >,
+    pads: Query<&JumpPad>,
+    rings: Query<&Transform, With<SpeedRing>>,
+    mut grants: EventWriter<GrantPowerUp>,
+    mut sounds: EventWriter<SpatialSound>,
+) {
+    for collision in collisions.iter() {
+        let CollisionEvent::Started(a, b, _) = collision else {
+            continue;
+        };
+        let (player, other) = if players.contains(*a) { (*a, *b) } else { (*b, *a) };
+        let Ok((player_transform, mut velocity, local)) = players.get_mut(player) else {
+            continue;
+        };
+        if let Ok(pad) = pads.get(other) {
+            velocity.linvel = pad.launch;
+            sounds.send(SpatialSound {
+                effect: SoundEffect::Bounce,
+                position: player_transform.translation,
+                volume: 1.0,
+            });
+        } else if let Ok(ring) = rings.get(other) {
+            let axis = ring.up();
+            let direction = if player_transform.forward().dot(axis) < 0.0 {
+                -axis
+            } else {
+                axis
+            };
+            velocity.linvel += direction * SPEED_RING_PUSH;
+            if local.is_some() {
+                grants.send(GrantPowerUp(PowerUpKind::SpeedBoost));
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_wind(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut players: Query<(Entity, &mut Velocity), Or<(With<Player>, With<RemotePlayer>, With<Bot>)>>,
+    zones: Query<(Entity, &WindZone)>,
+) {
+    for (player, mut velocity) in &mut players {
+        for (zone, wind) in &zones {
+            if rapier_context.intersection_pair(player, zone) != Some(true) {
+                continue;
+            }
+            velocity.linvel += wind.force * time.delta_seconds();
+            velocity.linvel.y = velocity.linvel.y.min(MAX_WIND_RISE);
+        }
+    }
+}
+
+fn show_trajectories(
+    show: Res<ShowTrajectories>,
+    mut markers: Query<(&mut Visibility, Ref<TrajectoryMarker>)>,
+) {
+    let visibility = if show.0 {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    for (mut marker, added) in &mut markers {
+        if (show.is_changed() || added.is_added()) && *marker != visibility {
+            *marker = visibility;
+        }
+    }
+}
+
+fn trajectories_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let show = match args {
+        ["on"] => true,
+        ["off"] => false,
+        _ => return Err(String::new()),
+    };
+    world.resource_mut::<ShowTrajectories>().0 = show;
+    Ok(format!("trajectories {}", args[0]))
+}
+
+pub struct FeaturePlugin;
+impl Plugin for FeaturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShowTrajectories>()
+            .add_systems(
+                (trigger_features, apply_wind, show_trajectories)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_console_command(
+                "trajectories",
+                "trajectories <on|off>",
+                &["on", "off"],
+                trajectories_command,
+            );
+    }
+}
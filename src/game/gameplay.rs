@@ -1,24 +1,73 @@
+use crate::console::console::{console_closed, parse_arg, ConsoleAppExt};
+use crate::game::archetypes::{spawn_archetype, Archetype, ArchetypePlugin, Archetypes, RED_BALL};
+use crate::game::bots::BotPlugin;
+use crate::game::debug::DebugOverlayPlugin;
+use crate::game::features::FeaturePlugin;
+use crate::game::ghost::GhostPlugin;
+use crate::game::hud::HudPlugin;
+use crate::game::islands::IslandPlugin;
+use crate::game::levels::{LevelPlugin, LevelSelect};
+use crate::game::liquid::LiquidPlugin;
 use crate::game::load::ScenePlugin;
+use crate::game::minimap::MinimapPlugin;
+use crate::game::mods::ModPlugin;
+use crate::game::music::MusicPlugin;
 use crate::game::physics::PhysicsPlugin;
-use crate::game::text::TextPlugin;
+use crate::game::powerup::PowerUpPlugin;
+use crate::game::save::SavePlugin;
+use crate::game::sky::SkyPlugin;
 use crate::game::sound::SoundPlugin;
+use crate::game::spectator::SpectatorPlugin;
 use crate::prelude::*;
+use bevy::audio::AudioPlugin;
+use bevy::ecs::system::CommandQueue;
 
 pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(TextPlugin)
+        app.add_plugin(ModPlugin)
+            .add_plugin(ArchetypePlugin)
+            .add_plugin(HudPlugin)
+            .add_plugin(DebugOverlayPlugin)
             .add_plugin(PhysicsPlugin)
-            .add_plugin(SoundPlugin)
+            .add_plugin(GhostPlugin)
+            .add_plugin(MinimapPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(PowerUpPlugin)
+            .add_plugin(IslandPlugin)
+            .add_plugin(LevelPlugin)
+            .add_plugin(FeaturePlugin)
+            .add_plugin(LiquidPlugin)
+            .add_plugin(SkyPlugin)
+            .add_plugin(BotPlugin)
+            .add_plugin(SpectatorPlugin)
+            .add_event::<BallCollected>()
+            .add_event::<RoundOver>()
+            .add_event::<SoundEffect>()
+            .add_event::<SpatialSound>()
             .add_system(check_win_condition.in_set(OnUpdate(GameState::InGame)))
-            .add_system(menu_input_system.in_set(OnUpdate(GameState::InGame)))
+            .add_system(round_timer_system.in_set(OnUpdate(GameState::InGame)))
+            .add_system(
+                menu_input_system
+                    .run_if(console_closed)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
             .add_system(new_game.in_schedule(OnEnter(GameState::NewGame)))
             .insert_resource(GameInfo {
                 is_won: false,
                 wins: 0,
                 collected: 0,
+                total: 5,
             })
-            .add_plugin(ScenePlugin);
+            .add_plugin(ScenePlugin)
+            .add_console_command("seed", "seed <n>", &[], seed_command)
+            .add_console_command("regen", "regen", &[], regen_command)
+            .add_console_command("give", "give balls <n>", &["balls"], give_command)
+            .add_console_command("spawn", "spawn <enemy|archetype>", &["enemy"], spawn_command);
+        // Headless runs have nothing to play sounds through.
+        if app.is_plugin_added::<AudioPlugin>() {
+            app.add_plugin(SoundPlugin).add_plugin(MusicPlugin);
+        }
     }
 }
 
@@ -33,30 +82,63 @@ fn menu_input_system(
     }
 }
 
+fn round_timer_system(time: Res<Time>, mut timer_query: Query<&mut FPSTimer>) {
+    for mut clock in &mut timer_query {
+        clock.elapsed += time.delta_seconds();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn check_win_condition(
     mut commands: Commands,
     mut game_state: ResMut<GameInfo>,
     query: Query<Entity, With<Reboot>>,
-    player: Query<Entity, With<Player>>,
+    mut player: Query<(Entity, &mut Health, &mut Score, &LocalPlayer), With<Player>>,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<StandardMaterial>>,
     displayquality: ResMut<DisplayQuality>,
+    world_seed: ResMut<WorldSeed>,
+    mode: Res<GameMode>,
+    role: Res<NetRole>,
+    archetypes: Res<Archetypes>,
+    archetype_assets: Res<Assets<Archetype>>,
+    modes: Res<GameModes>,
+    choice: Res<ModeChoice>,
+    mut levels: LevelSelect,
 ) {
     if game_state.is_won {
         game_state.is_won = false;
+        // Settle the next level first, since it decides where players drop in.
+        levels.next_map(game_state.wins, *role);
+        let spawn = levels.spawn_point();
 
-        for player in player.iter() {
+        for (player, mut health, mut score, local) in player.iter_mut() {
+            *health = Health::default();
+            score.0 = 0;
             commands.entity(player).remove::<TransformBundle>();
             commands.entity(player).insert(TransformBundle::from(
-                Transform::from_xyz(0.0, 80.0, 0.0).looking_at(Vec3::new(-1.0, -1.0, 0.0), Vec3::Y),
+                Transform::from_translation(spawn.local(local.index))
+                    .looking_at(Vec3::new(-1.0, -1.0, 0.0), Vec3::Y),
             ));
-            for entity in query.iter() {
-                if entity != player {
-                    commands.entity(entity).despawn();
-                }
-            }
         }
-        crate::game::load::setup(commands, meshes, materials, displayquality.into());
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
+        }
+        crate::game::load::setup(
+            commands,
+            meshes,
+            materials,
+            displayquality.into(),
+            world_seed,
+            mode,
+            role,
+            archetypes,
+            archetype_assets,
+            modes,
+            choice,
+            game_state,
+            levels,
+        );
     }
 }
 
@@ -64,3 +146,89 @@ fn new_game(mut game_state: ResMut<NextState<GameState>>,) {
     game_state.set(GameState::InGame)
 }
 
+
+fn seed_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let seed: u32 = parse_arg(args, 0)?;
+    world.resource_mut::<WorldSeed>().next = Some(seed);
+    world.resource_mut::<GameInfo>().is_won = true;
+    Ok(format!("regenerating with seed {seed}"))
+}
+
+fn regen_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    world.resource_mut::<GameInfo>().is_won = true;
+    Ok("regenerating".into())
+}
+
+fn give_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    if args.first() != Some(&"balls") {
+        return Err(String::new());
+    }
+    let count: usize = parse_arg(args, 1)?;
+    let balls: Vec<Entity> = world
+        .query_filtered::<Entity, With<BlueBall>>()
+        .iter(world)
+        .take(count)
+        .collect();
+    for ball in &balls {
+        world.despawn(*ball);
+        world.send_event(BallCollected);
+        let mut game_state = world.resource_mut::<GameInfo>();
+        game_state.collected += 1;
+        if game_state.collected == game_state.total {
+            game_state.is_won = true;
+            game_state.collected = 0;
+            game_state.wins += 1;
+            world.send_event(RoundOver { won: true });
+        }
+    }
+    Ok(format!("collected {} blue balls", balls.len()))
+}
+
+/// Spawns the named archetype at `position`, or in front of the player if
+/// there is none.
+pub fn spawn_in_world(world: &mut World, name: &str, position: Option<Vec3>) -> Result<(), String> {
+    let archetype = world
+        .resource::<Archetypes>()
+        .get(name, world.resource::<Assets<Archetype>>())
+        .ok_or_else(|| format!("unknown archetype `{name}`"))?;
+    let position = match position {
+        Some(position) => position,
+        None => {
+            let Some(player) = world
+                .query_filtered::<&Transform, With<Player>>()
+                .iter(world)
+                .next()
+                .copied()
+            else {
+                return Err("no player to spawn in front of".into());
+            };
+            player.translation + player.forward() * 5.0
+        }
+    };
+    let mut queue = CommandQueue::default();
+    world.resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+        world.resource_scope(|world, mut materials: Mut<Assets<StandardMaterial>>| {
+            let mut commands = Commands::new(&mut queue, world);
+            spawn_archetype(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                name,
+                &archetype,
+                Transform::from_translation(position),
+            )
+            .insert(Reboot);
+        });
+    });
+    queue.apply(world);
+    Ok(())
+}
+
+fn spawn_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = match *args.first().ok_or_else(String::new)? {
+        "enemy" => RED_BALL,
+        name => name,
+    };
+    spawn_in_world(world, name, None)?;
+    Ok(format!("spawned {name}"))
+}
@@ -0,0 +1,387 @@
+use crate::cli::LaunchOptions;
+use crate::game::levels::CurrentLevel;
+use crate::game::load::MAP_VERSION;
+use crate::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+const GHOST_DIR: &str = "ghosts";
+const GHOST_MAGIC: &[u8; 4] = b"SQGH";
+const GHOST_VERSION: u16 = 1;
+const SAMPLE_INTERVAL: f32 = 0.1;
+
+/// A recorded run: the player position sampled every `interval` seconds
+/// plus the time each blue ball was collected.
+#[derive(Clone)]
+pub struct GhostRun {
+    pub seed: u32,
+    pub interval: f32,
+    pub finish: f32,
+    pub splits: Vec<f32>,
+    pub samples: Vec<Vec3>,
+}
+
+impl GhostRun {
+    fn new(seed: u32) -> Self {
+        GhostRun {
+            seed,
+            interval: SAMPLE_INTERVAL,
+            finish: 0.0,
+            splits: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn position_at(&self, time: f32) -> Option<Vec3> {
+        let last = self.samples.len().checked_sub(1)?;
+        let index = (time / self.interval).max(0.0);
+        let i = (index as usize).min(last);
+        let next = (i + 1).min(last);
+        Some(self.samples[i].lerp(self.samples[next], index.fract()))
+    }
+
+    /// Time at which the ghost passed closest to `position`.
+    pub fn time_near(&self, position: Vec3) -> Option<f32> {
+        self.samples
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                a.distance_squared(position)
+                    .total_cmp(&b.distance_squared(position))
+            })
+            .map(|(i, _)| i as f32 * self.interval)
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(GHOST_MAGIC)?;
+        writer.write_all(&GHOST_VERSION.to_le_bytes())?;
+        writer.write_all(&MAP_VERSION.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&self.interval.to_le_bytes())?;
+        writer.write_all(&self.finish.to_le_bytes())?;
+        writer.write_all(&(self.splits.len() as u32).to_le_bytes())?;
+        for split in &self.splits {
+            writer.write_all(&split.to_le_bytes())?;
+        }
+        writer.write_all(&(self.samples.len() as u32).to_le_bytes())?;
+        for sample in &self.samples {
+            for value in sample.to_array() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != GHOST_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a ghost file",
+            ));
+        }
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        if u16::from_le_bytes(version) != GHOST_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported ghost version",
+            ));
+        }
+        // Runs recorded on maps the generator no longer makes would replay
+        // through the new terrain.
+        reader.read_exact(&mut version)?;
+        if u16::from_le_bytes(version) != MAP_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "recorded on an older version of the map",
+            ));
+        }
+        let seed = read_u32(reader)?;
+        let interval = read_f32(reader)?;
+        let finish = read_f32(reader)?;
+        let splits = (0..read_u32(reader)?)
+            .map(|_| read_f32(reader))
+            .collect::<io::Result<Vec<_>>>()?;
+        let samples = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(Vec3::new(
+                    read_f32(reader)?,
+                    read_f32(reader)?,
+                    read_f32(reader)?,
+                ))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(GhostRun {
+            seed,
+            interval,
+            finish,
+            splits,
+            samples,
+        })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn ghost_path(seed: u32, quality: DisplayQuality) -> PathBuf {
+    PathBuf::from(GHOST_DIR).join(format!("{seed}-{quality:?}.ghost").to_lowercase())
+}
+
+pub fn load_ghost_file(path: &Path) -> io::Result<GhostRun> {
+    GhostRun::read(&mut BufReader::new(File::open(path)?))
+}
+
+pub fn load_ghost(seed: u32, quality: DisplayQuality) -> Option<GhostRun> {
+    let path = ghost_path(seed, quality);
+    if !path.exists() {
+        return None;
+    }
+    match load_ghost_file(&path) {
+        Ok(run) => Some(run),
+        Err(err) => {
+            warn!("Ignoring ghost for seed {}: {}", seed, err);
+            None
+        }
+    }
+}
+
+fn save_ghost(run: &GhostRun, quality: DisplayQuality) -> io::Result<()> {
+    fs::create_dir_all(GHOST_DIR)?;
+    let mut writer = BufWriter::new(File::create(ghost_path(run.seed, quality))?);
+    run.write(&mut writer)?;
+    writer.flush()
+}
+
+/// The best run for the current seed, if one has been recorded.
+#[derive(Resource, Default)]
+pub struct Ghost(pub Option<GhostRun>);
+
+#[derive(Resource)]
+struct GhostRecorder(GhostRun);
+
+/// The ghost given with `--replay`, raced instead of the saved best run.
+#[derive(Resource, Default)]
+struct ReplayGhost(Option<GhostRun>);
+
+fn load_replay_ghost(
+    options: Res<LaunchOptions>,
+    mut world_seed: ResMut<WorldSeed>,
+    mut replay: ResMut<ReplayGhost>,
+) {
+    let Some(path) = &options.replay else {
+        return;
+    };
+    match load_ghost_file(path) {
+        Ok(run) => {
+            world_seed.next = Some(run.seed);
+            replay.0 = Some(run);
+        }
+        Err(err) => error!("Failed to load {}: {}", path.display(), err),
+    }
+}
+
+#[derive(Component)]
+pub struct GhostBall;
+
+#[derive(Component)]
+struct GhostText;
+
+#[allow(clippy::too_many_arguments)]
+fn reload_ghost(
+    mut commands: Commands,
+    world_seed: Res<WorldSeed>,
+    displayquality: Res<DisplayQuality>,
+    replay: Res<ReplayGhost>,
+    current_level: Res<CurrentLevel>,
+    mut ghost: ResMut<Ghost>,
+    ghost_balls: Query<Entity, With<GhostBall>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !world_seed.is_changed() && !current_level.is_changed() {
+        return;
+    }
+    for entity in &ghost_balls {
+        commands.entity(entity).despawn_recursive();
+    }
+    // A level's map isn't built from the seed, so runs on it can't be
+    // compared with the seed's ghost.
+    if current_level.level.is_some() {
+        commands.remove_resource::<GhostRecorder>();
+        ghost.0 = None;
+        return;
+    }
+    commands.insert_resource(GhostRecorder(GhostRun::new(world_seed.current)));
+
+    ghost.0 = match &replay.0 {
+        Some(run) if run.seed == world_seed.current => Some(run.clone()),
+        _ => load_ghost(world_seed.current, *displayquality),
+    };
+    if let Some(start) = ghost.0.as_ref().and_then(|run| run.position_at(0.0)) {
+        commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(shape::UVSphere::default().into()),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::rgba(0.8, 0.9, 1.0, 0.35),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                }),
+                transform: Transform::from_translation(start),
+                ..default()
+            })
+            .insert(GhostBall);
+    }
+}
+
+fn record_ghost(
+    recorder: Option<ResMut<GhostRecorder>>,
+    player: Query<&Transform, With<PrimaryPlayer>>,
+    timer: Query<&FPSTimer>,
+    mut collected: EventReader<BallCollected>,
+) {
+    let Some(mut recorder) = recorder else {
+        return;
+    };
+    let Ok(elapsed) = timer.get_single().map(|timer| timer.elapsed) else {
+        return;
+    };
+    for _ in collected.iter() {
+        recorder.0.splits.push(elapsed);
+    }
+    if let Ok(transform) = player.get_single() {
+        while recorder.0.samples.len() as f32 * recorder.0.interval <= elapsed {
+            recorder.0.samples.push(transform.translation);
+        }
+    }
+}
+
+fn finish_round(
+    mut round_over: EventReader<RoundOver>,
+    recorder: Option<ResMut<GhostRecorder>>,
+    timer: Query<&FPSTimer>,
+    mut ghost: ResMut<Ghost>,
+    world_seed: Res<WorldSeed>,
+    displayquality: Res<DisplayQuality>,
+) {
+    let Some(mut recorder) = recorder else {
+        return;
+    };
+    for event in round_over.iter() {
+        let seed = recorder.0.seed;
+        let run = std::mem::replace(&mut recorder.0, GhostRun::new(seed));
+        if !event.won {
+            continue;
+        }
+        let finish = timer
+            .get_single()
+            .map(|timer| timer.elapsed)
+            .unwrap_or_default();
+        // The next map may already be loaded, so compare against the file on disk.
+        let best = load_ghost(run.seed, *displayquality);
+        if best.is_none_or(|best| finish < best.finish) {
+            let run = GhostRun { finish, ..run };
+            if let Err(err) = save_ghost(&run, *displayquality) {
+                warn!("Failed to save ghost for seed {}: {}", run.seed, err);
+            }
+            if world_seed.current == run.seed {
+                ghost.0 = Some(run);
+            }
+        }
+    }
+}
+
+fn move_ghost(
+    ghost: Res<Ghost>,
+    timer: Query<&FPSTimer>,
+    mut ghost_balls: Query<&mut Transform, With<GhostBall>>,
+) {
+    let (Some(run), Ok(timer)) = (&ghost.0, timer.get_single()) else {
+        return;
+    };
+    for mut transform in &mut ghost_balls {
+        if let Some(position) = run.position_at(timer.elapsed) {
+            transform.translation = position;
+        }
+    }
+}
+
+fn ghost_text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 30.0,
+                color: Color::BLACK,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(5.0),
+                right: Val::Px(15.0),
+                ..default()
+            },
+            ..default()
+        }),
+        GhostText,
+        Hud,
+        HudWidget::Ghost,
+    ));
+}
+
+fn update_ghost_text(
+    ghost: Res<Ghost>,
+    timer: Query<&FPSTimer>,
+    player: Query<&Transform, With<PrimaryPlayer>>,
+    mut text: Query<&mut Text, With<GhostText>>,
+) {
+    for mut text in &mut text {
+        let section = &mut text.sections[0];
+        let (Some(run), Ok(timer), Ok(player)) =
+            (&ghost.0, timer.get_single(), player.get_single())
+        else {
+            section.value.clear();
+            continue;
+        };
+        let Some(ghost_time) = run.time_near(player.translation) else {
+            continue;
+        };
+        let delta = timer.elapsed - ghost_time;
+        section.value = format!("Best {:.2}\nGhost {:+.2}", run.finish, delta);
+        section.style.color = if delta > 0.0 {
+            Color::rgb(0.8, 0.1, 0.1)
+        } else {
+            Color::rgb(0.1, 0.6, 0.1)
+        };
+    }
+}
+
+pub struct GhostPlugin;
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Ghost>()
+            .init_resource::<ReplayGhost>()
+            .add_startup_system(load_replay_ghost)
+            .add_system(ghost_text_setup.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(reload_ghost)
+            .add_systems(
+                (record_ghost, finish_round, move_ghost, update_ghost_text)
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
@@ -0,0 +1,415 @@
+use crate::player::player::player_screen;
+use crate::prelude::*;
+
+const HUD_TEXT_COLOR: Color = Color::BLACK;
+const NOTIFICATION_SECONDS: f32 = 3.0;
+const MAX_NOTIFICATIONS: usize = 4;
+
+#[derive(Component)]
+struct ObjectiveText;
+
+#[derive(Component)]
+struct TimerText;
+
+/// Widgets showing one local player's state, by player index.
+#[derive(Component)]
+struct HealthText(usize);
+
+#[derive(Component)]
+struct HealthBar(usize);
+
+#[derive(Component)]
+struct HitBar(usize);
+
+#[derive(Component)]
+struct ScoreText(usize);
+
+#[derive(Component)]
+struct NotificationList;
+
+#[derive(Component)]
+struct NotificationEntry(Timer);
+
+fn hud_text(text: &str, font: &Handle<Font>) -> TextBundle {
+    TextBundle::from_section(
+        text,
+        TextStyle {
+            font: font.clone(),
+            font_size: 30.0,
+            color: HUD_TEXT_COLOR,
+        },
+    )
+}
+
+fn health_widget(parent: &mut ChildBuilder, font: &Handle<Font>, index: usize) {
+    parent.spawn((hud_text("", font), HealthText(index)));
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(150.0), Val::Px(10.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.4).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.8, 0.1, 0.1).into(),
+                    ..default()
+                },
+                HealthBar(index),
+            ));
+        });
+}
+
+/// A node covering a local player's part of the window.
+fn player_screen_style(index: usize, count: usize) -> Style {
+    let screen = player_screen(index, count);
+    Style {
+        size: Size::new(
+            Val::Percent(100.0 * screen.width()),
+            Val::Percent(100.0 * screen.height()),
+        ),
+        position_type: PositionType::Absolute,
+        position: UiRect {
+            left: Val::Percent(100.0 * screen.min.x),
+            top: Val::Percent(100.0 * screen.min.y),
+            ..default()
+        },
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    }
+}
+
+fn hud_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    local_players: Res<LocalPlayers>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let count = local_players.0;
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(5.0),
+                        left: Val::Px(15.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            Hud,
+        ))
+        .with_children(|parent| {
+            parent.spawn((hud_text("", &font), ObjectiveText, HudWidget::Objective));
+            parent.spawn((hud_text("", &font), TimerText, HudWidget::Timer));
+            if count == 1 {
+                parent
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        HudWidget::Health,
+                    ))
+                    .with_children(|parent| health_widget(parent, &font, 0));
+            }
+        });
+
+    // In split-screen each player's score and health sit at the bottom of their view.
+    if count > 1 {
+        for index in 0..count {
+            commands
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::FlexEnd,
+                            ..player_screen_style(index, count)
+                        },
+                        ..default()
+                    },
+                    Hud,
+                    HudWidget::Health,
+                ))
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                margin: UiRect::bottom(Val::Px(10.0)),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn((hud_text("", &font), ScoreText(index)));
+                            health_widget(parent, &font, index);
+                        });
+                });
+        }
+    }
+
+    for index in 0..count {
+        commands
+            .spawn((
+                NodeBundle {
+                    style: player_screen_style(index, count),
+                    ..default()
+                },
+                Hud,
+                HudWidget::Crosshair,
+            ))
+            .with_children(|parent| {
+                for size in [
+                    Size::new(Val::Px(24.0), Val::Px(3.0)),
+                    Size::new(Val::Px(3.0), Val::Px(24.0)),
+                ] {
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            size,
+                            position_type: PositionType::Absolute,
+                            ..default()
+                        },
+                        background_color: HUD_TEXT_COLOR.into(),
+                        ..default()
+                    });
+                }
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(40.0), Val::Px(4.0)),
+                            position_type: PositionType::Absolute,
+                            margin: UiRect {
+                                top: Val::Px(50.0),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        background_color: Color::rgba(0.0, 0.0, 0.0, 0.4).into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            HitBar(index),
+                        ));
+                    });
+            });
+    }
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(80.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        },
+        Hud,
+        HudWidget::Notifications,
+        NotificationList,
+    ));
+}
+
+fn update_objective(game_info: Res<GameInfo>, mut query: Query<(&mut Text, Ref<ObjectiveText>)>) {
+    for (mut text, widget) in &mut query {
+        if game_info.is_changed() || widget.is_added() {
+            text.sections[0].value = format!(
+                "Collected {}/{}\n{} Wins",
+                game_info.collected, game_info.total, game_info.wins
+            );
+        }
+    }
+}
+
+fn update_timer(timer_query: Query<&FPSTimer>, mut query: Query<&mut Text, With<TimerText>>) {
+    let elapsed = timer_query
+        .iter()
+        .map(|timer| timer.elapsed)
+        .last()
+        .unwrap_or_default();
+    let value = format!("Elapsed Time: {:.1}", elapsed);
+    for mut text in &mut query {
+        // Only touch the text when the displayed tenth changes, to avoid relayout.
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+fn update_health(
+    players: Query<(Ref<Health>, &LocalPlayer)>,
+    mut text: Query<(&mut Text, Ref<HealthText>)>,
+    mut bar: Query<(&mut Style, Ref<HealthBar>)>,
+) {
+    for (health, local) in &players {
+        for (mut text, widget) in &mut text {
+            if widget.0 == local.index && (health.is_changed() || widget.is_added()) {
+                text.sections[0].value = format!("Health {}", health.current.ceil() as i32);
+            }
+        }
+        for (mut style, widget) in &mut bar {
+            if widget.0 == local.index && (health.is_changed() || widget.is_added()) {
+                style.size.width = Val::Percent(100.0 * health.current / health.max);
+            }
+        }
+    }
+}
+
+fn update_scores(
+    players: Query<(Ref<Score>, &LocalPlayer)>,
+    mut text: Query<(&mut Text, Ref<ScoreText>)>,
+) {
+    for (score, local) in &players {
+        for (mut text, widget) in &mut text {
+            if widget.0 == local.index && (score.is_changed() || widget.is_added()) {
+                text.sections[0].value = format!("Player {}: {}", local.index + 1, score.0);
+            }
+        }
+    }
+}
+
+/// Fills while a hit charges and shows the cooldown while it recharges.
+fn update_hit_bar(
+    players: Query<(&Hit, &HitTimer, &LocalPlayer)>,
+    mut bar: Query<(&mut Style, &mut BackgroundColor, &HitBar)>,
+) {
+    for (hit, hit_timer, local) in &players {
+        let (fill, color) = if !hit.hit {
+            (hit_timer.percent(), Color::GRAY)
+        } else if hit.charge > 0.0 {
+            (hit.charge, Color::ORANGE)
+        } else {
+            (1.0, Color::WHITE)
+        };
+        let width = Val::Percent(100.0 * fill);
+        for (mut style, mut background, widget) in &mut bar {
+            if widget.0 != local.index {
+                continue;
+            }
+            if style.size.width != width {
+                style.size.width = width;
+            }
+            if background.0 != color {
+                background.0 = color;
+            }
+        }
+    }
+}
+
+fn show_notifications(
+    mut commands: Commands,
+    mut notifications: EventReader<Notification>,
+    asset_server: Res<AssetServer>,
+    list: Query<(Entity, Option<&Children>), With<NotificationList>>,
+) {
+    let Ok((list, children)) = list.get_single() else {
+        notifications.clear();
+        return;
+    };
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let mut count = children.map_or(0, |children| children.len());
+    let mut oldest = children.into_iter().flat_map(|children| children.iter());
+    for notification in notifications.iter() {
+        if count >= MAX_NOTIFICATIONS {
+            if let Some(oldest) = oldest.next() {
+                commands.entity(*oldest).despawn_recursive();
+            }
+        } else {
+            count += 1;
+        }
+        commands.entity(list).with_children(|parent| {
+            parent.spawn((
+                hud_text(&notification.0, &font),
+                NotificationEntry(Timer::from_seconds(NOTIFICATION_SECONDS, TimerMode::Once)),
+            ));
+        });
+    }
+}
+
+fn expire_notifications(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut entries: Query<(Entity, &mut NotificationEntry, &mut Text)>,
+) {
+    for (entity, mut entry, mut text) in &mut entries {
+        if entry.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        } else if entry.0.percent_left() < 0.3 {
+            let alpha = entry.0.percent_left() / 0.3;
+            text.sections[0].style.color = HUD_TEXT_COLOR.with_a(alpha);
+        }
+    }
+}
+
+fn apply_hud_settings(
+    hud_settings: Res<HudSettings>,
+    mut widgets: Query<(&HudWidget, &mut Style)>,
+    added: Query<(), Added<HudWidget>>,
+) {
+    if !hud_settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (widget, mut style) in &mut widgets {
+        let display = if hud_settings.is_visible(*widget) {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if style.display != display {
+            style.display = display;
+        }
+    }
+}
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HudSettings>()
+            .add_event::<Notification>()
+            .add_system(hud_setup.in_schedule(OnEnter(GameState::InGame)))
+            .add_systems(
+                (
+                    update_objective,
+                    update_timer,
+                    update_health,
+                    update_scores,
+                    update_hit_bar,
+                    show_notifications,
+                    expire_notifications,
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(apply_hud_settings);
+    }
+}
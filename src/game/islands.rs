@@ -0,0 +1,296 @@
+use crate::game::load::{ground_height, voxel_position};
+use crate::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::TAU;
+
+/// Mixed into the world seed so islands don't shift the rest of the map's
+/// random placement.
+const ISLAND_SEED: u64 = 0x0151_a4d5;
+const ISLAND_LAYERS: usize = 3;
+/// Highest top layer an island may have, leaving headroom below the ceiling.
+const MAX_ISLAND_TOP: usize = 72;
+const PLATFORM_SIZE: Vec3 = Vec3::new(4.0, 0.5, 4.0);
+
+/// A floating island, in voxel grid coordinates so it lines up with the
+/// terrain.
+pub struct SkyIsland {
+    /// Centre voxel of the top layer.
+    pub top: (usize, usize, usize),
+    pub radius: usize,
+}
+
+impl SkyIsland {
+    /// Voxels of the island, from the top layer down to its tapered base.
+    fn voxels(&self) -> Vec<(usize, usize, usize)> {
+        let (cx, cy, cz) = self.top;
+        let mut voxels = Vec::new();
+        for layer in 0..ISLAND_LAYERS.min(self.radius) {
+            let radius = (self.radius - layer) as i32;
+            for dx in -radius..=radius {
+                for dz in -radius..=radius {
+                    if dx * dx + dz * dz <= radius * radius + radius {
+                        voxels.push((
+                            (cx as i32 + dx) as usize,
+                            cy - layer,
+                            (cz as i32 + dz) as usize,
+                        ));
+                    }
+                }
+            }
+        }
+        voxels
+    }
+
+    /// The empty voxels on top of the island, where items can be placed.
+    pub fn surface_spots(&self) -> Vec<(usize, usize, usize)> {
+        self.voxels()
+            .into_iter()
+            .filter(|&(_, y, _)| y == self.top.1)
+            .map(|(x, y, z)| (x, y + 1, z))
+            .collect()
+    }
+
+    fn distance(&self, x: usize, z: usize) -> f32 {
+        Vec2::new(self.top.0 as f32 - x as f32, self.top.2 as f32 - z as f32).length()
+    }
+}
+
+/// Places a few islands above the terrain, kept apart so platforms have room
+/// to travel between them.
+pub fn generate_islands(voxel_data: &[Vec<Vec<Voxel>>], seed: u32) -> Vec<SkyIsland> {
+    let width = voxel_data.len();
+    let height = voxel_data[0].len();
+    let depth = voxel_data[0][0].len();
+    let mut rng = StdRng::seed_from_u64(seed as u64 ^ ISLAND_SEED);
+
+    let count = rng.gen_range(3..=5);
+    let mut islands: Vec<SkyIsland> = Vec::new();
+    for _ in 0..count * 10 {
+        if islands.len() == count {
+            break;
+        }
+        let radius = rng.gen_range(3..=5);
+        let margin = radius + 2;
+        let x = rng.gen_range(margin..width - margin);
+        let z = rng.gen_range(margin..depth - margin);
+        let y = (height + rng.gen_range(6..=10)).min(MAX_ISLAND_TOP);
+        if islands
+            .iter()
+            .any(|island| island.distance(x, z) < (island.radius + radius + 10) as f32)
+        {
+            continue;
+        }
+        islands.push(SkyIsland {
+            top: (x, y, z),
+            radius,
+        });
+    }
+    islands
+}
+
+enum PlatformPath {
+    /// Eases back and forth between two points.
+    Linear { from: Vec3, to: Vec3 },
+    /// Circles around a point on the horizontal plane.
+    Circular { center: Vec3, radius: f32 },
+}
+
+#[derive(Component)]
+pub struct Platform {
+    path: PlatformPath,
+    period: f32,
+    phase: f32,
+    /// How far the platform moved in the last frame.
+    delta: Vec3,
+}
+
+impl Platform {
+    /// Position along the path after `elapsed` seconds of the round, so every
+    /// platform is in the same place for the same seed and round time.
+    fn position(&self, elapsed: f32) -> Vec3 {
+        let angle = (elapsed / self.period + self.phase) * TAU;
+        match self.path {
+            PlatformPath::Linear { from, to } => from.lerp(to, (1.0 - angle.cos()) / 2.0),
+            PlatformPath::Circular { center, radius } => {
+                center + Vec3::new(angle.cos(), 0.0, angle.sin()) * radius
+            }
+        }
+    }
+}
+
+/// Where a platform sits flush with the top of an island, at its rim facing
+/// `towards`.
+fn island_edge(voxel_data: &[Vec<Vec<Voxel>>], island: &SkyIsland, towards: Vec3) -> Vec3 {
+    let center = island_center(voxel_data, island);
+    let direction = Vec3::new(towards.x - center.x, 0.0, towards.z - center.z)
+        .try_normalize()
+        .unwrap_or(Vec3::X);
+    center + direction * (island.radius as f32 + PLATFORM_SIZE.x / 2.0 + 1.0)
+}
+
+/// Top surface of the island's centre voxel, lowered so a platform's top is
+/// level with it.
+pub fn island_center(voxel_data: &[Vec<Vec<Voxel>>], island: &SkyIsland) -> Vec3 {
+    voxel_position(voxel_data, island.top) + Vec3::Y * (0.5 - PLATFORM_SIZE.y / 2.0)
+}
+
+/// Lays out the platforms: a lift from the ground to the first island, a
+/// shuttle between each pair of neighbouring islands and some that circle an
+/// island's rim.
+fn generate_platforms(
+    voxel_data: &[Vec<Vec<Voxel>>],
+    islands: &[SkyIsland],
+    seed: u32,
+) -> Vec<Platform> {
+    let mut rng = StdRng::seed_from_u64(seed as u64 ^ ISLAND_SEED.rotate_left(32));
+    let mut platforms = Vec::new();
+    let platform = |path, rng: &mut StdRng, period: std::ops::Range<f32>| Platform {
+        path,
+        period: rng.gen_range(period),
+        phase: rng.gen_range(0.0..1.0),
+        delta: Vec3::ZERO,
+    };
+
+    if let Some(first) = islands.first() {
+        let to = island_edge(voxel_data, first, Vec3::ZERO);
+        let reach = (PLATFORM_SIZE.x / 2.0).ceil() as i32;
+        let ground = ground_height(voxel_data, to, reach) + PLATFORM_SIZE.y;
+        let from = Vec3::new(to.x, ground, to.z);
+        platforms.push(platform(
+            PlatformPath::Linear { from, to },
+            &mut rng,
+            8.0..12.0,
+        ));
+    }
+    for pair in islands.windows(2) {
+        let from = island_edge(voxel_data, &pair[0], island_center(voxel_data, &pair[1]));
+        let to = island_edge(voxel_data, &pair[1], island_center(voxel_data, &pair[0]));
+        platforms.push(platform(
+            PlatformPath::Linear { from, to },
+            &mut rng,
+            8.0..12.0,
+        ));
+    }
+    for island in islands {
+        if rng.gen_bool(0.5) {
+            let path = PlatformPath::Circular {
+                center: island_center(voxel_data, island),
+                radius: island.radius as f32 + PLATFORM_SIZE.x / 2.0 + 1.0,
+            };
+            platforms.push(platform(path, &mut rng, 10.0..16.0));
+        }
+    }
+    platforms
+}
+
+pub fn spawn_islands(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    voxel_data: &[Vec<Vec<Voxel>>],
+    islands: &[SkyIsland],
+    seed: u32,
+) {
+    let cube_mesh = meshes.add(shape::Cube::new(1.0).into());
+    let island_material = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.45, 0.6, 0.35),
+        perceptual_roughness: 0.6,
+        ..Default::default()
+    });
+    for island in islands {
+        for voxel in island.voxels() {
+            commands
+                .spawn(PbrBundle {
+                    mesh: cube_mesh.clone(),
+                    material: island_material.clone(),
+                    transform: Transform::from_translation(voxel_position(voxel_data, voxel)),
+                    ..Default::default()
+                })
+                .insert(Reboot)
+                .insert(Cube)
+                .insert(Renderable)
+                .insert(RigidBody::Fixed)
+                .insert(Collider::cuboid(0.5, 0.5, 0.5));
+        }
+    }
+
+    let platform_mesh =
+        meshes.add(shape::Box::new(PLATFORM_SIZE.x, PLATFORM_SIZE.y, PLATFORM_SIZE.z).into());
+    let platform_material = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.55, 0.45, 0.3),
+        perceptual_roughness: 0.8,
+        ..Default::default()
+    });
+    for platform in generate_platforms(voxel_data, islands, seed) {
+        commands.spawn((
+            PbrBundle {
+                mesh: platform_mesh.clone(),
+                material: platform_material.clone(),
+                transform: Transform::from_translation(platform.position(0.0)),
+                ..Default::default()
+            },
+            RigidBody::KinematicPositionBased,
+            Collider::cuboid(
+                PLATFORM_SIZE.x / 2.0,
+                PLATFORM_SIZE.y / 2.0,
+                PLATFORM_SIZE.z / 2.0,
+            ),
+            Reboot,
+            Cube,
+            platform,
+        ));
+    }
+}
+
+fn move_platforms(timer: Query<&FPSTimer>, mut platforms: Query<(&mut Platform, &mut Transform)>) {
+    let Ok(timer) = timer.get_single() else {
+        return;
+    };
+    for (mut platform, mut transform) in &mut platforms {
+        let position = platform.position(timer.elapsed);
+        platform.delta = position - transform.translation;
+        transform.translation = position;
+    }
+}
+
+/// Moves players along with a platform they are standing on.
+#[allow(clippy::type_complexity)]
+fn carry_player(
+    rapier_context: Res<RapierContext>,
+    platforms: Query<(&Platform, &Transform)>,
+    mut player_query: Query<
+        (Entity, &mut Transform),
+        (Or<(With<Player>, With<RemotePlayer>, With<Bot>)>, Without<Platform>),
+    >,
+) {
+    for (player, mut transform) in &mut player_query {
+        for contact_pair in rapier_context.contacts_with(player) {
+            if !contact_pair.has_any_active_contacts() {
+                continue;
+            }
+            let other = if contact_pair.collider1() == player {
+                contact_pair.collider2()
+            } else {
+                contact_pair.collider1()
+            };
+            if let Ok((platform, platform_transform)) = platforms.get(other) {
+                if transform.translation.y > platform_transform.translation.y {
+                    transform.translation += platform.delta;
+                }
+            }
+        }
+    }
+}
+
+pub struct IslandPlugin;
+impl Plugin for IslandPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(move_platforms.in_set(OnUpdate(GameState::InGame)))
+            .add_system(
+                carry_player
+                    .after(move_platforms)
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
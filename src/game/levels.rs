@@ -0,0 +1,415 @@
+use crate::console::console::{parse_arg, ConsoleAppExt};
+use crate::game::archetypes::{spawn_archetype, Archetype, Archetypes, BLUE_BALL};
+use crate::game::data::load_data_file;
+use crate::game::load::{grid_position, voxel_position};
+use crate::game::mods::Mods;
+use crate::prelude::*;
+use bevy::ecs::system::SystemParam;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+const CAMPAIGN_PATH: &str = "levels/campaign.ron";
+const PROGRESS_DIR: &str = "saves";
+const PROGRESS_FILE: &str = "campaign.ron";
+/// Levels are built in the same arena random maps are generated in.
+pub const LEVEL_SIZE: usize = 64;
+const MAX_HEIGHT: usize = 64;
+
+type Spot = (usize, usize, usize);
+
+/// A box of voxels between two corners, both included.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Region {
+    pub voxel: Voxel,
+    pub from: Spot,
+    pub to: Spot,
+}
+
+/// An archetype placed in a voxel, such as a ball or an enemy.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Placement {
+    pub archetype: String,
+    pub at: Spot,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PowerUpPlacement {
+    pub kind: PowerUpKind,
+    pub at: Spot,
+}
+
+/// How a level is lit, in place of its game mode's day cycle.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct Lighting {
+    /// Hour of the day the level starts at, the mode's when left out.
+    pub hour: Option<f32>,
+    /// Real seconds for a full day, 0 keeping the hour fixed. The mode's when
+    /// left out.
+    pub cycle_seconds: Option<f32>,
+    /// Scales the sun, moon and ambient light.
+    pub brightness: f32,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting {
+            hour: None,
+            cycle_seconds: None,
+            brightness: 1.0,
+        }
+    }
+}
+
+/// A handcrafted map, read from a file under `assets/levels/`. Positions are
+/// voxel coordinates, with `y` going up from the floor.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Level {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_height")]
+    pub height: usize,
+    /// Filled in order, so later regions carve into earlier ones.
+    #[serde(default)]
+    pub regions: Vec<Region>,
+    /// The voxel players drop into.
+    pub spawn: Spot,
+    #[serde(default)]
+    pub placements: Vec<Placement>,
+    #[serde(default)]
+    pub power_ups: Vec<PowerUpPlacement>,
+    #[serde(default = "default_walls")]
+    pub walls: bool,
+    #[serde(default)]
+    pub lighting: Lighting,
+}
+
+fn default_height() -> usize {
+    32
+}
+
+fn default_walls() -> bool {
+    true
+}
+
+impl Level {
+    fn contains(&self, (x, y, z): Spot) -> bool {
+        x < LEVEL_SIZE && y < self.height && z < LEVEL_SIZE
+    }
+
+    /// Why the level can't be played, if it can't.
+    fn check(&self) -> Result<(), String> {
+        if !(1..=MAX_HEIGHT).contains(&self.height) {
+            return Err(format!("height must be between 1 and {MAX_HEIGHT}"));
+        }
+        let outside = |what: String, spot: Spot| {
+            format!("{what} at {spot:?} is outside the {LEVEL_SIZE}x{}x{LEVEL_SIZE} map", self.height)
+        };
+        for (index, region) in self.regions.iter().enumerate() {
+            for corner in [region.from, region.to] {
+                if !self.contains(corner) {
+                    return Err(outside(format!("region {}", index + 1), corner));
+                }
+            }
+        }
+        if !self.contains(self.spawn) {
+            return Err(outside("spawn".into(), self.spawn));
+        }
+        for placement in &self.placements {
+            if !self.contains(placement.at) {
+                return Err(outside(placement.archetype.clone(), placement.at));
+            }
+        }
+        for power_up in &self.power_ups {
+            if !self.contains(power_up.at) {
+                return Err(outside(format!("{:?}", power_up.kind), power_up.at));
+            }
+        }
+        if !self.placements.iter().any(|placement| placement.archetype == BLUE_BALL) {
+            return Err("no blue balls to collect".into());
+        }
+        let (x, y, z) = self.spawn;
+        if self.voxels()[x][y][z].is_solid() {
+            return Err("spawn is inside solid ground".into());
+        }
+        Ok(())
+    }
+
+    pub fn voxels(&self) -> Vec<Vec<Vec<Voxel>>> {
+        let mut voxels = vec![vec![vec![Voxel::Empty; LEVEL_SIZE]; self.height]; LEVEL_SIZE];
+        for region in &self.regions {
+            let ((x0, y0, z0), (x1, y1, z1)) = (region.from, region.to);
+            for column in &mut voxels[x0.min(x1)..=x0.max(x1)] {
+                for layer in &mut column[y0.min(y1)..=y0.max(y1)] {
+                    layer[z0.min(z1)..=z0.max(z1)].fill(region.voxel);
+                }
+            }
+        }
+        voxels
+    }
+
+    fn spawn_position(&self) -> Vec3 {
+        // A little above the voxel, so players never start stuck in the floor.
+        grid_position(LEVEL_SIZE, LEVEL_SIZE, self.spawn) + Vec3::Y
+    }
+}
+
+/// Places the level's balls and enemies, returning how many blue balls there
+/// are to collect.
+pub fn spawn_placements(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    level: &Level,
+    voxel_data: &[Vec<Vec<Voxel>>],
+    archetypes: &Archetypes,
+    archetype_assets: &Assets<Archetype>,
+) -> usize {
+    let mut blue_balls = 0;
+    for placement in &level.placements {
+        let Some(archetype) = archetypes.get(&placement.archetype, archetype_assets) else {
+            warn!("Level {} places unknown archetype {}", level.name, placement.archetype);
+            continue;
+        };
+        let position = voxel_position(voxel_data, placement.at);
+        spawn_archetype(commands, meshes, materials, &placement.archetype, &archetype, Transform::from_translation(position))
+            .insert(Reboot);
+        if placement.archetype == BLUE_BALL {
+            blue_balls += 1;
+        }
+    }
+    blue_balls
+}
+
+/// The order levels are played in, from `assets/levels/campaign.ron`.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CampaignFile {
+    levels: Vec<String>,
+}
+
+pub struct CampaignLevel {
+    /// Where the level was read from, under the assets directory.
+    pub path: String,
+    /// The level, or why it couldn't be loaded.
+    pub level: Result<Level, String>,
+}
+
+impl CampaignLevel {
+    pub fn title(&self) -> &str {
+        match &self.level {
+            Ok(level) if !level.name.is_empty() => &level.name,
+            _ => &self.path,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Campaign {
+    pub levels: Vec<CampaignLevel>,
+}
+
+impl Campaign {
+    /// Whether every level before this one that loaded has been finished.
+    pub fn is_unlocked(&self, index: usize, progress: &CampaignProgress) -> bool {
+        self.levels[..index]
+            .iter()
+            .all(|entry| entry.level.is_err() || progress.is_completed(&entry.path))
+    }
+
+    /// The first level after `index` that loaded, skipping broken ones.
+    fn next_level(&self, index: usize) -> Option<usize> {
+        (index + 1..self.levels.len()).find(|&next| self.levels[next].level.is_ok())
+    }
+}
+
+fn read_level(mods: &Mods, path: &str) -> Result<Level, String> {
+    let file = mods.resolve(path);
+    let contents = fs::read_to_string(&file).map_err(|err| format!("can't read {}: {err}", file.display()))?;
+    let level: Level = ron::from_str(&contents).map_err(|err| err.to_string())?;
+    level.check()?;
+    Ok(level)
+}
+
+fn load_campaign(mut commands: Commands, mods: Res<Mods>) {
+    let file = load_data_file::<CampaignFile>(&mods, CAMPAIGN_PATH);
+    let levels = file
+        .levels
+        .into_iter()
+        .map(|path| {
+            let level = read_level(&mods, &path);
+            if let Err(err) = &level {
+                warn!("Skipping level {}: {}", path, err);
+            }
+            CampaignLevel { path, level }
+        })
+        .collect();
+    commands.insert_resource(Campaign { levels });
+}
+
+/// The levels finished so far, kept in `saves/campaign.ron`.
+#[derive(Resource, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct CampaignProgress {
+    completed: BTreeSet<String>,
+}
+
+impl CampaignProgress {
+    fn load() -> Self {
+        let path = Path::new(PROGRESS_DIR).join(PROGRESS_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring invalid {}: {}", path.display(), err);
+                CampaignProgress::default()
+            }),
+            Err(_) => CampaignProgress::default(),
+        }
+    }
+
+    fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                fs::create_dir_all(PROGRESS_DIR).map_err(|err| err.to_string())?;
+                fs::write(Path::new(PROGRESS_DIR).join(PROGRESS_FILE), contents).map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            warn!("Failed to save campaign progress: {}", err);
+        }
+    }
+
+    pub fn is_completed(&self, path: &str) -> bool {
+        self.completed.contains(path)
+    }
+}
+
+/// The campaign level being played, if any.
+#[derive(Resource, Default)]
+pub struct CurrentLevel {
+    index: Option<usize>,
+    /// Wins when the level was picked, so the map after a win moves on.
+    wins: i32,
+    /// The level the map in play was built from.
+    pub level: Option<Level>,
+}
+
+impl CurrentLevel {
+    pub fn start(index: usize, wins: i32) -> Self {
+        CurrentLevel {
+            index: Some(index),
+            wins,
+            level: None,
+        }
+    }
+
+    pub fn lighting(&self) -> Lighting {
+        self.level.as_ref().map(|level| level.lighting).unwrap_or_default()
+    }
+}
+
+/// Decides which level, if any, the next map is built from.
+#[derive(SystemParam)]
+pub struct LevelSelect<'w> {
+    current: ResMut<'w, CurrentLevel>,
+    campaign: Res<'w, Campaign>,
+    progress: ResMut<'w, CampaignProgress>,
+    spawn: ResMut<'w, SpawnPoint>,
+    notifications: EventWriter<'w, Notification>,
+}
+
+impl LevelSelect<'_> {
+    /// The level for a new map, moving on to the next one once the current
+    /// level has been won. `None` means a random map. Also moves the spawn
+    /// point, so calling it again before the map is built changes nothing.
+    pub fn next_map(&mut self, wins: i32, role: NetRole) -> Option<Level> {
+        if role != NetRole::Offline {
+            self.current.index = None;
+        }
+        if let Some(index) = self.current.index.filter(|_| wins > self.current.wins) {
+            self.finish(index);
+        }
+        self.current.wins = wins;
+        let level = self
+            .current
+            .index
+            .and_then(|index| self.campaign.levels.get(index))
+            .and_then(|entry| entry.level.clone().ok());
+        self.spawn.0 = level.as_ref().map_or(SPAWN_POINT, Level::spawn_position);
+        self.current.level = level.clone();
+        level
+    }
+
+    pub fn spawn_point(&self) -> SpawnPoint {
+        *self.spawn
+    }
+
+    fn finish(&mut self, index: usize) {
+        let Some(entry) = self.campaign.levels.get(index) else {
+            return;
+        };
+        if self.progress.completed.insert(entry.path.clone()) {
+            self.progress.save();
+        }
+        match self.campaign.next_level(index) {
+            Some(next) => {
+                self.current.index = Some(next);
+                self.notifications.send(Notification(format!(
+                    "Level complete! Next up: {}",
+                    self.campaign.levels[next].title()
+                )));
+            }
+            // The last level is played again until another is picked.
+            None => self.notifications.send(Notification("Campaign complete!".into())),
+        }
+    }
+}
+
+fn level_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let campaign = world.resource::<Campaign>();
+    let index = match args.first() {
+        None => {
+            return Ok(match &world.resource::<CurrentLevel>().level {
+                Some(level) => format!("level {}", level.name),
+                None => "random map".into(),
+            });
+        }
+        Some(&"off") => None,
+        Some(_) => {
+            let number: usize = parse_arg(args, 0)?;
+            let entry = number
+                .checked_sub(1)
+                .and_then(|index| campaign.levels.get(index))
+                .ok_or_else(|| format!("the campaign has {} levels", campaign.levels.len()))?;
+            if let Err(err) = &entry.level {
+                return Err(format!("{} can't be played: {err}", entry.path));
+            }
+            Some(number - 1)
+        }
+    };
+    if *world.resource::<NetRole>() != NetRole::Offline {
+        return Err("levels are only played offline".into());
+    }
+    let mut game_info = world.resource_mut::<GameInfo>();
+    game_info.is_won = true;
+    let wins = game_info.wins;
+    *world.resource_mut::<CurrentLevel>() = match index {
+        Some(index) => CurrentLevel::start(index, wins),
+        None => CurrentLevel::default(),
+    };
+    Ok("regenerating".into())
+}
+
+pub struct LevelPlugin;
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Campaign>()
+            .insert_resource(CampaignProgress::load())
+            .init_resource::<CurrentLevel>()
+            .init_resource::<SpawnPoint>()
+            .add_startup_system(load_campaign)
+            .add_console_command("level", "level [<number>|off]", &["off"], level_command);
+    }
+}
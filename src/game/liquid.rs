@@ -0,0 +1,279 @@
+use crate::game::load::{column_height, voxel_position};
+use crate::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const LIQUID_SEED: u64 = 0x11a0_1d5e;
+/// Percentage of columns that are low enough to end up under the waterline.
+const BASIN_PERCENTILE: usize = 20;
+/// Only pools of at most this many columns can turn out to be lava, so the
+/// big lakes stay swimmable.
+const MAX_LAVA_POOL: usize = 40;
+const LAVA_CHANCE: f64 = 0.35;
+/// Upward push in a liquid, as a multiple of the body's gravity.
+const BUOYANCY: f32 = 1.15;
+const LAVA_DAMAGE_PER_SECOND: f32 = 60.0;
+/// How bouncy red balls are in water.
+const WATER_RESTITUTION: f32 = 0.2;
+
+impl Liquid {
+    /// How quickly a body moving through the liquid slows down, per second.
+    fn drag(self) -> f32 {
+        match self {
+            Liquid::Water => 2.0,
+            Liquid::Lava => 4.0,
+        }
+    }
+
+    /// Multiplier on the player's walking speed.
+    pub fn speed_factor(self) -> f32 {
+        match self {
+            Liquid::Water => 0.55,
+            Liquid::Lava => 0.35,
+        }
+    }
+}
+
+/// Floods every column lower than the waterline, then turns some of the
+/// smaller pools into lava.
+pub fn fill_basins(voxels: &mut [Vec<Vec<Voxel>>], seed: u32) {
+    let width = voxels.len();
+    let height = voxels[0].len();
+    let depth = voxels[0][0].len();
+    let columns: Vec<Vec<usize>> = (0..width)
+        .map(|x| (0..depth).map(|z| column_height(voxels, x, z)).collect())
+        .collect();
+    // Columns without any terrain open onto the void; there is nothing to
+    // hold liquid there.
+    let mut heights: Vec<usize> = columns
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|column| *column > 0)
+        .collect();
+    if heights.is_empty() {
+        return;
+    }
+    heights.sort_unstable();
+    let level = (heights[heights.len() * BASIN_PERCENTILE / 100] + 1).min(height - 1);
+    let wet = |x: usize, z: usize| columns[x][z] > 0 && columns[x][z] < level;
+
+    let mut rng = StdRng::seed_from_u64(seed as u64 ^ LIQUID_SEED);
+    let mut visited = vec![vec![false; depth]; width];
+    for x in 0..width {
+        for z in 0..depth {
+            if visited[x][z] || !wet(x, z) {
+                continue;
+            }
+            visited[x][z] = true;
+            let mut pool = vec![(x, z)];
+            let mut next = 0;
+            while let Some(&(px, pz)) = pool.get(next) {
+                next += 1;
+                let neighbours = [
+                    (px.wrapping_sub(1), pz),
+                    (px + 1, pz),
+                    (px, pz.wrapping_sub(1)),
+                    (px, pz + 1),
+                ];
+                for (nx, nz) in neighbours {
+                    if nx < width && nz < depth && !visited[nx][nz] && wet(nx, nz) {
+                        visited[nx][nz] = true;
+                        pool.push((nx, nz));
+                    }
+                }
+            }
+            let liquid = if pool.len() <= MAX_LAVA_POOL && rng.gen_bool(LAVA_CHANCE) {
+                Liquid::Lava
+            } else {
+                Liquid::Water
+            };
+            for (px, pz) in pool {
+                for layer in &mut voxels[px][columns[px][pz]..level] {
+                    layer[pz] = Voxel::Liquid(liquid);
+                }
+            }
+        }
+    }
+}
+
+/// The liquid resting on a column's terrain, where it starts and how many
+/// layers deep it is.
+fn liquid_column(voxel_data: &[Vec<Vec<Voxel>>], x: usize, z: usize) -> Option<(Liquid, usize, usize)> {
+    let bottom = column_height(voxel_data, x, z);
+    let Some(Voxel::Liquid(liquid)) = voxel_data[x].get(bottom).map(|layer| layer[z]) else {
+        return None;
+    };
+    let layers = voxel_data[x][bottom..]
+        .iter()
+        .take_while(|layer| layer[z] == Voxel::Liquid(liquid))
+        .count();
+    Some((liquid, bottom, layers))
+}
+
+/// Spawns translucent sensor volumes over the flooded columns, merging
+/// neighbouring columns of the same liquid and depth into one box.
+pub fn spawn_liquids(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    voxel_data: &[Vec<Vec<Voxel>>],
+) {
+    let width = voxel_data.len();
+    let depth = voxel_data[0][0].len();
+    let mesh = meshes.add(shape::Cube::new(1.0).into());
+    let water = materials.add(StandardMaterial {
+        base_color: Color::rgba(0.2, 0.45, 0.9, 0.5),
+        perceptual_roughness: 0.1,
+        alpha_mode: AlphaMode::Blend,
+        ..default()
+    });
+    let lava = materials.add(StandardMaterial {
+        base_color: Color::rgba(1.0, 0.35, 0.05, 0.85),
+        emissive: Color::rgb(0.9, 0.25, 0.0),
+        alpha_mode: AlphaMode::Blend,
+        ..default()
+    });
+    let pools: Vec<Vec<_>> = (0..width)
+        .map(|x| (0..depth).map(|z| liquid_column(voxel_data, x, z)).collect())
+        .collect();
+    let mut covered = vec![vec![false; depth]; width];
+    for x in 0..width {
+        for z in 0..depth {
+            let Some(column) = pools[x][z] else {
+                continue;
+            };
+            if covered[x][z] {
+                continue;
+            }
+            // Grow the box along z first, then along x while whole rows match.
+            let same = |cx: usize, cz: usize| !covered[cx][cz] && pools[cx][cz] == Some(column);
+            let rows = (z..depth).take_while(|&cz| same(x, cz)).count();
+            let columns = (x..width)
+                .take_while(|&cx| (z..z + rows).all(|cz| same(cx, cz)))
+                .count();
+            for covered in &mut covered[x..x + columns] {
+                covered[z..z + rows].fill(true);
+            }
+
+            let (liquid, bottom, layers) = column;
+            let size = Vec3::new(columns as f32, layers as f32, rows as f32);
+            let position = voxel_position(voxel_data, (x, bottom, z)) + (size - Vec3::ONE) / 2.0;
+            commands.spawn((
+                PbrBundle {
+                    mesh: mesh.clone(),
+                    material: match liquid {
+                        Liquid::Water => water.clone(),
+                        Liquid::Lava => lava.clone(),
+                    },
+                    transform: Transform::from_translation(position).with_scale(size),
+                    ..default()
+                },
+                RigidBody::Fixed,
+                Collider::cuboid(0.5, 0.5, 0.5),
+                Sensor,
+                liquid,
+                Reboot,
+            ));
+        }
+    }
+}
+
+/// Tracks which liquid, if any, each body is touching. Lava wins over water.
+fn detect_liquids(
+    rapier_context: Res<RapierContext>,
+    liquids: Query<&Liquid>,
+    mut bodies: Query<(Entity, &mut InLiquid)>,
+) {
+    for (entity, mut in_liquid) in &mut bodies {
+        let liquid = rapier_context
+            .intersections_with(entity)
+            .filter(|(_, _, intersecting)| *intersecting)
+            .filter_map(|(a, b, _)| liquids.get(if a == entity { b } else { a }).ok())
+            .copied()
+            .max();
+        if in_liquid.0 != liquid {
+            in_liquid.0 = liquid;
+        }
+    }
+}
+
+fn buoyancy(time: Res<Time>, mut bodies: Query<(&InLiquid, &mut Velocity, Option<&GravityScale>)>) {
+    for (in_liquid, mut velocity, gravity_scale) in &mut bodies {
+        let Some(liquid) = in_liquid.0 else {
+            continue;
+        };
+        let gravity = 9.81 * gravity_scale.map_or(1.0, |scale| scale.0);
+        velocity.linvel.y += gravity * BUOYANCY * time.delta_seconds();
+        velocity.linvel *= 1.0 - (liquid.drag() * time.delta_seconds()).min(1.0);
+    }
+}
+
+/// Bouncy balls lose most of their bounce in water.
+fn damp_bounces(
+    mut balls: Query<(&InLiquid, &Bounciness, &mut Restitution), Changed<InLiquid>>,
+) {
+    for (in_liquid, bounciness, mut restitution) in &mut balls {
+        restitution.coefficient = match in_liquid.0 {
+            Some(Liquid::Water) => WATER_RESTITUTION,
+            _ => bounciness.0,
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn lava_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    cheats: Res<Cheats>,
+    power_ups: Res<ActivePowerUps>,
+    mut players: Query<
+        (&InLiquid, &mut Health, Option<&Player>, Option<&Bot>),
+        Or<(With<Player>, With<RemotePlayer>, With<Bot>)>,
+    >,
+    enemies: Query<(Entity, &InLiquid, &Transform), With<Enemy>>,
+    mut notifications: EventWriter<Notification>,
+    mut sounds: EventWriter<SpatialSound>,
+    role: Res<NetRole>,
+) {
+    // A client's own ball and red balls are burned by the server.
+    if *role == NetRole::Client {
+        return;
+    }
+    for (in_liquid, mut health, local, bot) in &mut players {
+        // Cheats and power-ups only look after the people playing.
+        let protected = bot.is_none()
+            && (cheats.god || power_ups.get(PowerUpKind::Invulnerability).is_some());
+        if in_liquid.0 != Some(Liquid::Lava) || health.current <= 0.0 || protected {
+            continue;
+        }
+        health.current -= LAVA_DAMAGE_PER_SECOND * time.delta_seconds();
+        if health.current <= 0.0 {
+            health.current = 0.0;
+            if local.is_some() {
+                notifications.send(Notification("Burned in lava".into()));
+            }
+        }
+    }
+    for (enemy, in_liquid, transform) in &enemies {
+        if in_liquid.0 == Some(Liquid::Lava) {
+            commands.entity(enemy).despawn_recursive();
+            sounds.send(SpatialSound {
+                effect: SoundEffect::Death,
+                position: transform.translation,
+                volume: 0.5,
+            });
+        }
+    }
+}
+
+pub struct LiquidPlugin;
+impl Plugin for LiquidPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (detect_liquids, buoyancy, damp_bounces, lava_system)
+                .chain()
+                .in_set(OnUpdate(GameState::InGame)),
+        );
+    }
+}
@@ -1,216 +1,210 @@
 use noise::{NoiseFn, Perlin};
-use rand::{seq::SliceRandom, Rng};
-
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::game::archetypes::{
+    spawn_archetype, Archetype, Archetypes, Tag, BLUE_BALL, RED_BALL, SUN, WALL,
+};
+use crate::game::features::spawn_features;
+use crate::game::islands::{generate_islands, spawn_islands, SkyIsland};
+use crate::game::levels::{spawn_placements, LevelSelect};
+use crate::game::liquid::{fill_basins, spawn_liquids};
+use crate::game::sky::{spawn_sky, Sun};
 use crate::prelude::*;
 use bevy_rapier3d::prelude::*;
 use std::f32::consts::PI;
 const VOXEL_SIZE: f32 = 1.0;
+/// Bumped whenever a seed starts generating a different map, so ghosts
+/// recorded on the old one are not replayed.
+pub const MAP_VERSION: u16 = 4;
 
-pub fn spawn_balls(
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    voxel_data: &Vec<Vec<Vec<bool>>>,
-) {
-    let width = voxel_data.len();
-    let height = voxel_data[0].len();
-    let depth = voxel_data[0][0].len();
+/// World position of the voxel at `(x, y, z)`.
+pub fn voxel_position(voxel_data: &[Vec<Vec<Voxel>>], spot: (usize, usize, usize)) -> Vec3 {
+    grid_position(voxel_data.len(), voxel_data[0][0].len(), spot)
+}
+
+/// World position of the voxel at `(x, y, z)` in a map `width` by `depth`
+/// voxels across.
+pub fn grid_position(width: usize, depth: usize, (x, y, z): (usize, usize, usize)) -> Vec3 {
     let center_offset = Vec3::new(
         -(width as f32 * VOXEL_SIZE / 2.0),
         0.5,
         -(depth as f32 * VOXEL_SIZE / 2.0),
     );
+    Vec3::new(x as f32, y as f32, z as f32) * VOXEL_SIZE + center_offset
+}
+
+/// Height of the topmost solid voxel in the column, 0 when it is empty.
+pub fn column_height(voxel_data: &[Vec<Vec<Voxel>>], x: usize, z: usize) -> usize {
+    voxel_data[x]
+        .iter()
+        .rposition(|layer| layer[z].is_solid())
+        .map_or(0, |y| y + 1)
+}
+
+/// Height of the highest terrain within `reach` voxels of `position`.
+pub fn ground_height(voxel_data: &[Vec<Vec<Voxel>>], position: Vec3, reach: i32) -> f32 {
+    let width = voxel_data.len() as i32;
+    let depth = voxel_data[0][0].len() as i32;
+    let origin = voxel_position(voxel_data, (0, 0, 0));
+    let (cx, cz) = (
+        (position.x - origin.x).round() as i32,
+        (position.z - origin.z).round() as i32,
+    );
+    let mut top = 0;
+    for x in (cx - reach).max(0)..=(cx + reach).min(width - 1) {
+        for z in (cz - reach).max(0)..=(cz + reach).min(depth - 1) {
+            top = top.max(column_height(voxel_data, x as usize, z as usize));
+        }
+    }
+    top as f32
+}
+
+/// The first empty voxel above solid ground in each column, where items can
+/// be placed.
+#[allow(clippy::needless_range_loop)]
+pub fn surface_spots(voxel_data: &[Vec<Vec<Voxel>>]) -> Vec<(usize, usize, usize)> {
+    let width = voxel_data.len();
+    let height = voxel_data[0].len();
+    let depth = voxel_data[0][0].len();
 
     let mut positions: Vec<(usize, usize, usize)> = Vec::new();
     for x in 0..width {
         for z in 0..depth {
             let mut has_block_below = false;
             for y in 0..height {
-                if voxel_data[x][y][z] {
+                if voxel_data[x][y][z].is_solid() {
                     has_block_below = true;
                 } else if has_block_below {
-                    positions.push((x, y, z));
+                    if voxel_data[x][y][z] == Voxel::Empty {
+                        positions.push((x, y, z));
+                    }
                     break;
                 }
             }
         }
     }
+    positions
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_balls(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    voxel_data: &[Vec<Vec<Voxel>>],
+    island_spots: &[(usize, usize, usize)],
+    rng: &mut StdRng,
+    rules: &ModeDef,
+    archetypes: &Archetypes,
+    archetype_assets: &Assets<Archetype>,
+) -> usize {
+    let mut positions = surface_spots(voxel_data);
+    positions.shuffle(rng);
+    let mut island_spots = island_spots.to_vec();
+    island_spots.shuffle(rng);
+
+    let mut ground_spots = positions.iter();
+    let mut blue_spots = Vec::new();
+    for _ in 0..rules.blue_balls {
+        // Some balls are hidden up on the sky islands.
+        let on_island = if rng.gen_bool(0.25) { island_spots.pop() } else { None };
+        blue_spots.extend(on_island.or_else(|| ground_spots.next().copied()));
+    }
 
-    positions.shuffle(&mut rand::thread_rng());
-
-    let num_blue_balls = 5;
-    let positions_to_spawn = positions.iter().take(num_blue_balls);
-
-    for (x, y, z) in positions_to_spawn {
-        let position = Vec3::new(*x as f32, *y as f32, *z as f32) * VOXEL_SIZE + center_offset;
-
-        commands
-            .spawn(PbrBundle {
-                mesh: meshes.add(shape::UVSphere::default().into()),
-                material: materials.add(Color::BLUE.into()),
-                visibility: Visibility::Visible,
-                transform: Transform::from_translation(position),
-                ..Default::default()
-            })
-            .insert(RigidBody::Fixed)
-            .insert(Collider::ball(VOXEL_SIZE))
-            .insert(Reboot)
-            .insert(Renderable)
-            .insert(BlueBall);
+    let blue_ball = archetypes.get(BLUE_BALL, archetype_assets).unwrap_or_default();
+    for spot in &blue_spots {
+        let position = voxel_position(voxel_data, *spot);
+        spawn_archetype(commands, meshes, materials, BLUE_BALL, &blue_ball, Transform::from_translation(position))
+            .insert(Reboot);
     }
 
-    for (x, y, z) in positions.iter().skip(num_blue_balls) {
-        if rand::thread_rng().gen_range(0..200) < 2 {
-            let position = Vec3::new(*x as f32, *y as f32, *z as f32) * VOXEL_SIZE + center_offset;
-
-            commands
-                .spawn(PbrBundle {
-                    mesh: meshes.add(shape::UVSphere::default().into()),
-                    material: materials.add(Color::RED.into()),
-                    transform: Transform::from_translation(position),
-                    ..Default::default()
-                })
-                .insert(RigidBody::Dynamic)
-                .insert(Collider::ball(VOXEL_SIZE))
-                .insert(Restitution::coefficient(2.1))
-                .insert(Reboot)
-                .insert(Enemy);
+    if rules.base != GameMode::Zen {
+        let red_ball = archetypes.get(RED_BALL, archetype_assets).unwrap_or_default();
+        for spot in ground_spots {
+            if rng.gen_range(0..200) < rules.red_balls {
+                let position = voxel_position(voxel_data, *spot);
+                spawn_archetype(commands, meshes, materials, RED_BALL, &red_ball, Transform::from_translation(position))
+                    .insert(Reboot);
+            }
+        }
+    }
+
+    // Kinds of ball added in the archetype files.
+    for (name, archetype) in archetypes.scattered(archetype_assets) {
+        if rules.base == GameMode::Zen && archetype.tags.contains(&Tag::Enemy) {
+            continue;
+        }
+        for spot in &positions {
+            if !blue_spots.contains(spot) && rng.gen::<f32>() < archetype.scatter {
+                let position = voxel_position(voxel_data, *spot);
+                spawn_archetype(commands, meshes, materials, &name, &archetype, Transform::from_translation(position))
+                    .insert(Reboot);
+            }
         }
     }
+    blue_spots.len()
 }
 
 pub fn spawn_walls(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    archetypes: &Archetypes,
+    archetype_assets: &Assets<Archetype>,
 ) {
-    let collider_size = Vec3::new(64.0, 0.9, 100.0);
-
-    let mesh = Mesh::from(shape::Box::new(
-        collider_size.x,
-        collider_size.y,
-        collider_size.z,
-    ));
-
-    let mut transform = Transform::from_xyz(32.0, 16.0, 0.0);
-    transform.rotate_x(std::f32::consts::PI / 2.0);
-    transform.rotate_y(std::f32::consts::PI / 2.0);
-    commands
-        .spawn(PbrBundle {
-            mesh: meshes.add(mesh.clone()),
-            transform,
-            material: materials.add(StandardMaterial {
-                base_color: Color::GRAY,
-                perceptual_roughness: 1.0,
-                ..Default::default()
-            }),
-            ..Default::default()
-        })
-        .insert(Reboot)
-        .insert(Collider::cuboid(
-            collider_size.x,
-            collider_size.y,
-            collider_size.z,
-        ))
-        .insert(RigidBody::Fixed);
-
-    let mut transform = Transform::from_xyz(0.0, 16.0, -32.0);
-    transform.rotate_x(std::f32::consts::PI / 2.0);
-    commands
-        .spawn(PbrBundle {
-            mesh: meshes.add(mesh.clone()),
-            transform,
-            material: materials.add(StandardMaterial {
-                base_color: Color::GRAY,
-                perceptual_roughness: 1.0,
-                ..Default::default()
-            }),
-            ..Default::default()
-        })
-        .insert(Reboot)
-        .insert(Collider::cuboid(
-            collider_size.x,
-            collider_size.y,
-            collider_size.z,
-        ))
-        .insert(RigidBody::Fixed);
-
-    let mut transform = Transform::from_xyz(-32.0, 16.0, 0.0);
-    transform.rotate_x(std::f32::consts::PI / 2.0);
-    transform.rotate_y(std::f32::consts::PI / 2.0);
-    commands
-        .spawn(PbrBundle {
-            mesh: meshes.add(mesh.clone()),
-            transform,
-            material: materials.add(StandardMaterial {
-                base_color: Color::GRAY,
-                perceptual_roughness: 1.0,
-                ..Default::default()
-            }),
-            ..Default::default()
-        })
-        .insert(Reboot)
-        .insert(Collider::cuboid(
-            collider_size.x,
-            collider_size.y,
-            collider_size.z,
-        ))
-        .insert(RigidBody::Fixed);
-
-    let mut transform = Transform::from_xyz(0.0, 16.0, 32.0);
-    transform.rotate_x(std::f32::consts::PI / 2.0);
-    commands
-        .spawn(PbrBundle {
-            mesh: meshes.add(mesh),
-            transform,
-            material: materials.add(StandardMaterial {
-                base_color: Color::GRAY,
-                perceptual_roughness: 1.0,
-                ..Default::default()
-            }),
-            ..Default::default()
-        })
-        .insert(Reboot)
-        .insert(Collider::cuboid(
-            collider_size.x,
-            collider_size.y,
-            collider_size.z,
-        ))
-        .insert(RigidBody::Fixed);
+    let wall = archetypes.get(WALL, archetype_assets).unwrap_or_default();
+    for (position, turned) in [
+        (Vec3::new(32.0, 16.0, 0.0), true),
+        (Vec3::new(0.0, 16.0, -32.0), false),
+        (Vec3::new(-32.0, 16.0, 0.0), true),
+        (Vec3::new(0.0, 16.0, 32.0), false),
+    ] {
+        let mut transform = Transform::from_translation(position);
+        transform.rotate_x(std::f32::consts::PI / 2.0);
+        if turned {
+            transform.rotate_y(std::f32::consts::PI / 2.0);
+        }
+        spawn_archetype(commands, meshes, materials, WALL, &wall, transform).insert(Reboot);
+    }
 }
 
-pub fn generate_voxels(width: usize, height: usize, depth: usize) -> Vec<Vec<Vec<bool>>> {
-    let random_int: u32 = rand::thread_rng().gen_range(1..=100);
-
-    let perlin = Perlin::new(random_int);
+#[allow(clippy::needless_range_loop)]
+pub fn generate_voxels(
+    width: usize,
+    height: usize,
+    depth: usize,
+    seed: u32,
+    terrain: &TerrainPreset,
+) -> Vec<Vec<Vec<Voxel>>> {
+    let perlin = Perlin::new(seed);
 
-    let mut voxels = vec![vec![vec![false; depth]; height]; width];
+    let mut voxels = vec![vec![vec![Voxel::Empty; depth]; height]; width];
 
     for x in 0..width {
         for y in 0..height {
             for z in 0..depth {
                 let noise_value = perlin.get([
-                    (x as f64 - (width as f64 / 2.0)) / 10.0,
-                    (y as f64 - (height as f64 / 2.0)) / 10.0,
-                    (z as f64 - (depth as f64 / 2.0)) / 10.0,
+                    (x as f64 - (width as f64 / 2.0)) / terrain.scale,
+                    (y as f64 - (height as f64 / 2.0)) / terrain.scale,
+                    (z as f64 - (depth as f64 / 2.0)) / terrain.scale,
                 ]);
 
-                let threshold = 0.2;
-
-                if noise_value > threshold {
-                    voxels[x][y][z] = true;
+                if noise_value > terrain.threshold {
+                    voxels[x][y][z] = Voxel::Solid;
                 }
             }
         }
     }
+    fill_basins(&mut voxels, seed);
     voxels
 }
 
+#[allow(clippy::needless_range_loop)]
 pub fn spawn_cubes(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
-    voxel_data: &Vec<Vec<Vec<bool>>>,
+    voxel_data: &[Vec<Vec<Voxel>>],
 ) {
     let width = voxel_data.len();
     let height = voxel_data[0].len();
@@ -231,16 +225,16 @@ pub fn spawn_cubes(
     for x in 0..width {
         for y in 0..height {
             for z in 0..depth {
-                if voxel_data[x][y][z] {
+                if voxel_data[x][y][z].is_solid() {
                     let position =
                         Vec3::new(x as f32, y as f32, z as f32) * VOXEL_SIZE + center_offset;
 
-                    let has_neighbors_on_all_sides = (x > 0 && voxel_data[x - 1][y][z])  // Left
-                        && (x < width - 1 && voxel_data[x + 1][y][z])  // Right
-                        && (y > 0 && voxel_data[x][y - 1][z])  // Bottom
-                        && (y < height - 1 && voxel_data[x][y + 1][z])  // Top
-                        && (z > 0 && voxel_data[x][y][z - 1])  // Back
-                        && (z < depth - 1 && voxel_data[x][y][z + 1]); // Front
+                    let has_neighbors_on_all_sides = (x > 0 && voxel_data[x - 1][y][z].is_solid())  // Left
+                        && (x < width - 1 && voxel_data[x + 1][y][z].is_solid())  // Right
+                        && (y > 0 && voxel_data[x][y - 1][z].is_solid())  // Bottom
+                        && (y < height - 1 && voxel_data[x][y + 1][z].is_solid())  // Top
+                        && (z > 0 && voxel_data[x][y][z - 1].is_solid())  // Back
+                        && (z < depth - 1 && voxel_data[x][y][z + 1].is_solid()); // Front
 
                     if !has_neighbors_on_all_sides {
                         commands
@@ -265,34 +259,103 @@ pub fn spawn_cubes(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     displayquality: Res<DisplayQuality>,
+    mut world_seed: ResMut<WorldSeed>,
+    mode: Res<GameMode>,
+    role: Res<NetRole>,
+    archetypes: Res<Archetypes>,
+    archetype_assets: Res<Assets<Archetype>>,
+    modes: Res<GameModes>,
+    choice: Res<ModeChoice>,
+    mut game_info: ResMut<GameInfo>,
+    mut levels: LevelSelect,
 ) {
-    let height;
-    match *displayquality {
-        DisplayQuality::Low => {
-            height = 8;
-        },
-        DisplayQuality::Medium => {
-            height = 32;
-        },
-        DisplayQuality::High => {
-            height = 64;
-        },
-    }
-
-    let voxel_data = generate_voxels(64, height, 64);
+    // Networked games stick to the built-in rules so every machine generates
+    // the same map.
+    let rules = match modes.modes.get(choice.0) {
+        Some(rules) if *role == NetRole::Offline && rules.base == *mode => rules.clone(),
+        _ => modes.modes[modes.builtin(*mode)].clone(),
+    };
+    let terrain = rules
+        .terrain
+        .as_deref()
+        .and_then(|name| modes.terrain(name))
+        .cloned()
+        .unwrap_or_default();
+    let height = world_seed
+        .pinned_height
+        .or(terrain.height.map(|height| height as u32))
+        .unwrap_or(match *displayquality {
+            DisplayQuality::Low => 8,
+            DisplayQuality::Medium => 32,
+            DisplayQuality::High => 64,
+        });
+    world_seed.height = height;
+
+    let seed = world_seed
+        .next
+        .take()
+        .unwrap_or_else(|| rand::thread_rng().gen_range(1..=100));
+    world_seed.current = seed;
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+
+    // A campaign level is built as it was drawn, without the random extras.
+    let level = levels.next_map(game_info.wins, *role);
+    let voxel_data = match &level {
+        Some(level) => level.voxels(),
+        None => generate_voxels(64, height as usize, 64, seed, &terrain),
+    };
 
     spawn_cubes(&mut commands, &mut meshes, &mut materials, &voxel_data); // + 24
 
-    spawn_balls(&mut commands, &mut meshes, &mut materials, &voxel_data); // + .5
+    spawn_liquids(&mut commands, &mut meshes, &mut materials, &voxel_data);
+
+    let mut island_spots = Vec::new();
+    if level.is_none() {
+        let islands = generate_islands(&voxel_data, seed);
+        spawn_islands(&mut commands, &mut meshes, &mut materials, &voxel_data, &islands, seed);
+        spawn_features(&mut commands, &mut meshes, &mut materials, &voxel_data, &islands, seed);
+        island_spots = islands.iter().flat_map(SkyIsland::surface_spots).collect();
+    }
+
+    // A client gets its balls from the server's snapshots.
+    game_info.total = rules.blue_balls as i32;
+    if let Some(level) = &level {
+        game_info.total = spawn_placements(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            level,
+            &voxel_data,
+            &archetypes,
+            &archetype_assets,
+        ) as i32;
+    } else if *role != NetRole::Client {
+        game_info.total = spawn_balls(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &voxel_data,
+            &island_spots,
+            &mut rng,
+            &rules,
+            &archetypes,
+            &archetype_assets,
+        ) as i32; // + .5
+    }
 
     commands.spawn(crate::prelude::FPSTimer { elapsed: 0.0 }).insert(Reboot);
 
-    spawn_walls(&mut commands, &mut meshes, &mut materials); // + .5
+    if level.as_ref().is_none_or(|level| level.walls) {
+        spawn_walls(&mut commands, &mut meshes, &mut materials, &archetypes, &archetype_assets); // + .5
+    }
+
+    commands.insert_resource(VoxelMap(voxel_data));
 
     commands
         .spawn(Collider::cuboid(64.0, 0.1, 64.0))
@@ -316,20 +379,26 @@ pub fn setup(
         brightness: 0.6,
     });
 
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            illuminance: 3200.0,
-            ..default()
-        },
-        transform: Transform::from_xyz(0.0, 2.0, 0.0)
-            .with_rotation(Quat::from_rotation_x(-PI / 4.)),
-        ..default()
-    }).insert(Reboot);
+    let sun = archetypes.get(SUN, &archetype_assets).unwrap_or_default();
+    spawn_archetype(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        SUN,
+        &sun,
+        Transform::from_xyz(0.0, 2.0, 0.0).with_rotation(Quat::from_rotation_x(-PI / 4.)),
+    )
+    .insert(Reboot)
+    .insert(Sun);
+
+    spawn_sky(&mut commands, &mut meshes, &mut materials, seed);
 }
 
 
 fn game_info(mut game_info: ResMut<GameInfo>,) {
-    game_info.is_won = true;
+    // The map was just generated from the launch seed; ending the round here
+    // would replace it with a random one.
+    game_info.is_won = false;
     game_info.collected = 0;
 
 }
@@ -0,0 +1,301 @@
+use crate::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::utils::HashSet;
+
+const MINIMAP_SIZE: f32 = 200.0;
+const COMPASS_POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+#[derive(Resource, Default)]
+struct MinimapImage(Handle<Image>);
+
+#[derive(Component)]
+struct Minimap;
+
+/// Dot following a blue ball or an enemy.
+#[derive(Component)]
+struct MinimapMarker(Entity);
+
+#[derive(Component)]
+struct PlayerMarker;
+
+#[derive(Component)]
+struct CompassText;
+
+/// Heading in degrees clockwise from north, where north is -Z.
+fn heading(transform: &Transform) -> f32 {
+    let forward = transform.forward();
+    forward.x.atan2(-forward.z).to_degrees().rem_euclid(360.0)
+}
+
+fn height_color(height: usize, max_height: usize) -> [u8; 4] {
+    if height == 0 {
+        return [77, 128, 77, 255];
+    }
+    let t = height as f32 / max_height.max(1) as f32;
+    let low = Vec3::new(120.0, 110.0, 80.0);
+    let high = Vec3::new(245.0, 245.0, 220.0);
+    let color = low.lerp(high, t);
+    [color.x as u8, color.y as u8, color.z as u8, 255]
+}
+
+fn build_minimap(
+    voxel_map: Res<VoxelMap>,
+    mut images: ResMut<Assets<Image>>,
+    mut minimap_image: ResMut<MinimapImage>,
+) {
+    if !voxel_map.is_changed() || voxel_map.0.is_empty() {
+        return;
+    }
+    let width = voxel_map.0.len();
+    let max_height = voxel_map.0[0].len();
+    let depth = voxel_map.0[0][0].len();
+
+    let mut data = Vec::with_capacity(width * depth * 4);
+    for z in 0..depth {
+        for x in 0..width {
+            data.extend(match voxel_map.column_liquid(x, z) {
+                Some(Liquid::Water) => [60, 110, 220, 255],
+                Some(Liquid::Lava) => [230, 90, 20, 255],
+                None => height_color(voxel_map.column_height(x, z), max_height),
+            });
+        }
+    }
+    minimap_image.0 = images.add(Image::new(
+        Extent3d {
+            width: width as u32,
+            height: depth as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+}
+
+fn minimap_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    minimap_image: Res<MinimapImage>,
+) {
+    commands
+        .spawn((
+            ImageBundle {
+                style: Style {
+                    size: Size::new(Val::Px(MINIMAP_SIZE), Val::Px(MINIMAP_SIZE)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(15.0),
+                        bottom: Val::Px(15.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                image: UiImage::new(minimap_image.0.clone()),
+                ..default()
+            },
+            Minimap,
+            Hud,
+            HudWidget::Minimap,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(4.0), Val::Px(16.0)),
+                        position_type: PositionType::Absolute,
+                        margin: UiRect {
+                            left: Val::Px(-2.0),
+                            top: Val::Px(-8.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    background_color: Color::WHITE.into(),
+                    z_index: ZIndex::Local(1),
+                    ..default()
+                },
+                PlayerMarker,
+            ));
+        });
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Auto),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(5.0),
+                        ..default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            Hud,
+            HudWidget::Compass,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 24.0,
+                        color: Color::BLACK,
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center),
+                CompassText,
+            ));
+        });
+}
+
+fn update_minimap_image(
+    minimap_image: Res<MinimapImage>,
+    mut minimap: Query<&mut UiImage, With<Minimap>>,
+) {
+    if minimap_image.is_changed() {
+        for mut image in &mut minimap {
+            image.texture = minimap_image.0.clone();
+        }
+    }
+}
+
+/// Position of a world point on the minimap, in percent of its size.
+fn map_position(voxel_map: &VoxelMap, position: Vec3) -> Option<(f32, f32)> {
+    let width = voxel_map.0.len() as f32;
+    let depth = voxel_map.0.first()?.first()?.len() as f32;
+    let x = (position.x + width / 2.0 + 0.5) / width;
+    let z = (position.z + depth / 2.0 + 0.5) / depth;
+    Some((x.clamp(0.0, 1.0) * 100.0, z.clamp(0.0, 1.0) * 100.0))
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_minimap_markers(
+    mut commands: Commands,
+    voxel_map: Res<VoxelMap>,
+    difficulty: Res<Difficulty>,
+    power_ups: Res<ActivePowerUps>,
+    player: Query<&Transform, With<PrimaryPlayer>>,
+    blue_balls: Query<(Entity, &Transform), (With<BlueBall>, Without<PlayerMarker>)>,
+    enemies: Query<(Entity, &Transform), (With<Enemy>, Without<PlayerMarker>)>,
+    minimap: Query<Entity, With<Minimap>>,
+    mut markers: Query<(Entity, &MinimapMarker, &mut Style, &mut Visibility), Without<PlayerMarker>>,
+    mut player_marker: Query<(&mut Style, &mut Transform), (With<PlayerMarker>, Without<PrimaryPlayer>)>,
+) {
+    let (Ok(player), Ok(minimap)) = (player.get_single(), minimap.get_single()) else {
+        return;
+    };
+    let Some((player_x, player_z)) = map_position(&voxel_map, player.translation) else {
+        return;
+    };
+    for (mut style, mut transform) in &mut player_marker {
+        style.position.left = Val::Percent(player_x);
+        style.position.top = Val::Percent(player_z);
+        transform.rotation = Quat::from_rotation_z(heading(player).to_radians());
+    }
+
+    let nearby = |radius: Option<f32>, position: Vec3| {
+        radius.is_some_and(|radius| player.translation.distance(position) <= radius)
+    };
+    let ball_radius = power_ups
+        .strength(PowerUpKind::Radar)
+        .or(difficulty.ball_detection_radius());
+    let enemy_radius = difficulty.enemy_detection_radius();
+    let target = |entity: Entity| {
+        if let Ok((_, ball)) = blue_balls.get(entity) {
+            return Some((ball.translation, nearby(ball_radius, ball.translation)));
+        }
+        let (_, enemy) = enemies.get(entity).ok()?;
+        Some((enemy.translation, nearby(enemy_radius, enemy.translation)))
+    };
+
+    let mut marked = HashSet::new();
+    for (marker, MinimapMarker(entity), mut style, mut visibility) in &mut markers {
+        let Some((position, shown)) = target(*entity) else {
+            commands.entity(marker).despawn_recursive();
+            continue;
+        };
+        marked.insert(*entity);
+        if let Some((x, z)) = map_position(&voxel_map, position) {
+            style.position.left = Val::Percent(x);
+            style.position.top = Val::Percent(z);
+        }
+        *visibility = if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    let balls = blue_balls.iter().map(|(entity, _)| (entity, Color::BLUE));
+    let enemies = enemies.iter().map(|(entity, _)| (entity, Color::RED));
+    commands.entity(minimap).with_children(|parent| {
+        for (entity, color) in balls.chain(enemies) {
+            if marked.contains(&entity) {
+                continue;
+            }
+            // Placed and shown by the next update.
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(8.0), Val::Px(8.0)),
+                        position_type: PositionType::Absolute,
+                        margin: UiRect {
+                            left: Val::Px(-4.0),
+                            top: Val::Px(-4.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                MinimapMarker(entity),
+            ));
+        }
+    });
+}
+
+fn update_compass(
+    player: Query<&Transform, With<PrimaryPlayer>>,
+    mut compass: Query<&mut Text, With<CompassText>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let heading = heading(player);
+    let center = (heading / 5.0).round() as i32 * 5;
+
+    let mut strip = String::new();
+    for offset in (-90..=90).step_by(5) {
+        let angle = (center + offset).rem_euclid(360);
+        if angle % 45 == 0 {
+            strip.push_str(COMPASS_POINTS[angle as usize / 45]);
+        } else if angle % 15 == 0 {
+            strip.push('|');
+        } else {
+            strip.push('.');
+        }
+    }
+    for mut text in &mut compass {
+        text.sections[0].value = format!("{}\n{:03}", strip, heading.round() as i32 % 360);
+    }
+}
+
+pub struct MinimapPlugin;
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MinimapImage>()
+            .init_resource::<VoxelMap>()
+            .add_system(build_minimap)
+            .add_system(minimap_setup.in_schedule(OnEnter(GameState::InGame)))
+            .add_systems(
+                (update_minimap_image, update_minimap_markers, update_compass)
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
@@ -1,5 +1,21 @@
+pub mod archetypes;
+pub mod bots;
+pub mod data;
+pub mod debug;
+pub mod features;
 pub mod gameplay;
+pub mod ghost;
+pub mod hud;
+pub mod islands;
+pub mod levels;
+pub mod liquid;
 pub mod load;
+pub mod minimap;
+pub mod mods;
+pub mod music;
 pub mod physics;
-pub mod text;
-pub mod sound;
\ No newline at end of file
+pub mod powerup;
+pub mod save;
+pub mod sky;
+pub mod sound;
+pub mod spectator;
\ No newline at end of file
@@ -0,0 +1,380 @@
+use crate::prelude::*;
+use bevy::asset::{AssetIo, AssetIoError, AssetPath, AssetSet, FileAssetIo, LoadState, Metadata};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+const MODS_DIR: &str = "mods";
+const MANIFEST: &str = "mod.ron";
+/// Seconds between looking for edited asset files while mods are loaded.
+const WATCH_INTERVAL: f32 = 0.5;
+
+/// What a mod says about itself in its `mod.ron`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ModManifest {
+    pub name: String,
+    pub version: String,
+    pub author: String,
+    pub description: String,
+    pub game_modes: Vec<ModeDef>,
+    pub terrain_presets: Vec<TerrainPreset>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModStatus {
+    Loaded,
+    Failed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ModInfo {
+    /// The name of the mod's folder.
+    pub id: String,
+    pub dir: PathBuf,
+    pub manifest: ModManifest,
+    pub status: ModStatus,
+    /// Conflicts and files that failed to load, shown in the mod list.
+    pub issues: Vec<String>,
+}
+
+impl ModInfo {
+    pub fn title(&self) -> &str {
+        if self.manifest.name.is_empty() {
+            &self.id
+        } else {
+            &self.manifest.name
+        }
+    }
+}
+
+/// The folders found under `mods/`, in the order they are applied.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Mods {
+    pub mods: Vec<ModInfo>,
+    /// Which mod each file they add or replace under the assets directory is
+    /// read from.
+    files: HashMap<PathBuf, usize>,
+}
+
+/// Every file below `dir`, relative to `root`.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(root, &path, files);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+}
+
+impl Mods {
+    /// Reads every mod's manifest, in alphabetical order so later mods win
+    /// conflicts the same way on every machine.
+    fn discover() -> Mods {
+        let mut mods = Mods::default();
+        let Ok(entries) = fs::read_dir(FileAssetIo::get_base_path().join(MODS_DIR)) else {
+            return mods;
+        };
+        let mut dirs: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+        dirs.sort();
+
+        for dir in dirs {
+            let id = dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let manifest = match fs::read_to_string(dir.join(MANIFEST)) {
+                Ok(contents) => ron::from_str::<ModManifest>(&contents)
+                    .map_err(|err| format!("Invalid {MANIFEST}: {err}")),
+                Err(_) => Err(format!("No {MANIFEST} found")),
+            };
+            let index = mods.mods.len();
+            let mut info = ModInfo {
+                id,
+                dir,
+                manifest: ModManifest::default(),
+                status: ModStatus::Loaded,
+                issues: Vec::new(),
+            };
+            match manifest {
+                Ok(manifest) => info.manifest = manifest,
+                Err(reason) => {
+                    info.status = ModStatus::Failed(reason);
+                    mods.mods.push(info);
+                    continue;
+                }
+            }
+
+            let mut files = Vec::new();
+            collect_files(&info.dir, &info.dir, &mut files);
+            files.retain(|file| file != Path::new(MANIFEST));
+            files.sort();
+            for file in files {
+                if let Some(previous) = mods.files.insert(file.clone(), index) {
+                    let other = &mut mods.mods[previous];
+                    other.issues.push(format!("{} is replaced by {}", file.display(), info.id));
+                    info.issues.push(format!("{} replaces the one from {}", file.display(), other.id));
+                }
+            }
+            mods.mods.push(info);
+        }
+        mods
+    }
+
+    /// The built-in game modes followed by those the mods add, skipping any
+    /// whose name is already taken.
+    fn game_modes(&mut self) -> GameModes {
+        let mut modes = GameModes::default();
+        for info in &mut self.mods {
+            for preset in &info.manifest.terrain_presets {
+                if modes.terrain(&preset.name).is_some() {
+                    info.issues.push(format!("Terrain {} is already defined", preset.name));
+                } else {
+                    modes.terrain.push(preset.clone());
+                }
+            }
+        }
+        for info in &mut self.mods {
+            for mode in &info.manifest.game_modes {
+                if modes.modes.iter().any(|known| known.name == mode.name) {
+                    info.issues.push(format!("Game mode {} is already defined", mode.name));
+                    continue;
+                }
+                if let Some(terrain) = mode.terrain.as_ref().filter(|name| modes.terrain(name).is_none()) {
+                    info.issues.push(format!("Game mode {} uses unknown terrain {}", mode.name, terrain));
+                }
+                modes.modes.push(mode.clone());
+            }
+        }
+        modes
+    }
+
+    /// Where the file at `path` under the assets directory is read from.
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        match self.files.get(Path::new(path)) {
+            Some(&index) => self.mods[index].dir.join(path),
+            None => FileAssetIo::get_base_path().join("assets").join(path),
+        }
+    }
+
+    /// The files mods add directly inside an assets folder.
+    pub fn files_in<'a>(&'a self, dir: &'a str) -> impl Iterator<Item = &'a Path> {
+        self.files
+            .keys()
+            .filter(move |file| file.parent() == Some(Path::new(dir)))
+            .map(PathBuf::as_path)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Files on disk being watched, with the asset to reload when one changes and
+/// when it last did.
+type WatchedFiles = HashMap<PathBuf, (PathBuf, Option<SystemTime>)>;
+
+/// Reads assets from the mod that provides them, falling back to the game's own.
+struct ModAssetIo {
+    assets: FileAssetIo,
+    mods: Vec<FileAssetIo>,
+    files: HashMap<PathBuf, usize>,
+    /// `None` until watching is turned on.
+    watched: Mutex<Option<WatchedFiles>>,
+}
+
+impl ModAssetIo {
+    fn source(&self, path: &Path) -> &FileAssetIo {
+        match self.files.get(path) {
+            Some(&index) => &self.mods[index],
+            None => &self.assets,
+        }
+    }
+
+    /// The assets whose files were modified since the last look.
+    fn changed(&self) -> Vec<PathBuf> {
+        let mut watched = self.watched.lock().unwrap();
+        let Some(watched) = watched.as_mut() else {
+            return Vec::new();
+        };
+        let mut changed = Vec::new();
+        for (file, (to_reload, last_modified)) in watched.iter_mut() {
+            let modified = modified(file);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed.push(to_reload.clone());
+            }
+        }
+        changed
+    }
+}
+
+impl AssetIo for ModAssetIo {
+    fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+        self.source(path).load_path(path)
+    }
+
+    fn read_directory(
+        &self,
+        path: &Path,
+    ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+        let mut entries: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|file| file.parent() == Some(path))
+            .cloned()
+            .collect();
+        match self.assets.read_directory(path) {
+            Ok(listed) => entries.extend(listed),
+            Err(err) if entries.is_empty() => return Err(err),
+            Err(_) => {}
+        }
+        entries.sort();
+        entries.dedup();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn get_metadata(&self, path: &Path) -> Result<Metadata, AssetIoError> {
+        self.source(path).get_metadata(path)
+    }
+
+    // Bevy only drains a `FileAssetIo`'s own watcher when it is the server's
+    // whole source, so the files are polled by `reload_changed_assets` instead,
+    // wherever the mod or the game keeps them.
+    fn watch_path_for_changes(
+        &self,
+        to_watch: &Path,
+        to_reload: Option<PathBuf>,
+    ) -> Result<(), AssetIoError> {
+        if let Some(watched) = self.watched.lock().unwrap().as_mut() {
+            let file = self.source(to_watch).root_path().join(to_watch);
+            let to_reload = to_reload.unwrap_or_else(|| to_watch.to_owned());
+            let last_modified = modified(&file);
+            watched.insert(file, (to_reload, last_modified));
+        }
+        Ok(())
+    }
+
+    fn watch_for_changes(&self) -> Result<(), AssetIoError> {
+        self.watched.lock().unwrap().get_or_insert_with(HashMap::new);
+        Ok(())
+    }
+}
+
+/// Reloads assets edited on disk while the asset server reads through mods.
+fn reload_changed_assets(
+    time: Res<Time>,
+    mut since_check: Local<f32>,
+    asset_server: Res<AssetServer>,
+) {
+    *since_check += time.delta_seconds();
+    if *since_check < WATCH_INTERVAL {
+        return;
+    }
+    *since_check = 0.0;
+    let Some(asset_io) = asset_server.asset_io().downcast_ref::<ModAssetIo>() else {
+        return;
+    };
+    for path in asset_io.changed() {
+        asset_server.reload_asset(path.as_path());
+    }
+}
+
+/// Finds the mods and, when they bring any files, has the asset server read
+/// through them. Must run before `DefaultPlugins`, which otherwise sets up an
+/// asset server reading only the assets directory, and so never sees its
+/// `watch_for_changes` setting.
+pub fn add_mods(app: &mut App, watch_for_changes: bool) {
+    let mut mods = Mods::discover();
+    let modes = mods.game_modes();
+    if !mods.files.is_empty() {
+        let asset_io = ModAssetIo {
+            assets: FileAssetIo::new("assets", false),
+            mods: mods.mods.iter().map(|info| FileAssetIo::new(&info.dir, false)).collect(),
+            files: mods.files.clone(),
+            watched: Mutex::default(),
+        };
+        if watch_for_changes {
+            asset_io.watch_for_changes().unwrap();
+        }
+        app.insert_resource(AssetServer::new(asset_io));
+    }
+    app.insert_resource(mods).insert_resource(modes);
+}
+
+/// Logs what was found, which `add_mods` runs too early to do itself.
+fn log_mods(mods: Res<Mods>) {
+    for info in &mods.mods {
+        match &info.status {
+            ModStatus::Loaded => info!("Loaded mod {}", info.title()),
+            ModStatus::Failed(reason) => warn!("Skipped mod {}: {}", info.id, reason),
+        }
+        for issue in &info.issues {
+            warn!("Mod {}: {}", info.id, issue);
+        }
+    }
+}
+
+/// Notes each file a mod provides that the game failed to load, so a broken
+/// asset shows up in the mod list rather than only in the log.
+fn report_failed_files(
+    asset_server: Res<AssetServer>,
+    mut mods: ResMut<Mods>,
+    mut reported: Local<HashSet<PathBuf>>,
+) {
+    let failed: Vec<(PathBuf, usize)> = mods
+        .files
+        .iter()
+        .filter(|(file, _)| !reported.contains(*file))
+        .filter(|(file, _)| {
+            asset_server.get_load_state(AssetPath::from(file.as_path())) == LoadState::Failed
+        })
+        .map(|(file, &index)| (file.clone(), index))
+        .collect();
+    for (file, index) in failed {
+        let info = &mut mods.mods[index];
+        warn!("Mod {} failed to load {}", info.id, file.display());
+        info.issues.push(format!("{} failed to load", file.display()));
+        reported.insert(file);
+    }
+}
+
+/// Keeps the mode picked in the menu and the rules it is based on in step,
+/// whichever of the two was changed.
+fn sync_mode_choice(
+    modes: Res<GameModes>,
+    mut choice: ResMut<ModeChoice>,
+    mut mode: ResMut<GameMode>,
+) {
+    let base = modes.modes.get(choice.0).map(|def| def.base);
+    if choice.is_changed() && !choice.is_added() {
+        if let Some(base) = base.filter(|base| *base != *mode) {
+            *mode = base;
+        }
+    } else if mode.is_changed() && base != Some(*mode) {
+        choice.0 = modes.builtin(*mode);
+    }
+}
+
+pub struct ModPlugin;
+impl Plugin for ModPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Mods>()
+            .init_resource::<GameModes>()
+            .init_resource::<ModeChoice>()
+            .add_startup_system(log_mods)
+            .add_system(report_failed_files)
+            .add_system(reload_changed_assets.in_base_set(AssetSet::LoadAssets))
+            .add_system(sync_mode_choice);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::prelude::*;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const MUSIC_CONFIG_PATH: &str = "soundtrack.music.ron";
+
+/// What the music is reacting to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum MusicContext {
+    Menu,
+    Exploring,
+    EnemyNearby,
+    LastBall,
+    Results,
+}
+
+#[derive(Deserialize, Clone)]
+struct Track {
+    path: String,
+    #[serde(default = "full")]
+    volume: f32,
+    #[serde(default = "full")]
+    speed: f32,
+}
+
+fn full() -> f32 {
+    1.0
+}
+
+/// Track assignments, loaded from `assets/soundtrack.music.ron`.
+#[derive(Resource, Deserialize, TypeUuid, Clone)]
+#[uuid = "0d3f97be-5a42-4f67-8591-f90e07195eab"]
+#[serde(default)]
+struct MusicConfig {
+    fade_seconds: f32,
+    paused_volume: f32,
+    enemy_radius: f32,
+    results_seconds: f32,
+    tracks: HashMap<MusicContext, Track>,
+}
+
+impl Default for MusicConfig {
+    fn default() -> Self {
+        let track = |speed| Track {
+            path: "sounds/music.ogg".into(),
+            volume: 1.0,
+            speed,
+        };
+        MusicConfig {
+            fade_seconds: 1.5,
+            paused_volume: 0.3,
+            enemy_radius: 12.0,
+            results_seconds: 4.0,
+            tracks: HashMap::from([
+                (MusicContext::Menu, track(0.9)),
+                (MusicContext::Exploring, track(1.0)),
+                (MusicContext::EnemyNearby, track(1.1)),
+                (MusicContext::LastBall, track(1.2)),
+                (MusicContext::Results, track(0.8)),
+            ]),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MusicConfigLoader;
+
+impl AssetLoader for MusicConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config: MusicConfig = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["music.ron"]
+    }
+}
+
+#[derive(Resource)]
+struct MusicConfigHandle(Handle<MusicConfig>);
+
+fn load_music_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(MusicConfigHandle(asset_server.load(MUSIC_CONFIG_PATH)));
+}
+
+/// Uses the track file once it has loaded, and again whenever it changes.
+/// Until then, or if it can't be read, the built-in tracks play.
+fn apply_music_config(
+    mut events: EventReader<AssetEvent<MusicConfig>>,
+    handle: Res<MusicConfigHandle>,
+    configs: Res<Assets<MusicConfig>>,
+    mut config: ResMut<MusicConfig>,
+) {
+    let changed = events.iter().any(|event| match event {
+        AssetEvent::Created { handle: changed } | AssetEvent::Modified { handle: changed } => {
+            *changed == handle.0
+        }
+        AssetEvent::Removed { .. } => false,
+    });
+    if !changed {
+        return;
+    }
+    if let Some(loaded) = configs.get(&handle.0) {
+        *config = loaded.clone();
+    }
+}
+
+/// A looping track and how far it is faded in.
+struct Layer {
+    sink: Handle<AudioSink>,
+    level: f32,
+}
+
+#[derive(Resource, Default)]
+struct MusicPlayer {
+    layers: HashMap<MusicContext, Layer>,
+    /// The last gameplay context, kept while the game is paused.
+    gameplay: Option<MusicContext>,
+    results_until: f64,
+}
+
+fn gameplay_context(
+    config: &MusicConfig,
+    now: f64,
+    player: &MusicPlayer,
+    game_info: &GameInfo,
+    player_position: Option<Vec3>,
+    enemies: &Query<&Transform, With<Enemy>>,
+) -> MusicContext {
+    if now < player.results_until {
+        return MusicContext::Results;
+    }
+    if game_info.total > 0 && game_info.collected == game_info.total - 1 {
+        return MusicContext::LastBall;
+    }
+    let enemy_nearby = player_position.is_some_and(|position| {
+        enemies
+            .iter()
+            .any(|enemy| enemy.translation.distance(position) <= config.enemy_radius)
+    });
+    if enemy_nearby {
+        MusicContext::EnemyNearby
+    } else {
+        MusicContext::Exploring
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_music(
+    mut music: ResMut<MusicPlayer>,
+    mut round_over: EventReader<RoundOver>,
+    config: Res<MusicConfig>,
+    time: Res<Time>,
+    game_state: Res<State<GameState>>,
+    pause: Res<Pause>,
+    game_info: Res<GameInfo>,
+    player: Query<&Transform, With<PrimaryPlayer>>,
+    enemies: Query<&Transform, With<Enemy>>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    settings: Res<AudioSettings>,
+) {
+    let now = time.raw_elapsed_seconds_f64();
+    if round_over.iter().count() > 0 {
+        music.results_until = now + config.results_seconds as f64;
+    }
+
+    let paused = game_state.0 == GameState::Menu && pause.paused && music.gameplay.is_some();
+    let context = match game_state.0 {
+        GameState::Splash => None,
+        GameState::InGame | GameState::NewGame => {
+            let position = player.get_single().ok().map(|player| player.translation);
+            let context = gameplay_context(&config, now, &music, &game_info, position, &enemies);
+            music.gameplay = Some(context);
+            Some(context)
+        }
+        _ if paused => music.gameplay,
+        _ => Some(MusicContext::Menu),
+    };
+
+    if let Some((context, track)) = context.and_then(|c| Some((c, config.tracks.get(&c)?))) {
+        music.layers.entry(context).or_insert_with(|| Layer {
+            sink: audio_sinks.get_handle(
+                audio.play_with_settings(
+                    asset_server.load(track.path.as_str()),
+                    PlaybackSettings::LOOP
+                        .with_volume(0.0)
+                        .with_speed(track.speed),
+                ),
+            ),
+            level: 0.0,
+        });
+    }
+
+    let step = time.raw_delta_seconds() / config.fade_seconds.max(0.01);
+    let duck = if paused { config.paused_volume } else { 1.0 };
+    let bus = settings.volume(AudioBus::Music);
+    for (layer_context, layer) in &mut music.layers {
+        let target = if Some(*layer_context) == context {
+            1.0
+        } else {
+            0.0
+        };
+        layer.level = if layer.level < target {
+            (layer.level + step).min(target)
+        } else {
+            (layer.level - step).max(target)
+        };
+        let Some(sink) = audio_sinks.get(&layer.sink) else {
+            continue;
+        };
+        let track_volume = config
+            .tracks
+            .get(layer_context)
+            .map_or(1.0, |track| track.volume);
+        sink.set_volume(layer.level * track_volume * bus * duck);
+        if layer.level > 0.0 && sink.is_paused() {
+            sink.play();
+        } else if layer.level == 0.0 && !sink.is_paused() {
+            sink.pause();
+        }
+    }
+}
+
+pub struct MusicPlugin;
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<MusicConfig>()
+            .init_asset_loader::<MusicConfigLoader>()
+            .init_resource::<MusicConfig>()
+            .init_resource::<MusicPlayer>()
+            .add_startup_system(load_music_config)
+            .add_system(apply_music_config)
+            .add_system(update_music.after(apply_music_config));
+    }
+}
@@ -1,24 +1,29 @@
+use crate::console::console::{console_closed, ConsoleAppExt};
+use crate::game::spectator::not_spectating;
+use crate::player::player::Controls;
 use crate::prelude::*;
 use crate::Player;
-use crate::game::sound::{play_hit_sound, play_score_sound, play_death_sound};
-use bevy::input::mouse::MouseButton;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
+#[allow(clippy::too_many_arguments)]
 pub fn collision_system(
     mut commands: Commands,
     rapier_context: Res<RapierContext>,
     enemy_query: Query<Entity, With<Enemy>>,
-    player_query: Query<Entity, With<Player>>,
+    mut player_query: Query<(Entity, &mut Health, &mut Jump, &mut Score), With<Player>>,
     cube_query: Query<Entity, With<Cube>>,
-    blue_ball_query: Query<Entity, With<BlueBall>>,
-    mut jump: ResMut<Jump>,
+    blue_ball_query: Query<(Entity, &Transform), With<BlueBall>>,
     mut game_state: ResMut<GameInfo>,
-    asset_server: Res<AssetServer>, 
-    audio: Res<Audio>, 
-    volume: Res<Volume>,
+    mut collected: EventWriter<BallCollected>,
+    mut notifications: EventWriter<Notification>,
+    cheats: Res<Cheats>,
+    power_ups: Res<ActivePowerUps>,
+    mut sounds: EventWriter<SpatialSound>,
+    role: Res<NetRole>,
+    mut taken: ResMut<TakenBalls>,
 ) {
-    for entity in player_query.iter() {
+    for (entity, mut health, mut jump, mut score) in player_query.iter_mut() {
         for contact_pair in rapier_context.contacts_with(entity) {
             let other_collider = if contact_pair.collider1() == entity {
                 contact_pair.collider2()
@@ -31,100 +36,317 @@ pub fn collision_system(
                     ava = true;
                 }
             }
-            if ava {
-                jump.avalible = true;
-            } else {
-                jump.avalible = false;
+            jump.avalible = ava;
+            // Balls on a client mirror the server, which decides who hits them.
+            if *role == NetRole::Client {
+                continue;
             }
 
             for enemy in enemy_query.iter() {
-                if other_collider == enemy {
-                    game_state.is_won = true;
-                    play_death_sound(&asset_server, &audio, &volume);
-                    game_state.collected = 0;
+                if other_collider == enemy
+                    && health.current > 0.0
+                    && !cheats.god
+                    && power_ups.get(PowerUpKind::Invulnerability).is_none()
+                {
+                    health.current = 0.0;
+                    notifications.send(Notification("Hit by a red ball".into()));
                 }
             }
-            for ball in blue_ball_query.iter() {
-                if other_collider == ball {
+            for (ball, ball_transform) in blue_ball_query.iter() {
+                if other_collider == ball && taken.0.insert(ball) {
                     game_state.collected += 1;
-                    play_score_sound(&asset_server, &audio, &volume);
+                    score.0 += 1;
+                    sounds.send(SpatialSound {
+                        effect: SoundEffect::Score,
+                        position: ball_transform.translation,
+                        volume: 1.0,
+                    });
                     commands.entity(ball).despawn();
-                    if game_state.collected == 5 {
-                        game_state.is_won = true;
-                        game_state.collected = 0;
-                        game_state.wins += 1;
-                    }
+                    collected.send(BallCollected);
+                    notifications.send(Notification(format!(
+                        "Blue ball {}/{}",
+                        game_state.collected, game_state.total
+                    )));
                 }
             }
         }
     }
 }
 
+/// Ends a game on this machine once every blue ball is taken. Whoever
+/// collected the most wins, with ties going to the players over the bots. In
+/// multiplayer the server decides instead.
+#[allow(clippy::too_many_arguments)]
+fn end_round(
+    mut game_state: ResMut<GameInfo>,
+    players: Query<(&Score, &LocalPlayer)>,
+    bots: Query<(&Score, &Bot)>,
+    local_players: Res<LocalPlayers>,
+    timer: Query<&FPSTimer>,
+    mut round_over: EventWriter<RoundOver>,
+    mut notifications: EventWriter<Notification>,
+    role: Res<NetRole>,
+    rules: Res<RoundRules>,
+) {
+    // A mode without blue balls never ends this way.
+    if game_state.total == 0
+        || game_state.collected < game_state.total
+        || *role != NetRole::Offline
+        || !rules.end_on_balls
+    {
+        return;
+    }
+    game_state.is_won = true;
+    game_state.collected = 0;
+    let player = players.iter().max_by_key(|(score, _)| score.0);
+    let winning_bot = bots
+        .iter()
+        .max_by_key(|(score, _)| score.0)
+        .filter(|(bot_score, _)| player.is_none_or(|(score, _)| bot_score.0 > score.0));
+    let won = winning_bot.is_none();
+    let message = match (winning_bot, player) {
+        (Some((score, bot)), _) => format!("Bot {} wins with {} blue balls", bot.index + 1, score.0),
+        (None, Some((score, local))) if local_players.0 > 1 => {
+            format!("Player {} wins with {} blue balls", local.index + 1, score.0)
+        }
+        _ => "All blue balls collected!".into(),
+    };
+    if won {
+        game_state.wins += 1;
+    }
+    let elapsed = timer.iter().map(|timer| timer.elapsed).last().unwrap_or_default();
+    info!("Round over after {elapsed:.1}s: {message}");
+    round_over.send(RoundOver { won });
+    notifications.send(Notification(message));
+}
+
+fn forget_taken_balls(mut taken: ResMut<TakenBalls>) {
+    taken.0.clear();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn death_system(
+    mut player_query: Query<(&mut Health, &mut Transform, &mut Velocity, &LocalPlayer), With<Player>>,
+    mut game_state: ResMut<GameInfo>,
+    mut round_over: EventWriter<RoundOver>,
+    mut died: EventWriter<PlayerDied>,
+    mut sounds: EventWriter<SoundEffect>,
+    role: Res<NetRole>,
+    local_players: Res<LocalPlayers>,
+    rules: Res<RoundRules>,
+    spawn: Res<SpawnPoint>,
+) {
+    for (mut health, mut transform, mut velocity, local) in player_query.iter_mut() {
+        if health.current <= 0.0 {
+            *health = Health::default();
+            sounds.send(SoundEffect::Death);
+            died.send(PlayerDied { index: local.index });
+            if *role != NetRole::Offline || local_players.0 > 1 || !rules.end_on_death {
+                // The round carries on, so only this player starts over.
+                transform.translation = spawn.local(local.index);
+                *velocity = Velocity::zero();
+                continue;
+            }
+            game_state.is_won = true;
+            game_state.collected = 0;
+            round_over.send(RoundOver { won: false });
+        }
+    }
+}
+
+const MAX_CHARGE_SECONDS: f32 = 1.0;
+const MIN_HIT_IMPULSE: f32 = 60.0;
+const MAX_HIT_IMPULSE: f32 = 250.0;
+const MIN_HIT_RANGE: f32 = 7.0;
+const MAX_HIT_RANGE: f32 = 14.0;
+/// A shockwave reaches bodies within this angle of the view direction, given
+/// as a cosine. -1.0 would make it a full sphere.
+const SHOCKWAVE_CONE_COS: f32 = 0.5;
+const SHOCKWAVE_IMPULSE_SCALE: f32 = 0.6;
+
+/// Charges a hit while `held` is set, to a shockwave if it holds true, and
+/// returns the charge once it is let go.
+pub fn charge_hit(
+    hit: &mut Hit,
+    timer: &mut HitTimer,
+    held: Option<bool>,
+    delta_seconds: f32,
+) -> Option<f32> {
+    if !hit.hit {
+        return None;
+    }
+    if let Some(shockwave) = held {
+        hit.shockwave = shockwave;
+        hit.charge = (hit.charge + delta_seconds / MAX_CHARGE_SECONDS).min(1.0);
+        return None;
+    }
+    if hit.charge == 0.0 {
+        return None;
+    }
+    let charge = std::mem::take(&mut hit.charge);
+    hit.hit = false;
+    *timer = HitTimer(Timer::from_seconds(0.5 + 0.5 * charge, TimerMode::Once));
+    Some(charge)
+}
+
+/// Charges a hit while the left (ray) or right (shockwave) mouse button, or
+/// the right or left trigger, is held and releases it when it is let go.
+#[allow(clippy::type_complexity)]
 pub fn ray_cast(
+    time: Res<Time>,
+    controls: Controls,
+    mut player_query: Query<
+        (Entity, &Transform, &LocalPlayer, &Aim, &mut Hit, &mut HitTimer),
+        With<Player>,
+    >,
+    mut fired: EventWriter<HitFired>,
+) {
+    for (player_entity, player_transform, local, aim, mut hit, mut timer) in player_query.iter_mut() {
+        let held = controls.hit(local.device);
+        let Some(charge) = charge_hit(&mut hit, &mut timer, held, time.delta_seconds()) else {
+            continue;
+        };
+        fired.send(HitFired {
+            shooter: player_entity,
+            origin: player_transform.translation,
+            direction: aim.0.unwrap_or_else(|| player_transform.forward()),
+            charge,
+            shockwave: hit.shockwave,
+        });
+    }
+}
+
+/// Pushes the bodies in front of each released hit.
+fn apply_hits(
     mut commands: Commands,
-    hit: ResMut<Hit>,
-    player_query: Query<(Entity, &Transform), With<Player>>,
+    mut fired: EventReader<HitFired>,
+    bodies: Query<(Entity, &Transform, &RigidBody)>,
     rapier_context: Res<RapierContext>,
-    mouse_input: Res<Input<MouseButton>>,
-    asset_server: Res<AssetServer>, 
-    audio: Res<Audio>, 
-    volume: Res<Volume>,
+    mut sounds: EventWriter<SpatialSound>,
 ) {
-    if mouse_input.pressed(MouseButton::Left) && hit.hit {
-        for (player_entity, player_transform) in player_query.iter() {
-            let player_forward = player_transform.forward();
-            let ray_origin = player_transform.translation;
-            let ray_direction = player_forward;
-            let max_toi = 7.0;
-            let hit = rapier_context.cast_ray(
-                ray_origin,
-                ray_direction,
-                max_toi,
-                true,
-                QueryFilter::exclude_fixed()
-                    .exclude_rigid_body(player_entity)
-                    .exclude_collider(player_entity),
-            );
-            if let Some((entity, _toi)) = hit {
-                let pushback_direction = ray_direction;
-                play_hit_sound(&asset_server, &audio, &volume);
-                commands.insert_resource(HitTimer(Timer::from_seconds(0.5, TimerMode::Once)));
-                commands.entity(entity).insert(Velocity {
-                    linvel: pushback_direction * 30.0,
-                    angvel: Vec3::ZERO,
+    for hit in fired.iter() {
+        let charge = hit.charge;
+        let impulse = MIN_HIT_IMPULSE + (MAX_HIT_IMPULSE - MIN_HIT_IMPULSE) * charge;
+        let range = MIN_HIT_RANGE + (MAX_HIT_RANGE - MIN_HIT_RANGE) * charge;
+        let ray_origin = hit.origin;
+        let ray_direction = hit.direction;
+        if hit.shockwave {
+            for (entity, transform, body) in &bodies {
+                let offset = transform.translation - ray_origin;
+                let distance = offset.length();
+                if entity == hit.shooter
+                    || *body != RigidBody::Dynamic
+                    || distance > range
+                    || offset.normalize_or_zero().dot(ray_direction) < SHOCKWAVE_CONE_COS
+                {
+                    continue;
+                }
+                let falloff = 1.0 - distance / range;
+                commands.entity(entity).insert(ExternalImpulse {
+                    impulse: offset.normalize_or_zero()
+                        * impulse
+                        * SHOCKWAVE_IMPULSE_SCALE
+                        * falloff,
+                    torque_impulse: Vec3::ZERO,
                 });
             }
+            sounds.send(SpatialSound {
+                effect: SoundEffect::Hit,
+                position: ray_origin + ray_direction,
+                volume: 0.5 + 0.5 * charge,
+            });
+            continue;
+        }
+        let target = rapier_context.cast_ray(
+            ray_origin,
+            ray_direction,
+            range,
+            true,
+            QueryFilter::exclude_fixed()
+                .exclude_rigid_body(hit.shooter)
+                .exclude_collider(hit.shooter),
+        );
+        if let Some((entity, toi)) = target {
+            sounds.send(SpatialSound {
+                effect: SoundEffect::Hit,
+                position: ray_origin + ray_direction * toi,
+                volume: 0.5 + 0.5 * charge,
+            });
+            commands.entity(entity).insert(ExternalImpulse {
+                impulse: ray_direction * impulse,
+                torque_impulse: Vec3::ZERO,
+            });
         }
     }
-    
 }
 
-fn hit_countdown(
-    mut hit: ResMut<Hit>,
-    time: Res<Time>,
-    mut timer: ResMut<HitTimer>,
+/// Red balls make a bounce sound when they collide, louder the faster they go.
+fn bounce_sounds(
+    mut collisions: EventReader<CollisionEvent>,
+    enemies: Query<(&Transform, &Velocity), With<Enemy>>,
+    mut sounds: EventWriter<SpatialSound>,
 ) {
-    if timer.0.tick(time.delta()).finished() {
-        hit.hit = true;
-    } else {
-        hit.hit = false;
+    for collision in collisions.iter() {
+        let CollisionEvent::Started(a, b, _) = collision else {
+            continue;
+        };
+        let Ok((transform, velocity)) = enemies.get(*a).or_else(|_| enemies.get(*b)) else {
+            continue;
+        };
+        let speed = velocity.linvel.length();
+        if speed < 2.0 {
+            continue;
+        }
+        sounds.send(SpatialSound {
+            effect: SoundEffect::Bounce,
+            position: transform.translation,
+            volume: (speed / 20.0).min(1.0),
+        });
+    }
+}
+
+fn hit_countdown(mut players: Query<(&mut Hit, &mut HitTimer)>, time: Res<Time>) {
+    for (mut hit, mut timer) in &mut players {
+        hit.hit = timer.0.tick(time.delta()).finished();
     }
 }
 
+fn god_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let mut cheats = world.resource_mut::<Cheats>();
+    cheats.god = !cheats.god;
+    Ok(format!("god mode {}", if cheats.god { "on" } else { "off" }))
+}
+
+fn physics_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let enabled = match args {
+        ["debug", "on"] => true,
+        ["debug", "off"] => false,
+        _ => return Err(String::new()),
+    };
+    world
+        .get_resource_mut::<DebugRenderContext>()
+        .ok_or("physics debug rendering is unavailable")?
+        .enabled = enabled;
+    Ok(format!("physics debug {}", args[1]))
+}
+
 pub struct PhysicsPlugin;
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(collision_system.in_set(OnUpdate(GameState::InGame)))
+        app.init_resource::<TakenBalls>()
+        .add_system(forget_taken_balls.in_base_set(CoreSet::First))
+        .add_system(collision_system.in_set(OnUpdate(GameState::InGame)))
+        .add_system(death_system.in_set(OnUpdate(GameState::InGame)).after(collision_system))
+        .add_system(end_round.in_set(OnUpdate(GameState::InGame)).after(collision_system))
         .add_system(hit_countdown.in_set(OnUpdate(GameState::InGame)))
-        .init_resource::<HitTimer>()
-        .insert_resource(Hit {
-            hit: false,
-        })
-            .insert_resource(Jump {
-                jumping: false,
-                elapsed: 0.0,
-                avalible: false,
-            })
-            .add_system(ray_cast.in_set(OnUpdate(GameState::InGame)));
+        .add_system(bounce_sounds.in_set(OnUpdate(GameState::InGame)))
+        .init_resource::<Cheats>()
+            .init_resource::<RoundRules>()
+            .add_event::<HitFired>()
+            .add_event::<PlayerDied>()
+            .add_system(ray_cast.run_if(console_closed).run_if(not_spectating).in_set(OnUpdate(GameState::InGame)))
+            .add_system(apply_hits.after(ray_cast).in_set(OnUpdate(GameState::InGame)))
+            .add_console_command("god", "god", &[], god_command)
+            .add_console_command("physics", "physics debug <on|off>", &["debug"], physics_command);
     }
 }
@@ -0,0 +1,479 @@
+use crate::console::console::ConsoleAppExt;
+use crate::game::data::load_data_file;
+use crate::game::levels::CurrentLevel;
+use crate::game::load::{surface_spots, voxel_position};
+use crate::game::mods::Mods;
+use crate::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const POWER_UP_CONFIG_PATH: &str = "powerups.ron";
+const MAGNET_SPEED: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+enum Stacking {
+    Refresh,
+    Extend,
+    Stack,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct PowerUpDef {
+    duration: f32,
+    stacking: Stacking,
+    max_stacks: u32,
+    strength: f32,
+    /// Relative chance of this power-up being placed.
+    weight: u32,
+    color: (f32, f32, f32),
+    icon: String,
+}
+
+impl Default for PowerUpDef {
+    fn default() -> Self {
+        PowerUpDef {
+            duration: 10.0,
+            stacking: Stacking::Refresh,
+            max_stacks: 1,
+            strength: 1.0,
+            weight: 1,
+            color: (1.0, 1.0, 1.0),
+            icon: "?".into(),
+        }
+    }
+}
+
+/// Power-up definitions, loaded from `assets/powerups.ron`.
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+struct PowerUpConfig {
+    count: usize,
+    powerups: BTreeMap<PowerUpKind, PowerUpDef>,
+}
+
+impl Default for PowerUpConfig {
+    fn default() -> Self {
+        let def = |duration, strength, icon: &str| PowerUpDef {
+            duration,
+            strength,
+            icon: icon.into(),
+            ..default()
+        };
+        PowerUpConfig {
+            count: 6,
+            powerups: BTreeMap::from([
+                (PowerUpKind::SpeedBoost, def(8.0, 1.6, "S")),
+                (PowerUpKind::DoubleJump, def(15.0, 1.0, "J")),
+                (PowerUpKind::Invulnerability, def(5.0, 1.0, "I")),
+                (PowerUpKind::Magnet, def(10.0, 10.0, "M")),
+                (PowerUpKind::Radar, def(6.0, 200.0, "R")),
+            ]),
+        }
+    }
+}
+
+impl PowerUpConfig {
+    fn color(&self, kind: PowerUpKind) -> Color {
+        let (r, g, b) = self.powerups[&kind].color;
+        Color::rgb(r, g, b)
+    }
+}
+
+fn label(kind: PowerUpKind) -> &'static str {
+    match kind {
+        PowerUpKind::SpeedBoost => "Speed boost",
+        PowerUpKind::DoubleJump => "Double jump",
+        PowerUpKind::Invulnerability => "Invulnerable",
+        PowerUpKind::Magnet => "Magnet",
+        PowerUpKind::Radar => "Radar",
+    }
+}
+
+#[derive(Component)]
+struct PowerUpPickup(PowerUpKind);
+
+#[derive(Component)]
+struct PowerUpBar;
+
+#[derive(Component)]
+struct PowerUpCountdown(PowerUpKind);
+
+fn load_power_up_config(mut commands: Commands, mods: Res<Mods>) {
+    commands.insert_resource(load_data_file::<PowerUpConfig>(&mods, POWER_UP_CONFIG_PATH));
+}
+
+/// Places pickups on free surface spots whenever a new map is generated, or
+/// where a campaign level puts them.
+#[allow(clippy::too_many_arguments)]
+fn spawn_power_ups(
+    mut commands: Commands,
+    voxel_map: Res<VoxelMap>,
+    world_seed: Res<WorldSeed>,
+    config: Res<PowerUpConfig>,
+    current_level: Res<CurrentLevel>,
+    balls: Query<&Transform, With<BlueBall>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !voxel_map.is_changed() || voxel_map.0.is_empty() {
+        return;
+    }
+    // A separate stream from the one placing balls, so both stay stable.
+    let mut rng = StdRng::seed_from_u64(world_seed.current as u64 ^ 0x5eed_b00f);
+    let placed: Vec<(PowerUpKind, Transform)> = match &current_level.level {
+        Some(level) => level
+            .power_ups
+            .iter()
+            .map(|power_up| {
+                let position = voxel_position(&voxel_map.0, power_up.at);
+                (power_up.kind, pickup_transform(position, &mut rng))
+            })
+            .collect(),
+        None => random_power_ups(&voxel_map.0, &config, &balls, &mut rng),
+    };
+
+    let mesh = meshes.add(shape::Cube { size: 0.8 }.into());
+    for (kind, transform) in placed {
+        commands.spawn((
+            PbrBundle {
+                mesh: mesh.clone(),
+                material: materials.add(StandardMaterial {
+                    base_color: config.color(kind),
+                    emissive: config.color(kind) * 0.5,
+                    ..default()
+                }),
+                transform,
+                ..default()
+            },
+            RigidBody::Fixed,
+            Collider::cuboid(0.6, 0.6, 0.6),
+            Sensor,
+            PowerUpPickup(kind),
+            Reboot,
+        ));
+    }
+}
+
+fn pickup_transform(position: Vec3, rng: &mut StdRng) -> Transform {
+    Transform::from_translation(position).with_rotation(Quat::from_rotation_y(rng.gen_range(0.0..1.5)))
+}
+
+/// Picks kinds by their weights and spots away from the blue balls.
+fn random_power_ups(
+    voxel_data: &[Vec<Vec<Voxel>>],
+    config: &PowerUpConfig,
+    balls: &Query<&Transform, With<BlueBall>>,
+    rng: &mut StdRng,
+) -> Vec<(PowerUpKind, Transform)> {
+    let kinds: Vec<(PowerUpKind, u32)> = config
+        .powerups
+        .iter()
+        .map(|(kind, def)| (*kind, def.weight))
+        .filter(|(_, weight)| *weight > 0)
+        .collect();
+    if kinds.is_empty() {
+        return Vec::new();
+    }
+    let mut spots = surface_spots(voxel_data);
+    spots.shuffle(rng);
+    spots
+        .into_iter()
+        .map(|spot| voxel_position(voxel_data, spot))
+        .filter(|position| {
+            balls
+                .iter()
+                .all(|ball| ball.translation.distance(*position) > 2.0)
+        })
+        .take(config.count)
+        .map_while(|position| {
+            let (kind, _) = kinds.choose_weighted(&mut *rng, |(_, weight)| *weight).ok()?;
+            Some((*kind, pickup_transform(position, rng)))
+        })
+        .collect()
+}
+
+fn spin_pickups(time: Res<Time>, mut pickups: Query<&mut Transform, With<PowerUpPickup>>) {
+    for mut transform in &mut pickups {
+        transform.rotate_y(time.delta_seconds() * 1.5);
+    }
+}
+
+fn apply_power_up(power_ups: &mut ActivePowerUps, kind: PowerUpKind, def: &PowerUpDef) {
+    match power_ups.0.iter_mut().find(|active| active.kind == kind) {
+        Some(active) => match def.stacking {
+            Stacking::Refresh => active.remaining = def.duration,
+            Stacking::Extend => {
+                active.remaining = (active.remaining + def.duration)
+                    .min(def.duration * def.max_stacks.max(1) as f32);
+            }
+            Stacking::Stack => {
+                active.stacks = (active.stacks + 1).min(def.max_stacks.max(1));
+                active.remaining = def.duration;
+            }
+        },
+        None => power_ups.0.push(ActivePowerUp {
+            kind,
+            remaining: def.duration,
+            stacks: 1,
+            strength: def.strength,
+        }),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collect_power_ups(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    config: Res<PowerUpConfig>,
+    mut power_ups: ResMut<ActivePowerUps>,
+    players: Query<Entity, With<Player>>,
+    pickups: Query<(Entity, &PowerUpPickup, &Transform)>,
+    balls: Query<(), With<BlueBall>>,
+    mut notifications: EventWriter<Notification>,
+    mut sounds: EventWriter<SpatialSound>,
+) {
+    // Power-ups are shared, so any local player can pick them up.
+    for (pickup, PowerUpPickup(kind), transform) in &pickups {
+        if !players
+            .iter()
+            .any(|player| rapier_context.intersection_pair(player, pickup) == Some(true))
+        {
+            continue;
+        }
+        commands.entity(pickup).despawn_recursive();
+        sounds.send(SpatialSound {
+            effect: SoundEffect::Score,
+            position: transform.translation,
+            volume: 0.6,
+        });
+        let Some(def) = config.powerups.get(kind) else {
+            continue;
+        };
+        apply_power_up(&mut power_ups, *kind, def);
+        let message = match kind {
+            PowerUpKind::Radar => format!("Radar: {} blue balls remaining", balls.iter().count()),
+            _ => format!("{}!", label(*kind)),
+        };
+        notifications.send(Notification(message));
+    }
+}
+
+fn grant_power_ups(
+    mut grants: EventReader<GrantPowerUp>,
+    config: Res<PowerUpConfig>,
+    mut power_ups: ResMut<ActivePowerUps>,
+    mut notifications: EventWriter<Notification>,
+) {
+    for GrantPowerUp(kind) in grants.iter() {
+        if let Some(def) = config.powerups.get(kind) {
+            apply_power_up(&mut power_ups, *kind, def);
+            notifications.send(Notification(format!("{}!", label(*kind))));
+        }
+    }
+}
+
+fn tick_power_ups(time: Res<Time>, mut power_ups: ResMut<ActivePowerUps>) {
+    if power_ups.0.is_empty() {
+        return;
+    }
+    for power_up in &mut power_ups.0 {
+        power_up.remaining -= time.delta_seconds();
+    }
+    power_ups.0.retain(|power_up| power_up.remaining > 0.0);
+}
+
+fn clear_power_ups(mut power_ups: ResMut<ActivePowerUps>) {
+    power_ups.0.clear();
+}
+
+fn clear_power_ups_on_death(
+    mut round_over: EventReader<RoundOver>,
+    mut power_ups: ResMut<ActivePowerUps>,
+) {
+    if round_over.iter().any(|round| !round.won) {
+        power_ups.0.clear();
+    }
+}
+
+/// Pulls blue balls within the magnet radius towards the nearest player.
+fn magnet_system(
+    time: Res<Time>,
+    power_ups: Res<ActivePowerUps>,
+    players: Query<&Transform, With<Player>>,
+    mut balls: Query<&mut Transform, (With<BlueBall>, Without<Player>)>,
+) {
+    let Some(radius) = power_ups.strength(PowerUpKind::Magnet) else {
+        return;
+    };
+    for mut ball in &mut balls {
+        let nearest = players.iter().map(|player| player.translation).min_by(|a, b| {
+            a.distance_squared(ball.translation)
+                .total_cmp(&b.distance_squared(ball.translation))
+        });
+        let Some(player) = nearest else {
+            return;
+        };
+        let offset = player - ball.translation;
+        let distance = offset.length();
+        if distance <= radius && distance > 0.1 {
+            ball.translation +=
+                offset / distance * (MAGNET_SPEED * time.delta_seconds()).min(distance);
+        }
+    }
+}
+
+fn power_up_hud_setup(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(15.0),
+                    bottom: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        },
+        Hud,
+        HudWidget::PowerUps,
+        PowerUpBar,
+    ));
+}
+
+/// Keeps one icon with a countdown per active power-up.
+fn update_power_up_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<PowerUpConfig>,
+    power_ups: Res<ActivePowerUps>,
+    bar: Query<Entity, With<PowerUpBar>>,
+    mut countdowns: Query<(&PowerUpCountdown, &mut Text)>,
+) {
+    let Ok(bar) = bar.get_single() else {
+        return;
+    };
+    let shown: Vec<PowerUpKind> = countdowns
+        .iter()
+        .map(|(countdown, _)| countdown.0)
+        .collect();
+    let active: Vec<PowerUpKind> = power_ups.0.iter().map(|power_up| power_up.kind).collect();
+    if shown.len() != active.len() || !active.iter().all(|kind| shown.contains(kind)) {
+        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+        commands.entity(bar).despawn_descendants();
+        commands.entity(bar).with_children(|parent| {
+            for kind in &active {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::right(Val::Px(10.0)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(40.0), Val::Px(40.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                background_color: config.color(*kind).into(),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    config.powerups[kind].icon.clone(),
+                                    TextStyle {
+                                        font: font.clone(),
+                                        font_size: 30.0,
+                                        color: Color::BLACK,
+                                    },
+                                ));
+                            });
+                        parent.spawn((
+                            TextBundle::from_section(
+                                "",
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 20.0,
+                                    color: Color::BLACK,
+                                },
+                            ),
+                            PowerUpCountdown(*kind),
+                        ));
+                    });
+            }
+        });
+        return;
+    }
+    for (countdown, mut text) in &mut countdowns {
+        let Some(power_up) = power_ups.get(countdown.0) else {
+            continue;
+        };
+        let value = match power_up.stacks {
+            1 => format!("{:.0}", power_up.remaining.ceil()),
+            stacks => format!("{:.0} x{}", power_up.remaining.ceil(), stacks),
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+fn power_up_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let kind = match *args.first().ok_or_else(String::new)? {
+        "speed" => PowerUpKind::SpeedBoost,
+        "jump" => PowerUpKind::DoubleJump,
+        "invulnerable" => PowerUpKind::Invulnerability,
+        "magnet" => PowerUpKind::Magnet,
+        "radar" => PowerUpKind::Radar,
+        other => return Err(format!("unknown power-up `{other}`")),
+    };
+    let def = world
+        .resource::<PowerUpConfig>()
+        .powerups
+        .get(&kind)
+        .cloned()
+        .ok_or_else(|| format!("{} is not defined", label(kind)))?;
+    apply_power_up(&mut world.resource_mut::<ActivePowerUps>(), kind, &def);
+    Ok(format!("{} for {}s", label(kind), def.duration))
+}
+
+pub struct PowerUpPlugin;
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActivePowerUps>()
+            .add_event::<GrantPowerUp>()
+            .add_startup_system(load_power_up_config)
+            .add_system(spawn_power_ups)
+            .add_system(clear_power_ups.in_schedule(OnEnter(GameState::NewGame)))
+            .add_system(power_up_hud_setup.in_schedule(OnEnter(GameState::InGame)))
+            .add_systems(
+                (
+                    spin_pickups,
+                    collect_power_ups,
+                    grant_power_ups,
+                    tick_power_ups,
+                    clear_power_ups_on_death,
+                    magnet_system,
+                    update_power_up_hud,
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_console_command(
+                "powerup",
+                "powerup <speed|jump|invulnerable|magnet|radar>",
+                &["speed", "jump", "invulnerable", "magnet", "radar"],
+                power_up_command,
+            );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::cli::LaunchOptions;
+use crate::game::levels::CurrentLevel;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const SAVE_DIR: &str = "saves";
+const QUICKSAVE_FILE: &str = "quicksave.ron";
+
+/// A game in progress, written to `saves/quicksave.ron` whenever the game is
+/// paused.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SaveGame {
+    pub seed: u32,
+    pub wins: i32,
+    pub mode: GameMode,
+    pub difficulty: Difficulty,
+}
+
+impl SaveGame {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        ron::from_str(&contents).map_err(|err| err.to_string())
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())?;
+        fs::create_dir_all(SAVE_DIR).map_err(|err| err.to_string())?;
+        fs::write(path, contents).map_err(|err| err.to_string())
+    }
+}
+
+fn quicksave(
+    world_seed: Res<WorldSeed>,
+    game_info: Res<GameInfo>,
+    mode: Res<GameMode>,
+    difficulty: Res<Difficulty>,
+    current_level: Res<CurrentLevel>,
+) {
+    // The seed doesn't bring back a level's map.
+    if current_level.level.is_some() {
+        return;
+    }
+    let save = SaveGame {
+        seed: world_seed.current,
+        wins: game_info.wins,
+        mode: *mode,
+        difficulty: *difficulty,
+    };
+    if let Err(err) = save.save(&Path::new(SAVE_DIR).join(QUICKSAVE_FILE)) {
+        warn!("Failed to write quicksave: {}", err);
+    }
+}
+
+/// Applies the save given with `--load`. The save's difficulty is treated
+/// like a launch option so it is not written to the config.
+fn load_launch_save(
+    mut options: ResMut<LaunchOptions>,
+    mut world_seed: ResMut<WorldSeed>,
+    mut game_info: ResMut<GameInfo>,
+    mut mode: ResMut<GameMode>,
+    mut difficulty: ResMut<Difficulty>,
+) {
+    let Some(path) = options.load.clone() else {
+        return;
+    };
+    match SaveGame::load(&path) {
+        Ok(save) => {
+            world_seed.next = Some(save.seed);
+            game_info.wins = save.wins;
+            *mode = save.mode;
+            *difficulty = save.difficulty;
+            options.difficulty = Some(save.difficulty);
+        }
+        Err(err) => error!("Failed to load {}: {}", path.display(), err),
+    }
+}
+
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(load_launch_save)
+            .add_system(quicksave.in_schedule(OnExit(GameState::InGame)));
+    }
+}
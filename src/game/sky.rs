@@ -0,0 +1,386 @@
+use crate::console::console::{parse_arg, ConsoleAppExt};
+use crate::game::data::load_data_file;
+use crate::game::levels::CurrentLevel;
+use crate::game::mods::Mods;
+use crate::game::spectator::SpectatorCamera;
+use crate::prelude::*;
+use bevy::pbr::{NotShadowCaster, NotShadowReceiver};
+use bevy::render::mesh::VertexAttributeValues;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+const DAY_NIGHT_CONFIG_PATH: &str = "daynight.ron";
+const SKY_RADIUS: f32 = 400.0;
+const CLOUD_COUNT: usize = 24;
+const CLOUD_SPREAD: f32 = 300.0;
+const CLOUD_DRIFT: f32 = 2.0;
+const SUN_ILLUMINANCE: f32 = 3200.0;
+const MOON_ILLUMINANCE: f32 = 150.0;
+
+/// How a game mode's day plays out.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+struct DayCycle {
+    /// Real seconds for a full day. 0 keeps the time of day fixed.
+    cycle_seconds: f32,
+    /// Hour of the day, from 0 to 24, that a new game starts at.
+    start_hour: f32,
+}
+
+impl Default for DayCycle {
+    fn default() -> Self {
+        DayCycle {
+            cycle_seconds: 300.0,
+            start_hour: 10.0,
+        }
+    }
+}
+
+/// Day length and starting time per game mode, loaded from
+/// `assets/daynight.ron`.
+#[derive(Resource, Deserialize)]
+#[serde(default)]
+struct DayNightConfig {
+    modes: HashMap<GameMode, DayCycle>,
+}
+
+impl Default for DayNightConfig {
+    fn default() -> Self {
+        DayNightConfig {
+            modes: HashMap::from([
+                (GameMode::Classic, DayCycle::default()),
+                (
+                    GameMode::Zen,
+                    DayCycle {
+                        cycle_seconds: 900.0,
+                        start_hour: 17.0,
+                    },
+                ),
+            ]),
+        }
+    }
+}
+
+impl DayNightConfig {
+    fn cycle(&self, mode: GameMode) -> DayCycle {
+        self.modes.get(&mode).copied().unwrap_or_default()
+    }
+}
+
+/// The in-game hour, from 0 to 24.
+#[derive(Resource)]
+pub struct TimeOfDay(pub f32);
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        TimeOfDay(DayCycle::default().start_hour)
+    }
+}
+
+impl TimeOfDay {
+    /// Sine of the sun's height above the horizon, negative at night.
+    fn sun_elevation(&self) -> f32 {
+        ((self.0 - 6.0) / 12.0 * PI).sin()
+    }
+
+    /// Direction from the ground towards the sun, rising in the east (+X).
+    fn sun_direction(&self) -> Vec3 {
+        let angle = (self.0 - 6.0) / 12.0 * PI;
+        Vec3::new(angle.cos(), angle.sin(), 0.3).normalize()
+    }
+}
+
+/// The directional light that follows the time of day.
+#[derive(Component)]
+pub struct Sun;
+
+#[derive(Component)]
+struct SkyDome;
+
+#[derive(Component)]
+struct Cloud;
+
+/// Sky, cloud and light colours for a point in the day.
+struct SkyColors {
+    zenith: Vec3,
+    horizon: Vec3,
+    cloud: Vec4,
+    sun: Vec3,
+    /// How much of the daytime light is left, from 0 at night to 1.
+    daylight: f32,
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn sky_colors(elevation: f32) -> SkyColors {
+    let night = (Vec3::new(0.01, 0.01, 0.05), Vec3::new(0.05, 0.05, 0.12));
+    let dusk = (Vec3::new(0.25, 0.3, 0.55), Vec3::new(0.95, 0.55, 0.3));
+    let day = (Vec3::new(0.25, 0.5, 0.95), Vec3::new(0.7, 0.85, 1.0));
+    let night_cloud = Vec4::new(0.15, 0.15, 0.2, 0.6);
+    let dusk_cloud = Vec4::new(1.0, 0.7, 0.55, 0.8);
+    let day_cloud = Vec4::new(1.0, 1.0, 1.0, 0.85);
+    let warm_sun = Vec3::new(1.0, 0.6, 0.35);
+    let (zenith, horizon, cloud) = if elevation < 0.0 {
+        let t = smoothstep(-0.2, 0.0, elevation);
+        (
+            night.0.lerp(dusk.0, t),
+            night.1.lerp(dusk.1, t),
+            night_cloud.lerp(dusk_cloud, t),
+        )
+    } else {
+        let t = smoothstep(0.0, 0.4, elevation);
+        (
+            dusk.0.lerp(day.0, t),
+            dusk.1.lerp(day.1, t),
+            dusk_cloud.lerp(day_cloud, t),
+        )
+    };
+    SkyColors {
+        zenith,
+        horizon,
+        cloud,
+        sun: warm_sun.lerp(Vec3::ONE, smoothstep(0.0, 0.4, elevation)),
+        daylight: smoothstep(-0.1, 0.3, elevation),
+    }
+}
+
+fn linear(color: Vec3) -> [f32; 4] {
+    Color::rgb(color.x, color.y, color.z).as_linear_rgba_f32()
+}
+
+/// Spawns the sky dome and a layer of clouds drifting high above the map.
+pub fn spawn_sky(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    seed: u32,
+) {
+    let mut dome = Mesh::from(shape::UVSphere {
+        radius: SKY_RADIUS,
+        sectors: 32,
+        stacks: 16,
+    });
+    let vertices = dome.count_vertices();
+    dome.insert_attribute(Mesh::ATTRIBUTE_COLOR, vec![[1.0; 4]; vertices]);
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(dome),
+            material: materials.add(StandardMaterial {
+                unlit: true,
+                cull_mode: None,
+                ..default()
+            }),
+            ..default()
+        },
+        NotShadowCaster,
+        NotShadowReceiver,
+        SkyDome,
+        Reboot,
+    ));
+
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+    let cloud_mesh = meshes.add(shape::UVSphere::default().into());
+    let cloud_material = materials.add(StandardMaterial {
+        base_color: Color::WHITE,
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    for _ in 0..CLOUD_COUNT {
+        let position = Vec3::new(
+            rng.gen_range(-CLOUD_SPREAD..CLOUD_SPREAD),
+            rng.gen_range(140.0..180.0),
+            rng.gen_range(-CLOUD_SPREAD..CLOUD_SPREAD),
+        );
+        let scale = Vec3::new(
+            rng.gen_range(12.0..30.0),
+            rng.gen_range(3.0..5.0),
+            rng.gen_range(8.0..18.0),
+        );
+        commands.spawn((
+            PbrBundle {
+                mesh: cloud_mesh.clone(),
+                material: cloud_material.clone(),
+                transform: Transform::from_translation(position).with_scale(scale),
+                ..default()
+            },
+            NotShadowCaster,
+            NotShadowReceiver,
+            Cloud,
+            Reboot,
+        ));
+    }
+}
+
+fn load_day_night_config(mut commands: Commands, mods: Res<Mods>) {
+    commands.insert_resource(load_data_file::<DayNightConfig>(&mods, DAY_NIGHT_CONFIG_PATH));
+}
+
+fn reset_time_of_day(
+    config: Res<DayNightConfig>,
+    mode: Res<GameMode>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    time_of_day.0 = config.cycle(*mode).start_hour.rem_euclid(24.0);
+}
+
+/// Starts a campaign level at its own hour when it has one. Lighting is
+/// refreshed either way, since the level may dim it.
+fn apply_level_lighting(
+    voxel_map: Res<VoxelMap>,
+    current_level: Res<CurrentLevel>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    if voxel_map.is_changed() {
+        time_of_day.0 = current_level.lighting().hour.map_or(time_of_day.0, |hour| hour.rem_euclid(24.0));
+    }
+}
+
+fn advance_time_of_day(
+    time: Res<Time>,
+    config: Res<DayNightConfig>,
+    mode: Res<GameMode>,
+    current_level: Res<CurrentLevel>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    let cycle_seconds = current_level
+        .lighting()
+        .cycle_seconds
+        .unwrap_or(config.cycle(*mode).cycle_seconds);
+    if cycle_seconds > 0.0 {
+        time_of_day.0 =
+            (time_of_day.0 + time.delta_seconds() * 24.0 / cycle_seconds).rem_euclid(24.0);
+    }
+}
+
+fn update_lighting(
+    time_of_day: Res<TimeOfDay>,
+    current_level: Res<CurrentLevel>,
+    mut ambient: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+    mut sun: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+    added: Query<(), Added<Sun>>,
+) {
+    if !time_of_day.is_changed() && added.is_empty() {
+        return;
+    }
+    let colors = sky_colors(time_of_day.sun_elevation());
+    let brightness = current_level.lighting().brightness;
+    for (mut light, mut transform) in &mut sun {
+        let direction = time_of_day.sun_direction();
+        // Below the horizon the moon takes over, shining from the opposite side.
+        let towards = if direction.y >= 0.0 {
+            direction
+        } else {
+            -direction
+        };
+        *transform = Transform::default().looking_to(-towards, Vec3::Y);
+        light.illuminance = MOON_ILLUMINANCE.max(SUN_ILLUMINANCE * colors.daylight) * brightness;
+        light.color = Color::rgb(colors.sun.x, colors.sun.y, colors.sun.z);
+    }
+    ambient.brightness = (0.05 + 0.55 * colors.daylight) * brightness;
+    ambient.color =
+        Color::rgb(0.6, 0.65, 1.0) * (1.0 - colors.daylight) + Color::WHITE * colors.daylight;
+    clear_color.0 = Color::rgb(colors.horizon.x, colors.horizon.y, colors.horizon.z);
+}
+
+#[allow(clippy::type_complexity)]
+fn update_sky(
+    time_of_day: Res<TimeOfDay>,
+    cameras: Query<
+        (&Transform, &Camera),
+        (Or<(With<PrimaryPlayer>, With<SpectatorCamera>)>, Without<SkyDome>),
+    >,
+    mut dome: Query<(&Handle<Mesh>, &mut Transform), With<SkyDome>>,
+    clouds: Query<&Handle<StandardMaterial>, With<Cloud>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    added: Query<(), Added<SkyDome>>,
+) {
+    let Ok((handle, mut transform)) = dome.get_single_mut() else {
+        return;
+    };
+    // Kept around whichever camera is looking, the player's or the spectator's.
+    if let Some((camera, _)) = cameras.iter().find(|(_, camera)| camera.is_active) {
+        transform.translation = camera.translation;
+    }
+    if !time_of_day.is_changed() && added.is_empty() {
+        return;
+    }
+    let colors = sky_colors(time_of_day.sun_elevation());
+    if let Some(mesh) = meshes.get_mut(handle) {
+        if let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        {
+            let gradient: Vec<[f32; 4]> = positions
+                .iter()
+                .map(|position| {
+                    let height = (position[1] / SKY_RADIUS).max(0.0).sqrt();
+                    linear(colors.horizon.lerp(colors.zenith, height))
+                })
+                .collect();
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, gradient);
+        }
+    }
+    if let Some(material) = clouds
+        .iter()
+        .next()
+        .and_then(|handle| materials.get_mut(handle))
+    {
+        material.base_color = Color::rgba(
+            colors.cloud.x,
+            colors.cloud.y,
+            colors.cloud.z,
+            colors.cloud.w,
+        );
+    }
+}
+
+fn drift_clouds(time: Res<Time>, mut clouds: Query<&mut Transform, With<Cloud>>) {
+    for mut transform in &mut clouds {
+        transform.translation.x += CLOUD_DRIFT * time.delta_seconds();
+        if transform.translation.x > CLOUD_SPREAD {
+            transform.translation.x -= 2.0 * CLOUD_SPREAD;
+        }
+    }
+}
+
+fn time_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let hour: f32 = parse_arg(args, 0)?;
+    if !(0.0..=24.0).contains(&hour) {
+        return Err("the hour must be between 0 and 24".into());
+    }
+    world.resource_mut::<TimeOfDay>().0 = hour.rem_euclid(24.0);
+    Ok(format!("time set to {hour:.1}h"))
+}
+
+pub struct SkyPlugin;
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .add_startup_system(load_day_night_config)
+            .add_system(reset_time_of_day.in_schedule(OnEnter(GameState::NewGame)))
+            .add_system(
+                apply_level_lighting
+                    .run_if(resource_exists::<VoxelMap>())
+                    .before(advance_time_of_day)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_systems(
+                (
+                    advance_time_of_day,
+                    update_lighting,
+                    update_sky,
+                    drift_clouds,
+                )
+                    .chain()
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_console_command("time", "time <hour>", &[], time_command);
+    }
+}
@@ -1,75 +1,387 @@
+use crate::console::console::ConsoleAppExt;
 use crate::prelude::*;
+use bevy::audio::{AudioSinkPlayback, Decodable, Source};
+use bevy::utils::HashMap;
+use rand::Rng;
+
+const HUM_PATH: &str = "sounds/score.ogg";
+const HUM_VOLUME: f32 = 0.35;
+/// Blue balls closer than this hum so they can be found by ear.
+const HUM_RADIUS: f32 = 20.0;
+/// World units per audio unit. Spatial sounds fall off with the inverse
+/// square of distance, at full volume up to one audio unit away.
+const AUDIO_SCALE: f32 = 8.0;
+const EAR_GAP: f32 = 0.25;
+
+/// How a sound effect is played.
+struct EffectSpec {
+    path: &'static str,
+    bus: AudioBus,
+    volume: f32,
+    speed: f32,
+    max_instances: usize,
+}
+
+fn spec(effect: SoundEffect) -> EffectSpec {
+    match effect {
+        SoundEffect::Hit => EffectSpec {
+            path: "sounds/hit.ogg",
+            bus: AudioBus::Sfx,
+            volume: 1.0,
+            speed: 0.5,
+            max_instances: 3,
+        },
+        SoundEffect::Score => EffectSpec {
+            path: "sounds/score.ogg",
+            bus: AudioBus::Sfx,
+            volume: 1.0,
+            speed: 1.0,
+            max_instances: 2,
+        },
+        SoundEffect::Death => EffectSpec {
+            path: "sounds/die.ogg",
+            bus: AudioBus::Sfx,
+            volume: 1.0,
+            speed: 1.0,
+            max_instances: 1,
+        },
+        SoundEffect::Click => EffectSpec {
+            path: "sounds/hit.ogg",
+            bus: AudioBus::Ui,
+            volume: 0.5,
+            speed: 1.5,
+            max_instances: 2,
+        },
+        SoundEffect::Bounce => EffectSpec {
+            path: "sounds/hit.ogg",
+            bus: AudioBus::Sfx,
+            volume: 0.8,
+            speed: 0.8,
+            max_instances: 4,
+        },
+    }
+}
+
+const EFFECTS: [SoundEffect; 5] = [
+    SoundEffect::Hit,
+    SoundEffect::Score,
+    SoundEffect::Death,
+    SoundEffect::Click,
+    SoundEffect::Bounce,
+];
 
 #[derive(Resource)]
-struct MusicController(Handle<AudioSink>);
+struct SoundHandles {
+    hum: Handle<AudioSource>,
+    effects: HashMap<SoundEffect, Handle<AudioSource>>,
+}
+
+/// Length in seconds at normal speed of each loaded effect clip, so the pool
+/// knows when a voice is free.
+#[derive(Resource, Default)]
+struct ClipLengths(HashMap<Handle<AudioSource>, f32>);
+
+enum Sink {
+    Flat(Handle<AudioSink>),
+    Spatial(Handle<SpatialAudioSink>),
+}
+
+struct PlayingSound {
+    effect: SoundEffect,
+    sink: Sink,
+    volume: f32,
+    ends: f64,
+}
+
+/// Effects that are still playing, so volume changes and pausing reach them.
+#[derive(Resource, Default)]
+struct PlayingSounds(Vec<PlayingSound>);
+
+/// Looping hums on nearby blue balls.
+#[derive(Resource, Default)]
+struct BallHums(HashMap<Entity, Handle<SpatialAudioSink>>);
+
+fn playback<'a>(
+    sink: &Sink,
+    flat: &'a Assets<AudioSink>,
+    spatial: &'a Assets<SpatialAudioSink>,
+) -> Option<&'a dyn AudioSinkPlayback> {
+    match sink {
+        Sink::Flat(handle) => flat.get(handle).map(|sink| sink as &dyn AudioSinkPlayback),
+        Sink::Spatial(handle) => spatial
+            .get(handle)
+            .map(|sink| sink as &dyn AudioSinkPlayback),
+    }
+}
+
+/// The camera in audio space.
+fn listener_transform(camera: &Transform) -> Transform {
+    Transform {
+        translation: camera.translation / AUDIO_SCALE,
+        ..*camera
+    }
+}
 
 pub struct SoundPlugin;
 impl Plugin for SoundPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(setup.in_schedule(OnExit(GameState::Splash)))
-        .add_system(update_volume.in_set(OnUpdate(MenuState::SettingsSound)));
+        app.init_resource::<PlayingSounds>()
+            .init_resource::<ClipLengths>()
+            .init_resource::<BallHums>()
+            .add_startup_system(preload_sounds)
+            .add_system(stop_game_sounds.in_schedule(OnExit(GameState::InGame)))
+            .add_system(measure_clips)
+            .add_system(play_sound_effects.after(measure_clips))
+            .add_system(update_listener.after(play_sound_effects))
+            .add_system(update_ball_hums)
+            .add_system(update_volume.after(play_sound_effects))
+            .add_console_command(
+                "mute",
+                "mute <master|music|sfx|ui>",
+                &["master", "music", "sfx", "ui"],
+                mute_command,
+            );
     }
 }
 
+fn preload_sounds(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SoundHandles {
+        hum: asset_server.load(HUM_PATH),
+        effects: EFFECTS
+            .into_iter()
+            .map(|effect| (effect, asset_server.load(spec(effect).path)))
+            .collect(),
+    });
+}
+
+fn clip_length(source: &AudioSource) -> f32 {
+    let decoder = source.decoder();
+    if let Some(duration) = decoder.total_duration() {
+        return duration.as_secs_f32();
+    }
+    let samples_per_second = decoder.sample_rate() as f32 * decoder.channels() as f32;
+    decoder.count() as f32 / samples_per_second
+}
+
+fn measure_clips(
+    mut events: EventReader<AssetEvent<AudioSource>>,
+    handles: Res<SoundHandles>,
+    sources: Res<Assets<AudioSource>>,
+    mut lengths: ResMut<ClipLengths>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if !handles.effects.values().any(|effect| effect == handle) {
+                    continue;
+                }
+                if let Some(source) = sources.get(handle) {
+                    lengths.0.insert(handle.clone_weak(), clip_length(source));
+                }
+            }
+            AssetEvent::Removed { handle } => {
+                lengths.0.remove(handle);
+            }
+        }
+    }
+}
 
-fn setup (
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
+#[allow(clippy::too_many_arguments)]
+fn play_sound_effects(
+    mut effects: EventReader<SoundEffect>,
+    mut spatial_effects: EventReader<SpatialSound>,
+    handles: Res<SoundHandles>,
+    lengths: Res<ClipLengths>,
     audio: Res<Audio>,
     audio_sinks: Res<Assets<AudioSink>>,
-    volume: Res<Volume>,
+    spatial_sinks: Res<Assets<SpatialAudioSink>>,
+    time: Res<Time>,
+    settings: Res<AudioSettings>,
+    game_state: Res<State<GameState>>,
+    listener: Query<&Transform, With<PrimaryPlayer>>,
+    mut playing: ResMut<PlayingSounds>,
 ) {
-    let music = asset_server.load("sounds/music.ogg");
-    let handle = audio_sinks.get_handle(audio.play_with_settings(
-        music,
-        PlaybackSettings::LOOP.with_volume(volume.0 as f32 * 0.1),
-    ));
-    commands.insert_resource(MusicController(handle));
-}
+    let now = time.elapsed_seconds_f64();
+    playing.0.retain(|sound| sound.ends > now);
 
+    let listener = listener.get_single().ok().map(listener_transform);
+    let requests = effects
+        .iter()
+        .map(|&effect| (effect, None, 1.0))
+        .chain(
+            spatial_effects
+                .iter()
+                .map(|sound| (sound.effect, Some(sound.position), sound.volume)),
+        );
+    let mut rng = rand::thread_rng();
+    for (effect, position, volume) in requests {
+        let spec = spec(effect);
+        // Gameplay sounds sent while the game is paused are dropped.
+        if spec.bus == AudioBus::Sfx && game_state.0 != GameState::InGame {
+            continue;
+        }
+        let instances = playing.0.iter().filter(|sound| sound.effect == effect).count();
+        if instances >= spec.max_instances {
+            continue;
+        }
+        // Until its clip has loaded there is no telling when a voice frees up.
+        let handle = &handles.effects[&effect];
+        let Some(&length) = lengths.0.get(handle) else {
+            continue;
+        };
+        let volume = volume * spec.volume * rng.gen_range(0.9..=1.0);
+        let speed = spec.speed * rng.gen_range(0.95..=1.05);
+        let settings = PlaybackSettings {
+            volume: volume * settings.volume(spec.bus),
+            speed,
+            ..default()
+        };
+        let source = handle.clone();
+        let sink = match (position, listener) {
+            (Some(position), Some(listener)) => {
+                Sink::Spatial(spatial_sinks.get_handle(audio.play_spatial_with_settings(
+                    source,
+                    settings,
+                    listener,
+                    EAR_GAP,
+                    position / AUDIO_SCALE,
+                )))
+            }
+            _ => Sink::Flat(audio_sinks.get_handle(audio.play_with_settings(source, settings))),
+        };
+        playing.0.push(PlayingSound {
+            effect,
+            sink,
+            volume,
+            ends: now + (length / speed) as f64,
+        });
+    }
+}
 
-fn update_volume (
-    audio_sinks: Res<Assets<AudioSink>>,
-    music_controller: Res<MusicController>,
-    volume: Res<Volume>,
+fn update_listener(
+    listener: Query<&Transform, (With<PrimaryPlayer>, Changed<Transform>)>,
+    spatial_sinks: Res<Assets<SpatialAudioSink>>,
+    playing: Res<PlayingSounds>,
+    hums: Res<BallHums>,
 ) {
-    if let Some(sink) = audio_sinks.get(&music_controller.0) {
-        sink.set_volume(volume.0 as f32 * 0.1);
+    let Ok(camera) = listener.get_single() else {
+        return;
+    };
+    let listener = listener_transform(camera);
+    let effects = playing.0.iter().filter_map(|sound| match &sound.sink {
+        Sink::Spatial(handle) => Some(handle),
+        Sink::Flat(_) => None,
+    });
+    for handle in effects.chain(hums.0.values()) {
+        if let Some(sink) = spatial_sinks.get(handle) {
+            sink.set_listener_position(listener, EAR_GAP);
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+fn update_ball_hums(
+    mut hums: ResMut<BallHums>,
+    balls: Query<(Entity, &Transform), With<BlueBall>>,
+    listener: Query<&Transform, With<PrimaryPlayer>>,
+    handles: Res<SoundHandles>,
+    audio: Res<Audio>,
+    spatial_sinks: Res<Assets<SpatialAudioSink>>,
+    settings: Res<AudioSettings>,
+    game_state: Res<State<GameState>>,
+) {
+    let camera = listener.get_single().ok().filter(|_| game_state.0 == GameState::InGame);
+    let nearby: HashMap<Entity, Vec3> = camera
+        .map(|camera| {
+            balls
+                .iter()
+                .filter(|(_, ball)| ball.translation.distance(camera.translation) <= HUM_RADIUS)
+                .map(|(entity, ball)| (entity, ball.translation))
+                .collect()
+        })
+        .unwrap_or_default();
 
-pub fn play_hit_sound(asset_server: &Res<AssetServer>, audio: &Res<Audio>, volume: &Res<Volume>) {
-    let music = asset_server.load("sounds/hit.ogg");
-    audio.play_with_settings(
-        music,
-        PlaybackSettings {
-            volume: volume.0 as f32 * 0.1,
-            speed: 0.5,
-            ..default()
+    hums.0.retain(|entity, handle| {
+        let keep = nearby.contains_key(entity);
+        if !keep {
+            if let Some(sink) = spatial_sinks.get(handle) {
+                sink.stop();
+            }
         }
-    );
+        keep
+    });
+    let Some(camera) = camera else {
+        return;
+    };
+    let volume = HUM_VOLUME * settings.volume(AudioBus::Sfx);
+    for (entity, position) in nearby {
+        if let Some(sink) = hums.0.get(&entity).and_then(|handle| spatial_sinks.get(handle)) {
+            if settings.is_changed() {
+                sink.set_volume(volume);
+            }
+            continue;
+        }
+        let handle = spatial_sinks.get_handle(audio.play_spatial_with_settings(
+            handles.hum.clone(),
+            PlaybackSettings::LOOP.with_volume(volume).with_speed(0.6),
+            listener_transform(camera),
+            EAR_GAP,
+            position / AUDIO_SCALE,
+        ));
+        hums.0.insert(entity, handle);
+    }
 }
 
-
-pub fn play_score_sound(asset_server: &Res<AssetServer>, audio: &Res<Audio>, volume: &Res<Volume>) {
-    let music = asset_server.load("sounds/score.ogg");
-    audio.play_with_settings(
-        music,
-        PlaybackSettings {
-            volume: volume.0 as f32 * 0.1,
-            ..default()
+fn stop_game_sounds(
+    audio_sinks: Res<Assets<AudioSink>>,
+    spatial_sinks: Res<Assets<SpatialAudioSink>>,
+    mut playing: ResMut<PlayingSounds>,
+) {
+    playing.0.retain(|sound| {
+        if spec(sound.effect).bus != AudioBus::Sfx {
+            return true;
+        }
+        if let Some(sink) = playback(&sound.sink, &audio_sinks, &spatial_sinks) {
+            sink.stop();
         }
-    );
+        false
+    });
 }
 
-pub fn play_death_sound(asset_server: &Res<AssetServer>, audio: &Res<Audio>, volume: &Res<Volume>) {
-    let music = asset_server.load("sounds/die.ogg");
-    audio.play_with_settings(
-        music,
-        PlaybackSettings {
-            volume: volume.0 as f32 * 0.1,
-            ..default()
+fn update_volume(
+    audio_sinks: Res<Assets<AudioSink>>,
+    spatial_sinks: Res<Assets<SpatialAudioSink>>,
+    settings: Res<AudioSettings>,
+    playing: Res<PlayingSounds>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for sound in &playing.0 {
+        if let Some(sink) = playback(&sound.sink, &audio_sinks, &spatial_sinks) {
+            sink.set_volume(sound.volume * settings.volume(spec(sound.effect).bus));
         }
-    );
-}
\ No newline at end of file
+    }
+}
+
+pub fn parse_bus(name: &str) -> Option<AudioBus> {
+    match name {
+        "master" | "volume" => Some(AudioBus::Master),
+        "music" => Some(AudioBus::Music),
+        "sfx" => Some(AudioBus::Sfx),
+        "ui" => Some(AudioBus::Ui),
+        _ => None,
+    }
+}
+
+fn mute_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = *args.first().ok_or_else(String::new)?;
+    let bus = parse_bus(name).ok_or_else(|| format!("unknown bus `{name}`"))?;
+    let mut settings = world.resource_mut::<AudioSettings>();
+    let channel = settings.channel_mut(bus);
+    channel.muted = !channel.muted;
+    Ok(format!(
+        "{name} {}",
+        if channel.muted { "muted" } else { "unmuted" }
+    ))
+}
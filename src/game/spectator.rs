@@ -0,0 +1,322 @@
+use crate::cli::{parse_spectator_view, LaunchOptions};
+use crate::console::console::{console_closed, ConsoleAppExt};
+use crate::game::ghost::GhostBall;
+use crate::game::load::voxel_position;
+use crate::player::player::{calculate_fov, KeyBindings};
+use crate::prelude::*;
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F5;
+const FOLLOW_KEY: KeyCode = KeyCode::F6;
+const OVERVIEW_KEY: KeyCode = KeyCode::F7;
+/// Held to fly faster.
+const FAST_KEY: KeyCode = KeyCode::LControl;
+
+const FLY_SPEED: f32 = 20.0;
+const FAST_FACTOR: f32 = 4.0;
+/// Degrees turned per pixel of mouse movement.
+const LOOK_SENSITIVITY: f32 = 0.1;
+const FOLLOW_DISTANCE: f32 = 12.0;
+const MIN_FOLLOW_DISTANCE: f32 = 3.0;
+const MAX_FOLLOW_DISTANCE: f32 = 60.0;
+
+/// The camera used while spectating, which renders nothing until it is needed.
+#[derive(Component)]
+pub struct SpectatorCamera {
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl Default for SpectatorCamera {
+    fn default() -> Self {
+        SpectatorCamera {
+            yaw: 0.0,
+            pitch: -0.4,
+            distance: FOLLOW_DISTANCE,
+        }
+    }
+}
+
+impl SpectatorCamera {
+    fn rotation(&self) -> Quat {
+        Quat::from_axis_angle(Vec3::Y, self.yaw) * Quat::from_axis_angle(Vec3::X, self.pitch)
+    }
+
+    fn turn(&mut self, degrees: Vec2) {
+        self.yaw -= degrees.x.to_radians();
+        self.pitch = (self.pitch - degrees.y.to_radians()).clamp(-1.54, 1.54);
+    }
+
+    fn look_from(&mut self, transform: &Transform) {
+        let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-1.54, 1.54);
+    }
+}
+
+/// Run condition that hands the players' controls over to the spectator camera.
+pub fn not_spectating(spectator: Res<Spectator>) -> bool {
+    !spectator.is_active()
+}
+
+/// Everything that can be followed: local and remote players, bots and ghosts.
+type FollowTargets<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static Transform),
+    (
+        Or<(With<Player>, With<RemotePlayer>, With<Bot>, With<GhostBall>)>,
+        Without<SpectatorCamera>,
+    ),
+>;
+
+/// The targets in a stable order, so cycling through them visits each in turn.
+fn sorted_targets(targets: &FollowTargets) -> Vec<Entity> {
+    let mut entities: Vec<Entity> = targets.iter().map(|(entity, _)| entity).collect();
+    entities.sort();
+    entities
+}
+
+fn apply_launch_options(options: Res<LaunchOptions>, mut spectator: ResMut<Spectator>) {
+    if let Some(view) = options.spectate {
+        *spectator = view;
+    }
+}
+
+fn spawn_spectator_camera(mut commands: Commands, fov: Res<Fov>, local_players: Res<LocalPlayers>) {
+    commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                order: -1,
+                is_active: false,
+                ..default()
+            },
+            projection: PerspectiveProjection {
+                fov: calculate_fov(fov.0 as f32).to_radians(),
+                ..default()
+            }
+            .into(),
+            ..default()
+        },
+        // Split-screen draws the HUD with its own camera.
+        UiCameraConfig {
+            show_ui: local_players.0 < 2,
+        },
+        SpectatorCamera::default(),
+    ));
+}
+
+fn spectator_keys(
+    keys: Res<Input<KeyCode>>,
+    targets: FollowTargets,
+    mut spectator: ResMut<Spectator>,
+) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        *spectator = match *spectator {
+            Spectator::Off => Spectator::Free,
+            _ => Spectator::Off,
+        };
+    }
+    if keys.just_pressed(FOLLOW_KEY) {
+        let targets = sorted_targets(&targets);
+        let next = match *spectator {
+            Spectator::Follow(Some(current)) => targets
+                .iter()
+                .position(|&target| target == current)
+                .map_or(0, |index| index + 1),
+            _ => 0,
+        };
+        // Cycling past the last target goes back to flying freely.
+        *spectator = match targets.get(next) {
+            Some(&target) => Spectator::Follow(Some(target)),
+            None => Spectator::Free,
+        };
+    }
+    if keys.just_pressed(OVERVIEW_KEY) {
+        *spectator = match *spectator {
+            Spectator::Overview => Spectator::Free,
+            _ => Spectator::Overview,
+        };
+    }
+}
+
+/// Turns the spectator camera on or off, placing it to suit the view it was
+/// switched to. The players' own cameras turn themselves off meanwhile.
+fn switch_cameras(
+    spectator: Res<Spectator>,
+    mut previous: Local<Spectator>,
+    voxel_map: Res<VoxelMap>,
+    player: Query<&Transform, (With<PrimaryPlayer>, Without<SpectatorCamera>)>,
+    mut cameras: Query<(&mut Camera, &mut Transform, &mut SpectatorCamera, &Projection)>,
+    mut huds: Query<&mut Visibility, With<Hud>>,
+) {
+    let Ok((mut camera, mut transform, mut rig, projection)) = cameras.get_single_mut() else {
+        return;
+    };
+    if rig.is_added() {
+        *previous = Spectator::Off;
+    }
+    let active = spectator.is_active();
+    if camera.is_active != active {
+        camera.is_active = active;
+    }
+    if *previous == *spectator {
+        return;
+    }
+
+    if *previous == Spectator::Off {
+        if let Ok(player) = player.get_single() {
+            *transform = *player;
+            rig.look_from(player);
+        }
+    }
+    if *spectator == Spectator::Overview && !voxel_map.0.is_empty() {
+        let map = &voxel_map.0;
+        let (width, height, depth) = (map.len(), map[0].len(), map[0][0].len());
+        let center = voxel_position(map, (width / 2, 0, depth / 2));
+        let fov = match projection {
+            Projection::Perspective(perspective) => perspective.fov,
+            _ => std::f32::consts::FRAC_PI_4,
+        };
+        let half_extent = width.max(depth) as f32 / 2.0;
+        let above = height as f32 + 1.1 * half_extent / (fov / 2.0).tan();
+        *transform = Transform::from_translation(center + Vec3::Y * above)
+            .looking_at(center, Vec3::NEG_Z);
+    } else if *previous == Spectator::Overview {
+        rig.look_from(&transform);
+    }
+    // The whole map is shown without the HUD in the way, ready for a screenshot.
+    let visibility = match *spectator {
+        Spectator::Overview => Visibility::Hidden,
+        _ => Visibility::Inherited,
+    };
+    for mut hud in &mut huds {
+        *hud = visibility;
+    }
+    *previous = *spectator;
+}
+
+/// Degrees the mouse asks to turn, or nothing if the cursor is free.
+fn mouse_turn(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    motion: &mut EventReader<MouseMotion>,
+) -> Vec2 {
+    let delta: Vec2 = motion.iter().map(|event| event.delta).sum();
+    match windows.get_single() {
+        Ok(window) if window.cursor.grab_mode != CursorGrabMode::None => delta * LOOK_SENSITIVITY,
+        _ => Vec2::ZERO,
+    }
+}
+
+/// Flies the camera freely through the map, ignoring physics.
+fn fly(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut motion: EventReader<MouseMotion>,
+    spectator: Res<Spectator>,
+    mut cameras: Query<(&mut Transform, &mut SpectatorCamera)>,
+) {
+    let turn = mouse_turn(&windows, &mut motion);
+    if *spectator != Spectator::Free {
+        return;
+    }
+    let Ok((mut transform, mut rig)) = cameras.get_single_mut() else {
+        return;
+    };
+    rig.turn(turn);
+    transform.rotation = rig.rotation();
+
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        f32::from(u8::from(keys.pressed(positive))) - f32::from(u8::from(keys.pressed(negative)))
+    };
+    let direction = transform.right()
+        * axis(key_bindings.move_right, key_bindings.move_left)
+        + transform.forward() * axis(key_bindings.move_forward, key_bindings.move_backward)
+        + Vec3::Y * axis(key_bindings.move_ascend, key_bindings.move_descend);
+    let speed = if keys.pressed(FAST_KEY) {
+        FLY_SPEED * FAST_FACTOR
+    } else {
+        FLY_SPEED
+    };
+    transform.translation += direction.normalize_or_zero() * speed * time.delta_seconds();
+}
+
+/// Orbits the followed target, picking the first one if it is missing.
+fn follow(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut motion: EventReader<MouseMotion>,
+    mut wheel: EventReader<MouseWheel>,
+    mut spectator: ResMut<Spectator>,
+    targets: FollowTargets,
+    mut cameras: Query<(&mut Transform, &mut SpectatorCamera)>,
+) {
+    let turn = mouse_turn(&windows, &mut motion);
+    let zoom: f32 = wheel.iter().map(|event| event.y).sum();
+    let Spectator::Follow(target) = *spectator else {
+        return;
+    };
+    let Ok((mut transform, mut rig)) = cameras.get_single_mut() else {
+        return;
+    };
+    let found = target.and_then(|target| targets.get(target).ok());
+    let Some((_, target)) = found.or_else(|| {
+        let first = *sorted_targets(&targets).first()?;
+        *spectator = Spectator::Follow(Some(first));
+        targets.get(first).ok()
+    }) else {
+        return;
+    };
+    rig.turn(turn);
+    rig.distance = (rig.distance - zoom).clamp(MIN_FOLLOW_DISTANCE, MAX_FOLLOW_DISTANCE);
+    transform.rotation = rig.rotation();
+    transform.translation = target.translation + transform.back() * rig.distance;
+}
+
+fn spectate_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let view = match args.first() {
+        None => match *world.resource::<Spectator>() {
+            Spectator::Off => Spectator::Free,
+            _ => Spectator::Off,
+        },
+        Some(&"off") => Spectator::Off,
+        Some(value) => parse_spectator_view(value)?,
+    };
+    *world.resource_mut::<Spectator>() = view;
+    Ok(match view {
+        Spectator::Off => "spectating off".to_string(),
+        Spectator::Free => "spectating: free camera".to_string(),
+        Spectator::Follow(_) => "spectating: following".to_string(),
+        Spectator::Overview => "spectating: overview".to_string(),
+    })
+}
+
+pub struct SpectatorPlugin;
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Spectator>()
+            .add_startup_system(apply_launch_options)
+            .add_system(spawn_spectator_camera.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(cleanup::<SpectatorCamera>.in_schedule(OnExit(GameState::InGame)))
+            .add_system(
+                spectator_keys
+                    .run_if(console_closed)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_systems(
+                (switch_cameras, fly.run_if(console_closed), follow)
+                    .chain()
+                    .after(spectator_keys)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_console_command(
+                "spectate",
+                "spectate [free|follow|overview|off]",
+                &["free", "follow", "overview", "off"],
+                spectate_command,
+            );
+    }
+}
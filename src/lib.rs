@@ -0,0 +1,70 @@
+pub mod cli;
+pub mod config;
+pub mod console;
+pub mod game;
+pub mod menu;
+pub mod net;
+pub mod player;
+pub mod prelude;
+pub mod script;
+
+use crate::cli::LaunchOptions;
+use crate::config::{Config, ConfigPlugin};
+use crate::console::console::ConsolePlugin;
+use crate::game::gameplay::GamePlugin;
+use crate::game::mods::add_mods;
+use crate::menu::menu::MenuPlugin;
+use crate::net::net::NetPlugin;
+use crate::player::player::PlayerPlugin;
+use crate::prelude::*;
+use crate::script::script::ScriptPlugin;
+use bevy::{
+    app::{ScheduleRunnerPlugin, ScheduleRunnerSettings},
+    audio::AudioPlugin,
+    diagnostic::FrameTimeDiagnosticsPlugin,
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::{ExitCondition, WindowPlugin},
+    winit::WinitPlugin,
+};
+use bevy_rapier3d::prelude::*;
+use std::time::Duration;
+
+/// Runs the app without a window, renderer or audio, `tick_rate` frames a second.
+pub fn add_headless_plugins(app: &mut App, tick_rate: f64) {
+    add_mods(app, false);
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: None,
+                exit_condition: ExitCondition::DontExit,
+                close_when_requested: false,
+            })
+            .set(RenderPlugin {
+                wgpu_settings: WgpuSettings {
+                    backends: None,
+                    ..default()
+                },
+            })
+            .disable::<WinitPlugin>()
+            .disable::<AudioPlugin>(),
+    )
+    .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+        1.0 / tick_rate,
+    )))
+    .add_plugin(ScheduleRunnerPlugin);
+}
+
+/// The game itself, shared by the client and the dedicated server.
+pub fn add_game_plugins(app: &mut App, persisted: Config, options: LaunchOptions) {
+    app.add_state::<GameState>()
+        .add_plugin(ConfigPlugin { persisted, options })
+        .add_plugin(MenuPlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(GamePlugin)
+        .add_plugin(NetPlugin)
+        .add_plugin(ScriptPlugin)
+        .add_plugin(bevy::diagnostic::SystemInformationDiagnosticsPlugin)
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_plugin(ConsolePlugin)
+        .add_plugin(FrameTimeDiagnosticsPlugin);
+}
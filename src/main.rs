@@ -1,35 +1,78 @@
-mod game;
-mod menu;
-mod player;
-mod prelude;
-
-use crate::game::gameplay::GamePlugin;
-use crate::menu::menu::MenuPlugin;
-use crate::player::player::PlayerPlugin;
-use crate::prelude::*;
 use bevy::{
-    diagnostic::FrameTimeDiagnosticsPlugin,
-    window::{PresentMode, WindowPlugin},
+    app::AppExit,
+    prelude::*,
+    asset::AssetPlugin,
+    window::{PresentMode, WindowMode, WindowPlugin},
 };
 use bevy_rapier3d::prelude::*;
+use sky_quest::cli::{LaunchOptions, USAGE};
+use sky_quest::config::Config;
+use sky_quest::game::mods::add_mods;
+use sky_quest::{add_game_plugins, add_headless_plugins};
+
+/// Exits a headless run once the requested number of frames have run.
+fn exit_after_frames(
+    mut frames: Local<u32>,
+    options: Res<LaunchOptions>,
+    mut exit: EventWriter<AppExit>,
+) {
+    *frames += 1;
+    if options.headless.is_some_and(|limit| *frames >= limit) {
+        info!("Headless run finished after {} frames", *frames);
+        exit.send(AppExit);
+    }
+}
+
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "Sky Quest".into(),
-                present_mode: PresentMode::AutoNoVsync,
-                fit_canvas_to_parent: true,
-                mode: bevy::window::WindowMode::BorderlessFullscreen,
-                ..Default::default()
-            }),
-            ..Default::default()
-        }))
-        .add_state::<GameState>()
-        .add_plugin(MenuPlugin)
-        .add_plugin(PlayerPlugin)
-        .add_plugin(GamePlugin)
-        .add_plugin(bevy::diagnostic::SystemInformationDiagnosticsPlugin::default())
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        .run();
+    let options = match LaunchOptions::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => std::process::exit(err.report(USAGE)),
+    };
+    let persisted = Config::load();
+    let config = persisted.with_overrides(&options);
+
+    let headless = options.headless.is_some();
+    let mut app = App::new();
+    if headless {
+        add_headless_plugins(&mut app, 60.0);
+        app.add_system(exit_after_frames);
+    } else {
+        add_mods(&mut app, true);
+        app.add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "Sky Quest".into(),
+                        present_mode: if config.vsync {
+                            PresentMode::AutoVsync
+                        } else {
+                            PresentMode::AutoNoVsync
+                        },
+                        fit_canvas_to_parent: true,
+                        mode: if config.fullscreen {
+                            WindowMode::BorderlessFullscreen
+                        } else {
+                            WindowMode::Windowed
+                        },
+                        resolution: config.resolution.into(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                // Archetype files are picked up as they are edited. With mods
+                // loaded, `add_mods` above turns this on for its own server.
+                .set(AssetPlugin {
+                    watch_for_changes: true,
+                    ..default()
+                }),
+        );
+    }
+    add_game_plugins(&mut app, persisted, options);
+    if !headless {
+        app.add_plugin(RapierDebugRenderPlugin {
+            enabled: false,
+            ..default()
+        });
+    }
+    app.run();
 }
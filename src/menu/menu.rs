@@ -1,3 +1,5 @@
+use crate::game::levels::{Campaign, CampaignProgress, CurrentLevel};
+use crate::game::mods::{ModStatus, Mods};
 use crate::menu::splash::SplashPlugin;
 use crate::prelude::*;
 use bevy::{app::AppExit, prelude::*};
@@ -8,21 +10,19 @@ pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(DisplayQuality::Medium)
-            .insert_resource(Fov(4))
-            .insert_resource(Volume(7))
-            .insert_resource(Pause { paused: false })
+        app.insert_resource(Pause { paused: false })
             .add_plugin(SplashPlugin)
             .add_state::<MenuState>()
             .add_systems((
                 setup_camera.in_schedule(OnEnter(GameState::Menu)),
-                cleanup::<Cursor>.in_schedule(OnEnter(GameState::Menu)),
-                cleanup::<TextChanges>.in_schedule(OnEnter(GameState::Menu)),
+                cleanup::<Hud>.in_schedule(OnEnter(GameState::Menu)),
                 menu_setup.in_schedule(OnEnter(GameState::Menu)),
                 cleanup::<Camera>.in_schedule(OnExit(GameState::Menu)),
             ))
             .add_systems((
                 main_menu_setup.in_schedule(OnEnter(MenuState::Main)),
+                setting_button::<LocalPlayers>.in_set(OnUpdate(MenuState::Main)),
+                setting_button::<ModeChoice>.in_set(OnUpdate(MenuState::Main)),
                 cleanup::<OnMainMenuScreen>.in_schedule(OnExit(MenuState::Main)),
             ))
             .add_systems((
@@ -37,14 +37,42 @@ impl Plugin for MenuPlugin {
             ))
             .add_systems((
                 sound_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsSound)),
-                setting_button::<Volume>.in_set(OnUpdate(MenuState::SettingsSound)),
+                volume_button.in_set(OnUpdate(MenuState::SettingsSound)),
+                mute_button.in_set(OnUpdate(MenuState::SettingsSound)),
                 cleanup::<OnSoundSettingsMenuScreen>.in_schedule(OnExit(MenuState::SettingsSound)),
             ))
             .add_systems((
                 fov_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsFov)),
                 setting_button::<Fov>.in_set(OnUpdate(MenuState::SettingsFov)),
+                setting_button::<CameraView>.in_set(OnUpdate(MenuState::SettingsFov)),
                 cleanup::<OnFovSettingsMenuScreen>.in_schedule(OnExit(MenuState::SettingsFov)),
             ))
+            .add_systems((
+                difficulty_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsDifficulty)),
+                setting_button::<Difficulty>.in_set(OnUpdate(MenuState::SettingsDifficulty)),
+                cleanup::<OnDifficultySettingsMenuScreen>
+                    .in_schedule(OnExit(MenuState::SettingsDifficulty)),
+            ))
+            .add_systems((
+                bot_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsBots)),
+                setting_button::<BotCount>.in_set(OnUpdate(MenuState::SettingsBots)),
+                setting_button::<BotSkill>.in_set(OnUpdate(MenuState::SettingsBots)),
+                cleanup::<OnBotSettingsMenuScreen>.in_schedule(OnExit(MenuState::SettingsBots)),
+            ))
+            .add_systems((
+                hud_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsHud)),
+                hud_toggle_button.in_set(OnUpdate(MenuState::SettingsHud)),
+                cleanup::<OnHudSettingsMenuScreen>.in_schedule(OnExit(MenuState::SettingsHud)),
+            ))
+            .add_systems((
+                mods_menu_setup.in_schedule(OnEnter(MenuState::SettingsMods)),
+                cleanup::<OnModsMenuScreen>.in_schedule(OnExit(MenuState::SettingsMods)),
+            ))
+            .add_systems((
+                campaign_menu_setup.in_schedule(OnEnter(MenuState::Campaign)),
+                level_button.in_set(OnUpdate(MenuState::Campaign)),
+                cleanup::<OnCampaignMenuScreen>.in_schedule(OnExit(MenuState::Campaign)),
+            ))
             .add_systems((menu_action, button_system).in_set(OnUpdate(GameState::Menu)));
     }
 }
@@ -64,6 +92,21 @@ struct OnSoundSettingsMenuScreen;
 #[derive(Component)]
 struct OnFovSettingsMenuScreen;
 
+#[derive(Component)]
+struct OnDifficultySettingsMenuScreen;
+
+#[derive(Component)]
+struct OnHudSettingsMenuScreen;
+
+#[derive(Component)]
+struct OnBotSettingsMenuScreen;
+
+#[derive(Component)]
+struct OnModsMenuScreen;
+
+#[derive(Component)]
+struct OnCampaignMenuScreen;
+
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 const HOVERED_PRESSED_BUTTON: Color = Color::rgb(0.25, 0.65, 0.25);
@@ -72,26 +115,49 @@ const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 #[derive(Component)]
 struct SelectedOption;
 
+#[derive(Component)]
+struct VolumeButton {
+    bus: AudioBus,
+    level: u32,
+}
+
+#[derive(Component)]
+struct MuteButton(AudioBus);
+
+/// Starts the campaign level at this index.
+#[derive(Component)]
+struct LevelButton(usize);
+
 #[derive(Component)]
 enum MenuButtonAction {
     Play,
+    Campaign,
     Settings,
     SettingsDisplay,
     SettingsSound,
     BackToMainMenu,
     BackToSettings,
     SettingsFov,
+    SettingsDifficulty,
+    SettingsHud,
+    SettingsBots,
+    SettingsMods,
     Quit,
     Resume,
 }
 
+#[allow(clippy::type_complexity)]
 fn button_system(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor, Option<&SelectedOption>),
         (Changed<Interaction>, With<Button>),
     >,
+    mut sounds: EventWriter<SoundEffect>,
 ) {
     for (interaction, mut color, selected) in &mut interaction_query {
+        if *interaction == Interaction::Clicked {
+            sounds.send(SoundEffect::Click);
+        }
         *color = match (*interaction, selected) {
             (Interaction::Clicked, _) | (Interaction::None, Some(_)) => PRESSED_BUTTON.into(),
             (Interaction::Hovered, Some(_)) => HOVERED_PRESSED_BUTTON.into(),
@@ -101,9 +167,10 @@ fn button_system(
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn setting_button<T: Resource + Component + PartialEq + Copy>(
     interaction_query: Query<(&Interaction, &T, Entity), (Changed<Interaction>, With<Button>)>,
-    mut selected_query: Query<(Entity, &mut BackgroundColor), With<SelectedOption>>,
+    mut selected_query: Query<(Entity, &mut BackgroundColor), (With<SelectedOption>, With<T>)>,
     mut commands: Commands,
     mut setting: ResMut<T>,
 ) {
@@ -118,11 +185,94 @@ fn setting_button<T: Resource + Component + PartialEq + Copy>(
     }
 }
 
+#[allow(clippy::type_complexity)]
+fn hud_toggle_button(
+    interaction_query: Query<(&Interaction, &HudWidget, Entity), (Changed<Interaction>, With<Button>)>,
+    mut commands: Commands,
+    mut hud_settings: ResMut<HudSettings>,
+) {
+    for (interaction, widget, entity) in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            if hud_settings.hidden.remove(widget) {
+                commands.entity(entity).insert(SelectedOption);
+            } else {
+                hud_settings.hidden.insert(*widget);
+                commands.entity(entity).remove::<SelectedOption>();
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn volume_button(
+    interaction_query: Query<(&Interaction, &VolumeButton, Entity), (Changed<Interaction>, With<Button>)>,
+    mut selected_query: Query<(Entity, &VolumeButton, &mut BackgroundColor), With<SelectedOption>>,
+    mut commands: Commands,
+    mut audio_settings: ResMut<AudioSettings>,
+) {
+    for (interaction, button, entity) in &interaction_query {
+        if *interaction == Interaction::Clicked && audio_settings.channel(button.bus).level != button.level {
+            for (previous_button, previous, mut previous_color) in &mut selected_query {
+                if previous.bus == button.bus {
+                    *previous_color = NORMAL_BUTTON.into();
+                    commands.entity(previous_button).remove::<SelectedOption>();
+                }
+            }
+            commands.entity(entity).insert(SelectedOption);
+            audio_settings.channel_mut(button.bus).level = button.level;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn mute_button(
+    interaction_query: Query<(&Interaction, &MuteButton, Entity), (Changed<Interaction>, With<Button>)>,
+    mut commands: Commands,
+    mut audio_settings: ResMut<AudioSettings>,
+) {
+    for (interaction, MuteButton(bus), entity) in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            let channel = audio_settings.channel_mut(*bus);
+            channel.muted = !channel.muted;
+            if channel.muted {
+                commands.entity(entity).insert(SelectedOption);
+            } else {
+                commands.entity(entity).remove::<SelectedOption>();
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn level_button(
+    interaction_query: Query<(&Interaction, &LevelButton), (Changed<Interaction>, With<Button>)>,
+    game_info: Res<GameInfo>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    for (interaction, LevelButton(index)) in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            *current_level = CurrentLevel::start(*index, game_info.wins);
+            game_state.set(GameState::NewGame);
+            menu_state.set(MenuState::Disabled);
+        }
+    }
+}
+
 fn menu_setup(mut menu_state: ResMut<NextState<MenuState>>) {
     menu_state.set(MenuState::Main);
 }
 
-fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, pause: ResMut<Pause>) {
+fn main_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    pause: ResMut<Pause>,
+    local_players: Res<LocalPlayers>,
+    role: Res<NetRole>,
+    modes: Res<GameModes>,
+    mode_choice: Res<ModeChoice>,
+) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     let button_style = Style {
         size: Size::new(Val::Px(250.0), Val::Px(65.0)),
@@ -233,6 +383,30 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, pause
                                 button_text_style.clone(),
                             ));
                         });
+                    // Levels are only played on this machine.
+                    if *role == NetRole::Offline {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                MenuButtonAction::Campaign,
+                            ))
+                            .with_children(|parent| {
+                                let icon = asset_server.load("textures/right.png");
+                                parent.spawn(ImageBundle {
+                                    style: button_icon_style.clone(),
+                                    image: UiImage::new(icon),
+                                    ..default()
+                                });
+                                parent.spawn(TextBundle::from_section(
+                                    "Campaign",
+                                    button_text_style.clone(),
+                                ));
+                            });
+                    }
                     parent
                         .spawn((
                             ButtonBundle {
@@ -254,6 +428,88 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, pause
                                 button_text_style.clone(),
                             ));
                         });
+                    // Split-screen is only offered for games on this machine.
+                    if *role == NetRole::Offline {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "Players",
+                                    button_text_style.clone(),
+                                ));
+                                for count in 1..=LocalPlayers::MAX {
+                                    let mut entity = parent.spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                size: Size::new(Val::Px(65.0), Val::Px(65.0)),
+                                                margin: UiRect::all(Val::Px(10.0)),
+                                                ..button_style.clone()
+                                            },
+                                            background_color: NORMAL_BUTTON.into(),
+                                            ..default()
+                                        },
+                                        LocalPlayers(count),
+                                    ));
+                                    entity.with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            count.to_string(),
+                                            button_text_style.clone(),
+                                        ));
+                                    });
+                                    if local_players.0 == count {
+                                        entity.insert(SelectedOption);
+                                    }
+                                }
+                            });
+                        // Game modes added by mods show up here too.
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(960.0), Val::Auto),
+                                    flex_wrap: FlexWrap::Wrap,
+                                    align_items: AlignItems::Center,
+                                    justify_content: JustifyContent::Center,
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "Mode",
+                                    button_text_style.clone(),
+                                ));
+                                for (index, mode) in modes.modes.iter().enumerate() {
+                                    let mut entity = parent.spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                size: Size::new(Val::Auto, Val::Px(65.0)),
+                                                margin: UiRect::all(Val::Px(10.0)),
+                                                padding: UiRect::horizontal(Val::Px(15.0)),
+                                                ..button_style.clone()
+                                            },
+                                            background_color: NORMAL_BUTTON.into(),
+                                            ..default()
+                                        },
+                                        ModeChoice(index),
+                                    ));
+                                    entity.with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            mode.name.clone(),
+                                            button_text_style.clone(),
+                                        ));
+                                    });
+                                    if mode_choice.0 == index {
+                                        entity.insert(SelectedOption);
+                                    }
+                                }
+                            });
+                    }
                     parent
                         .spawn((
                             ButtonBundle {
@@ -319,7 +575,11 @@ fn settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     for (action, text) in [
                         (MenuButtonAction::SettingsDisplay, "Display"),
                         (MenuButtonAction::SettingsSound, "Sound"),
-                        (MenuButtonAction::SettingsFov, "Fov"),
+                        (MenuButtonAction::SettingsFov, "Camera"),
+                        (MenuButtonAction::SettingsDifficulty, "Difficulty"),
+                        (MenuButtonAction::SettingsHud, "HUD"),
+                        (MenuButtonAction::SettingsBots, "Bots"),
+                        (MenuButtonAction::SettingsMods, "Mods"),
                         (MenuButtonAction::BackToMainMenu, "Back"),
                     ] {
                         parent
@@ -439,7 +699,11 @@ fn display_settings_menu_setup(
         });
 }
 
-fn fov_settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, fov: Res<Fov>) {
+fn difficulty_settings_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    difficulty: Res<Difficulty>,
+) {
     let button_style = Style {
         size: Size::new(Val::Px(200.0), Val::Px(65.0)),
         margin: UiRect::all(Val::Px(20.0)),
@@ -464,7 +728,7 @@ fn fov_settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer
                 },
                 ..default()
             },
-            OnFovSettingsMenuScreen,
+            OnDifficultySettingsMenuScreen,
         ))
         .with_children(|parent| {
             parent
@@ -488,19 +752,28 @@ fn fov_settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer
                             ..default()
                         })
                         .with_children(|parent| {
-                            parent
-                                .spawn(TextBundle::from_section("Fov", button_text_style.clone()));
-                            for fov_setting in [0, 1, 2, 3, 4, 5, 6, 7, 8, 9] {
+                            parent.spawn(TextBundle::from_section(
+                                "Difficulty",
+                                button_text_style.clone(),
+                            ));
+                            for difficulty_setting in
+                                [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard]
+                            {
                                 let mut entity = parent.spawn(ButtonBundle {
                                     style: Style {
-                                        size: Size::new(Val::Px(30.0), Val::Px(65.0)),
+                                        size: Size::new(Val::Px(150.0), Val::Px(65.0)),
                                         ..button_style.clone()
                                     },
                                     background_color: NORMAL_BUTTON.into(),
                                     ..default()
                                 });
-                                entity.insert(Fov(fov_setting));
-                                if *fov == Fov(fov_setting) {
+                                entity.insert(difficulty_setting).with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        format!("{difficulty_setting:?}"),
+                                        button_text_style.clone(),
+                                    ));
+                                });
+                                if *difficulty == difficulty_setting {
                                     entity.insert(SelectedOption);
                                 }
                             }
@@ -521,10 +794,122 @@ fn fov_settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer
         });
 }
 
-fn sound_settings_menu_setup(
+fn bot_settings_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bot_count: Res<BotCount>,
+    bot_skill: Res<BotSkill>,
+) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+    let row = NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: Color::BLUE.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnBotSettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::BLUE.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(row.clone()).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Bots", button_text_style.clone()));
+                        for count in 0..=BotCount::MAX {
+                            let mut entity = parent.spawn(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(65.0), Val::Px(65.0)),
+                                    ..button_style.clone()
+                                },
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            });
+                            entity.insert(BotCount(count)).with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    count.to_string(),
+                                    button_text_style.clone(),
+                                ));
+                            });
+                            if bot_count.0 == count {
+                                entity.insert(SelectedOption);
+                            }
+                        }
+                    });
+                    parent.spawn(row).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Skill", button_text_style.clone()));
+                        for skill in [BotSkill::Easy, BotSkill::Normal, BotSkill::Hard] {
+                            let mut entity = parent.spawn(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(150.0), Val::Px(65.0)),
+                                    ..button_style.clone()
+                                },
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            });
+                            entity.insert(skill).with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    format!("{skill:?}"),
+                                    button_text_style.clone(),
+                                ));
+                            });
+                            if *bot_skill == skill {
+                                entity.insert(SelectedOption);
+                            }
+                        }
+                    });
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToSettings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+fn hud_settings_menu_setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    volume: Res<Volume>,
+    hud_settings: Res<HudSettings>,
 ) {
     let button_style = Style {
         size: Size::new(Val::Px(200.0), Val::Px(65.0)),
@@ -550,7 +935,7 @@ fn sound_settings_menu_setup(
                 },
                 ..default()
             },
-            OnSoundSettingsMenuScreen,
+            OnHudSettingsMenuScreen,
         ))
         .with_children(|parent| {
             parent
@@ -564,31 +949,35 @@ fn sound_settings_menu_setup(
                     ..default()
                 })
                 .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("HUD", button_text_style.clone()));
                     parent
                         .spawn(NodeBundle {
                             style: Style {
-                                align_items: AlignItems::Center,
+                                size: Size::new(Val::Px(960.0), Val::Auto),
+                                flex_wrap: FlexWrap::Wrap,
+                                justify_content: JustifyContent::Center,
                                 ..default()
                             },
                             background_color: Color::BLUE.into(),
                             ..default()
                         })
                         .with_children(|parent| {
-                            parent.spawn(TextBundle::from_section(
-                                "Volume",
-                                button_text_style.clone(),
-                            ));
-                            for volume_setting in [0, 1, 2, 3, 4, 5, 6, 7, 8, 9] {
+                            for widget in HudWidget::ALL {
                                 let mut entity = parent.spawn(ButtonBundle {
                                     style: Style {
-                                        size: Size::new(Val::Px(30.0), Val::Px(65.0)),
+                                        size: Size::new(Val::Px(280.0), Val::Px(65.0)),
                                         ..button_style.clone()
                                     },
                                     background_color: NORMAL_BUTTON.into(),
                                     ..default()
                                 });
-                                entity.insert(Volume(volume_setting));
-                                if *volume == Volume(volume_setting) {
+                                entity.insert(widget).with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        format!("{widget:?}"),
+                                        button_text_style.clone(),
+                                    ));
+                                });
+                                if hud_settings.is_visible(widget) {
                                     entity.insert(SelectedOption);
                                 }
                             }
@@ -609,36 +998,548 @@ fn sound_settings_menu_setup(
         });
 }
 
-fn menu_action(
-    interaction_query: Query<
-        (&Interaction, &MenuButtonAction),
-        (Changed<Interaction>, With<Button>),
-    >,
-    mut app_exit_events: EventWriter<AppExit>,
-    mut menu_state: ResMut<NextState<MenuState>>,
-    mut game_state: ResMut<NextState<GameState>>,
-) {
-    for (interaction, menu_button_action) in &interaction_query {
-        if *interaction == Interaction::Clicked {
-            match menu_button_action {
-                MenuButtonAction::Quit => app_exit_events.send(AppExit),
-                MenuButtonAction::Play => {
-                    game_state.set(GameState::NewGame);
-                    menu_state.set(MenuState::Disabled);
-                }
-                MenuButtonAction::Resume => {
-                    game_state.set(GameState::InGame);
-                    menu_state.set(MenuState::Disabled);
-                }
-                MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
-                MenuButtonAction::SettingsDisplay => {
-                    menu_state.set(MenuState::SettingsDisplay);
-                }
-                MenuButtonAction::SettingsFov => {
-                    menu_state.set(MenuState::SettingsFov);
-                }
-                MenuButtonAction::SettingsSound => {
-                    menu_state.set(MenuState::SettingsSound);
+fn mods_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, mods: Res<Mods>) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_text_style = TextStyle {
+        font: font.clone(),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+    let detail_style = TextStyle {
+        font,
+        font_size: 24.0,
+        color: TEXT_COLOR,
+    };
+    let failed_style = TextStyle {
+        color: Color::rgb(1.0, 0.5, 0.4),
+        ..detail_style.clone()
+    };
+    let entry_style = Style {
+        flex_direction: FlexDirection::Column,
+        size: Size::new(Val::Px(800.0), Val::Auto),
+        margin: UiRect::all(Val::Px(10.0)),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnModsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::BLUE.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Mods", button_text_style.clone()));
+                    if mods.mods.is_empty() {
+                        parent.spawn(
+                            TextBundle::from_section(
+                                "No mods installed. Each mod goes in its own folder under mods/, with a mod.ron describing it.",
+                                detail_style.clone(),
+                            )
+                            .with_style(entry_style.clone()),
+                        );
+                    }
+                    for info in &mods.mods {
+                        parent
+                            .spawn(NodeBundle {
+                                style: entry_style.clone(),
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                let manifest = &info.manifest;
+                                let mut title = info.title().to_string();
+                                if !manifest.version.is_empty() {
+                                    title += &format!(" {}", manifest.version);
+                                }
+                                if !manifest.author.is_empty() {
+                                    title += &format!(" by {}", manifest.author);
+                                }
+                                parent.spawn(TextBundle::from_section(title, button_text_style.clone()));
+                                if !manifest.description.is_empty() {
+                                    parent.spawn(TextBundle::from_section(
+                                        manifest.description.clone(),
+                                        detail_style.clone(),
+                                    ));
+                                }
+                                match &info.status {
+                                    ModStatus::Loaded => {
+                                        parent.spawn(TextBundle::from_section("Loaded", detail_style.clone()));
+                                    }
+                                    ModStatus::Failed(reason) => {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Not loaded: {reason}"),
+                                            failed_style.clone(),
+                                        ));
+                                    }
+                                }
+                                for issue in &info.issues {
+                                    parent.spawn(TextBundle::from_section(issue.clone(), failed_style.clone()));
+                                }
+                            });
+                    }
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToSettings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+fn campaign_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    campaign: Res<Campaign>,
+    progress: Res<CampaignProgress>,
+) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_text_style = TextStyle {
+        font: font.clone(),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+    let detail_style = TextStyle {
+        font,
+        font_size: 24.0,
+        color: TEXT_COLOR,
+    };
+    let locked_style = TextStyle {
+        color: Color::GRAY,
+        ..button_text_style.clone()
+    };
+    let failed_style = TextStyle {
+        color: Color::rgb(1.0, 0.5, 0.4),
+        ..detail_style.clone()
+    };
+    let entry_style = Style {
+        flex_direction: FlexDirection::Column,
+        size: Size::new(Val::Px(800.0), Val::Auto),
+        margin: UiRect::all(Val::Px(10.0)),
+        padding: UiRect::all(Val::Px(5.0)),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnCampaignMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::BLUE.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Campaign", button_text_style.clone()));
+                    if campaign.levels.is_empty() {
+                        parent.spawn(
+                            TextBundle::from_section(
+                                "No levels found. The campaign lists them in levels/campaign.ron.",
+                                detail_style.clone(),
+                            )
+                            .with_style(entry_style.clone()),
+                        );
+                    }
+                    for (index, entry) in campaign.levels.iter().enumerate() {
+                        let unlocked = campaign.is_unlocked(index, &progress);
+                        let mut title = format!("{}. {}", index + 1, entry.title());
+                        if progress.is_completed(&entry.path) {
+                            title += " - done";
+                        } else if !unlocked {
+                            title += " - locked";
+                        }
+                        let node = NodeBundle {
+                            style: entry_style.clone(),
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        };
+                        // Only levels that loaded and are unlocked can be picked.
+                        let mut row = match &entry.level {
+                            Ok(_) if unlocked => parent.spawn((
+                                ButtonBundle {
+                                    style: node.style,
+                                    background_color: node.background_color,
+                                    ..default()
+                                },
+                                LevelButton(index),
+                            )),
+                            _ => parent.spawn(node),
+                        };
+                        row.with_children(|parent| {
+                            let style = if unlocked { &button_text_style } else { &locked_style };
+                            parent.spawn(TextBundle::from_section(title, style.clone()));
+                            match &entry.level {
+                                Ok(level) if !level.description.is_empty() => {
+                                    parent.spawn(TextBundle::from_section(
+                                        level.description.clone(),
+                                        detail_style.clone(),
+                                    ));
+                                }
+                                Ok(_) => {}
+                                Err(err) => {
+                                    parent.spawn(TextBundle::from_section(
+                                        format!("Can't be played, {err}"),
+                                        failed_style.clone(),
+                                    ));
+                                }
+                            }
+                        });
+                    }
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToMainMenu,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+fn fov_settings_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    fov: Res<Fov>,
+    camera: Res<CameraView>,
+) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnFovSettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::BLUE.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::BLUE.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn(TextBundle::from_section("Fov", button_text_style.clone()));
+                            for fov_setting in [0, 1, 2, 3, 4, 5, 6, 7, 8, 9] {
+                                let mut entity = parent.spawn(ButtonBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Px(30.0), Val::Px(65.0)),
+                                        ..button_style.clone()
+                                    },
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                });
+                                entity.insert(Fov(fov_setting));
+                                if *fov == Fov(fov_setting) {
+                                    entity.insert(SelectedOption);
+                                }
+                            }
+                        });
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::BLUE.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("View", button_text_style.clone()));
+                            for (view, text) in [
+                                (CameraView::FirstPerson, "First person"),
+                                (CameraView::ThirdPerson, "Third person"),
+                            ] {
+                                let mut entity = parent.spawn(ButtonBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Px(250.0), Val::Px(65.0)),
+                                        ..button_style.clone()
+                                    },
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                });
+                                entity.insert(view).with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        text,
+                                        button_text_style.clone(),
+                                    ));
+                                });
+                                if *camera == view {
+                                    entity.insert(SelectedOption);
+                                }
+                            }
+                        });
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToSettings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+fn sound_settings_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    audio_settings: Res<AudioSettings>,
+) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnSoundSettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::BLUE.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for bus in AudioBus::ALL {
+                        let channel = audio_settings.channel(bus);
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                background_color: Color::BLUE.into(),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(
+                                    TextBundle::from_section(bus.label(), button_text_style.clone())
+                                        .with_style(Style {
+                                            size: Size::new(Val::Px(180.0), Val::Auto),
+                                            margin: UiRect::horizontal(Val::Px(20.0)),
+                                            ..default()
+                                        }),
+                                );
+                                for level in 0..=9 {
+                                    let mut entity = parent.spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                size: Size::new(Val::Px(30.0), Val::Px(50.0)),
+                                                margin: UiRect::all(Val::Px(8.0)),
+                                                ..button_style.clone()
+                                            },
+                                            background_color: NORMAL_BUTTON.into(),
+                                            ..default()
+                                        },
+                                        VolumeButton { bus, level },
+                                    ));
+                                    if channel.level == level {
+                                        entity.insert(SelectedOption);
+                                    }
+                                }
+                                let mut entity = parent.spawn((
+                                    ButtonBundle {
+                                        style: Style {
+                                            size: Size::new(Val::Px(120.0), Val::Px(50.0)),
+                                            margin: UiRect::all(Val::Px(8.0)),
+                                            ..button_style.clone()
+                                        },
+                                        background_color: NORMAL_BUTTON.into(),
+                                        ..default()
+                                    },
+                                    MuteButton(bus),
+                                ));
+                                entity.with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        "Mute",
+                                        button_text_style.clone(),
+                                    ));
+                                });
+                                if channel.muted {
+                                    entity.insert(SelectedOption);
+                                }
+                            });
+                    }
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToSettings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn menu_action(
+    interaction_query: Query<
+        (&Interaction, &MenuButtonAction),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut app_exit_events: EventWriter<AppExit>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut current_level: ResMut<CurrentLevel>,
+) {
+    for (interaction, menu_button_action) in &interaction_query {
+        if *interaction == Interaction::Clicked {
+            match menu_button_action {
+                MenuButtonAction::Quit => app_exit_events.send(AppExit),
+                MenuButtonAction::Play => {
+                    *current_level = CurrentLevel::default();
+                    game_state.set(GameState::NewGame);
+                    menu_state.set(MenuState::Disabled);
+                }
+                MenuButtonAction::Campaign => menu_state.set(MenuState::Campaign),
+                MenuButtonAction::Resume => {
+                    game_state.set(GameState::InGame);
+                    menu_state.set(MenuState::Disabled);
+                }
+                MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
+                MenuButtonAction::SettingsDisplay => {
+                    menu_state.set(MenuState::SettingsDisplay);
+                }
+                MenuButtonAction::SettingsFov => {
+                    menu_state.set(MenuState::SettingsFov);
+                }
+                MenuButtonAction::SettingsSound => {
+                    menu_state.set(MenuState::SettingsSound);
+                }
+                MenuButtonAction::SettingsDifficulty => {
+                    menu_state.set(MenuState::SettingsDifficulty);
+                }
+                MenuButtonAction::SettingsHud => {
+                    menu_state.set(MenuState::SettingsHud);
+                }
+                MenuButtonAction::SettingsBots => {
+                    menu_state.set(MenuState::SettingsBots);
+                }
+                MenuButtonAction::SettingsMods => {
+                    menu_state.set(MenuState::SettingsMods);
                 }
                 MenuButtonAction::BackToMainMenu => menu_state.set(MenuState::Main),
                 MenuButtonAction::BackToSettings => {
@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod menu;
 pub mod splash;
\ No newline at end of file
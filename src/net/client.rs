@@ -0,0 +1,584 @@
+use crate::game::archetypes::{spawn_archetype, Archetype, Archetypes, BLUE_BALL, RED_BALL};
+use crate::game::sky::TimeOfDay;
+use crate::net::net::{avatar_bundle, round_result};
+use crate::net::protocol::{
+    ClientMessage, HitInput, NetSocket, PlayerInput, ServerMessage, Snapshot,
+};
+use crate::player::player::PlayerMovement;
+use crate::prelude::*;
+use bevy::app::AppExit;
+use bevy_rapier3d::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::net::SocketAddr;
+
+const JOIN_INTERVAL: f32 = 0.5;
+const SERVER_TIMEOUT: f32 = 5.0;
+/// Other players and balls are drawn this far in the past, so there is
+/// usually a newer snapshot to move towards.
+const INTERPOLATION_DELAY: f32 = 0.1;
+/// Prediction errors larger than this snap straight to the server's position.
+const SNAP_DISTANCE: f32 = 3.0;
+/// Share of a smaller prediction error corrected on each snapshot.
+const CORRECTION: f32 = 0.2;
+/// The round clock is resynchronised when it drifts this far from the
+/// server's, which keeps the moving platforms lined up.
+const MAX_CLOCK_DRIFT: f32 = 0.25;
+/// Inputs kept for prediction while the server isn't acknowledging them.
+const MAX_PENDING_INPUTS: usize = 120;
+const MAX_SAMPLES: usize = 20;
+
+#[derive(Resource)]
+pub struct Client {
+    server: SocketAddr,
+    id: Option<u32>,
+    sequence: u32,
+    /// Inputs the server hasn't acknowledged yet and how far each moved us
+    /// here.
+    pending: VecDeque<(u32, Vec3)>,
+    parts: SnapshotParts,
+    snapshot: Option<Snapshot>,
+    /// The latest server clock and the local time it arrived at.
+    server_clock: Option<(f32, f32)>,
+    last_heard: f32,
+    since_join: f32,
+    /// Set once the server has said it is full, to stop asking to join.
+    turned_away: bool,
+    connection_lost: bool,
+    balls: HashMap<u64, Entity>,
+    avatars: HashMap<u32, Entity>,
+}
+
+impl Client {
+    pub fn new(server: SocketAddr) -> Self {
+        Client {
+            server,
+            id: None,
+            sequence: 0,
+            pending: VecDeque::new(),
+            parts: SnapshotParts::default(),
+            snapshot: None,
+            server_clock: None,
+            last_heard: 0.0,
+            since_join: JOIN_INTERVAL,
+            turned_away: false,
+            connection_lost: false,
+            balls: HashMap::new(),
+            avatars: HashMap::new(),
+        }
+    }
+}
+
+/// Puts snapshots sent in several parts back together.
+#[derive(Default)]
+struct SnapshotParts {
+    newest: u32,
+    partial: Option<(Snapshot, Vec<u16>)>,
+}
+
+impl SnapshotParts {
+    /// Returns the snapshot once this completes it, unless a newer one has
+    /// already come in.
+    fn add(&mut self, part: Snapshot) -> Option<Snapshot> {
+        if part.tick <= self.newest {
+            return None;
+        }
+        let newer = self
+            .partial
+            .as_ref()
+            .is_none_or(|(snapshot, _)| part.tick > snapshot.tick);
+        if newer {
+            // Whatever arrived of an older snapshot is no use any more.
+            let number = part.part;
+            self.partial = Some((part, vec![number]));
+        } else if let Some((snapshot, received)) =
+            self.partial.as_mut().filter(|(snapshot, received)| {
+                snapshot.tick == part.tick && !received.contains(&part.part)
+            })
+        {
+            received.push(part.part);
+            snapshot.balls.extend(part.balls);
+        } else {
+            return None;
+        }
+        let complete = self
+            .partial
+            .as_ref()
+            .is_some_and(|(snapshot, received)| received.len() >= snapshot.parts as usize);
+        if !complete {
+            return None;
+        }
+        let (snapshot, _) = self.partial.take()?;
+        self.newest = snapshot.tick;
+        Some(snapshot)
+    }
+}
+
+/// Recent server positions of something the server simulates, tagged with the
+/// server clock.
+#[derive(Component, Default)]
+struct Interpolated(VecDeque<(f32, Vec3, Quat)>);
+
+impl Interpolated {
+    fn push(&mut self, clock: f32, position: Vec3, rotation: Quat) {
+        self.0.push_back((clock, position, rotation));
+        if self.0.len() > MAX_SAMPLES {
+            self.0.pop_front();
+        }
+    }
+
+    fn sample(&self, clock: f32) -> Option<(Vec3, Quat)> {
+        let after = self.0.iter().position(|sample| sample.0 >= clock);
+        let (to_clock, to_position, to_rotation) =
+            *self.0.get(after.unwrap_or(self.0.len().checked_sub(1)?))?;
+        let Some(&(from_clock, from_position, from_rotation)) = after
+            .and_then(|after| after.checked_sub(1))
+            .and_then(|before| self.0.get(before))
+        else {
+            return Some((to_position, to_rotation));
+        };
+        let t = ((clock - from_clock) / (to_clock - from_clock)).clamp(0.0, 1.0);
+        Some((
+            from_position.lerp(to_position, t),
+            from_rotation.slerp(to_rotation, t),
+        ))
+    }
+}
+
+fn join(time: Res<Time>, mut client: ResMut<Client>, socket: Res<NetSocket>) {
+    if client.id.is_some() || client.turned_away {
+        return;
+    }
+    client.since_join += time.delta_seconds();
+    if client.since_join >= JOIN_INTERVAL {
+        client.since_join = 0.0;
+        socket.send(&ClientMessage::Join, client.server);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_messages(
+    time: Res<Time>,
+    mut client: ResMut<Client>,
+    mut socket: ResMut<NetSocket>,
+    mut mode: ResMut<GameMode>,
+    mut world_seed: ResMut<WorldSeed>,
+    mut game_info: ResMut<GameInfo>,
+    mut notifications: EventWriter<Notification>,
+    mut round_over: EventWriter<RoundOver>,
+) {
+    let now = time.elapsed_seconds();
+    for (message, address) in socket.receive::<ServerMessage>() {
+        if address != client.server {
+            continue;
+        }
+        client.last_heard = now;
+        client.connection_lost = false;
+        match message {
+            ServerMessage::Welcome {
+                id,
+                mode: server_mode,
+                terrain_height,
+            } => {
+                if client.id.is_none() {
+                    info!("Joined {address} as player {id}");
+                    notifications.send(Notification(format!("Joined as player {id}")));
+                }
+                client.id = Some(id);
+                *mode = server_mode;
+                world_seed.pinned_height = Some(terrain_height);
+            }
+            ServerMessage::Snapshot(part) => {
+                if let Some(snapshot) = client.parts.add(part) {
+                    client.snapshot = Some(snapshot);
+                }
+            }
+            ServerMessage::RoundOver { winner, score } => {
+                let won = Some(winner) == client.id;
+                if won {
+                    game_info.wins += 1;
+                }
+                round_over.send(RoundOver { won });
+                notifications.send(Notification(round_result(
+                    winner,
+                    client.id.unwrap_or_default(),
+                    score,
+                )));
+            }
+            ServerMessage::Notice(notice) => notifications.send(Notification(notice)),
+            ServerMessage::Full => {
+                if !client.turned_away {
+                    warn!("{address} is full");
+                    notifications.send(Notification("The server is full".into()));
+                }
+                client.turned_away = true;
+            }
+        }
+    }
+    if client.id.is_some() && !client.connection_lost && now - client.last_heard > SERVER_TIMEOUT {
+        client.connection_lost = true;
+        warn!(
+            "No word from {} for {SERVER_TIMEOUT} seconds",
+            client.server
+        );
+        notifications.send(Notification("Lost connection to the server".into()));
+    }
+}
+
+/// Adds a server position to the entity mirroring `id`, returning whether
+/// there was one.
+fn push_sample<K: Hash + Eq>(
+    entities: &HashMap<K, Entity>,
+    mirrored: &mut Query<&mut Interpolated>,
+    id: K,
+    clock: f32,
+    position: Vec3,
+    rotation: Quat,
+) -> bool {
+    let Some(mut interpolated) = entities
+        .get(&id)
+        .and_then(|entity| mirrored.get_mut(*entity).ok())
+    else {
+        return false;
+    };
+    interpolated.push(clock, position, rotation);
+    true
+}
+
+/// Despawns the mirrors of anything no longer in the snapshot.
+fn forget_missing<K: Hash + Eq>(
+    commands: &mut Commands,
+    entities: &mut HashMap<K, Entity>,
+    seen: &HashSet<K>,
+) {
+    entities.retain(|id, entity| {
+        let keep = seen.contains(id);
+        if !keep {
+            if let Some(entity) = commands.get_entity(*entity) {
+                entity.despawn_recursive();
+            }
+        }
+        keep
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_snapshot(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut client: ResMut<Client>,
+    mut world_seed: ResMut<WorldSeed>,
+    mut game_info: ResMut<GameInfo>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut round_timer: Query<&mut FPSTimer>,
+    mut player: Query<&mut Transform, With<Player>>,
+    mut mirrored: Query<&mut Interpolated>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    archetypes: Res<Archetypes>,
+    archetype_assets: Res<Assets<Archetype>>,
+) {
+    let Some(snapshot) = client.snapshot.take() else {
+        return;
+    };
+    if snapshot.seed != world_seed.current || snapshot.terrain_height != world_seed.height {
+        // Load the server's map; its snapshots apply once that's done.
+        if world_seed.next != Some(snapshot.seed)
+            || world_seed.pinned_height != Some(snapshot.terrain_height)
+        {
+            world_seed.next = Some(snapshot.seed);
+            world_seed.pinned_height = Some(snapshot.terrain_height);
+            game_info.is_won = true;
+        }
+        return;
+    }
+    client.server_clock = Some((snapshot.clock, time.elapsed_seconds()));
+    time_of_day.0 = snapshot.hour;
+    if let Ok(mut timer) = round_timer.get_single_mut() {
+        if (timer.elapsed - snapshot.round_time).abs() > MAX_CLOCK_DRIFT {
+            timer.elapsed = snapshot.round_time;
+        }
+    }
+
+    // Replay the inputs the server hasn't seen on top of where it last put us.
+    client
+        .pending
+        .retain(|(sequence, _)| *sequence > snapshot.ack);
+    let me = snapshot
+        .players
+        .iter()
+        .find(|state| Some(state.id) == client.id);
+    if let (Some(me), Ok(mut transform)) = (me, player.get_single_mut()) {
+        game_info.collected = me.score;
+        let predicted = Vec3::from_array(me.position)
+            + client
+                .pending
+                .iter()
+                .map(|(_, movement)| *movement)
+                .sum::<Vec3>();
+        let error = predicted - transform.translation;
+        if error.length() > SNAP_DISTANCE {
+            transform.translation = predicted;
+        } else {
+            transform.translation += error * CORRECTION;
+        }
+    }
+
+    let client = &mut *client;
+    let mut seen = HashSet::new();
+    for state in &snapshot.players {
+        if Some(state.id) == client.id {
+            continue;
+        }
+        seen.insert(state.id);
+        let position = Vec3::from_array(state.position);
+        let rotation = Quat::from_array(state.rotation);
+        if !push_sample(
+            &client.avatars,
+            &mut mirrored,
+            state.id,
+            snapshot.clock,
+            position,
+            rotation,
+        ) {
+            let mut interpolated = Interpolated::default();
+            interpolated.push(snapshot.clock, position, rotation);
+            let avatar = commands
+                .spawn(avatar_bundle(
+                    &mut meshes,
+                    &mut materials,
+                    state.id,
+                    position,
+                ))
+                .insert((RigidBody::KinematicPositionBased, interpolated))
+                .id();
+            client.avatars.insert(state.id, avatar);
+        }
+    }
+    forget_missing(&mut commands, &mut client.avatars, &seen);
+
+    let mut seen = HashSet::new();
+    for state in &snapshot.balls {
+        seen.insert(state.id);
+        let position = Vec3::from_array(state.position);
+        if push_sample(
+            &client.balls,
+            &mut mirrored,
+            state.id,
+            snapshot.clock,
+            position,
+            Quat::IDENTITY,
+        ) {
+            continue;
+        }
+        let mut interpolated = Interpolated::default();
+        interpolated.push(snapshot.clock, position, Quat::IDENTITY);
+        let name = if state.blue { BLUE_BALL } else { RED_BALL };
+        let archetype = archetypes.get(name, &archetype_assets).unwrap_or_default();
+        let mut ball = spawn_archetype(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            name,
+            &archetype,
+            Transform::from_translation(position),
+        );
+        if !state.blue {
+            ball.insert(Reboot);
+        }
+        ball.insert((RigidBody::KinematicPositionBased, interpolated));
+        client.balls.insert(state.id, ball.id());
+    }
+    forget_missing(&mut commands, &mut client.balls, &seen);
+}
+
+fn interpolate(
+    time: Res<Time>,
+    client: Res<Client>,
+    mut mirrored: Query<(&Interpolated, &mut Transform)>,
+) {
+    let Some((server_clock, received)) = client.server_clock else {
+        return;
+    };
+    let clock = server_clock + time.elapsed_seconds() - received - INTERPOLATION_DELAY;
+    for (interpolated, mut transform) in &mut mirrored {
+        if let Some((position, rotation)) = interpolated.sample(clock) {
+            transform.translation = position;
+            transform.rotation = rotation;
+        }
+    }
+}
+
+fn send_input(
+    mut client: ResMut<Client>,
+    socket: Res<NetSocket>,
+    mut movement: ResMut<PlayerMovement>,
+    player: Query<(Entity, &Transform), With<Player>>,
+    mut fired: EventReader<HitFired>,
+) {
+    let Ok((entity, transform)) = player.get_single() else {
+        return;
+    };
+    let hit = fired
+        .iter()
+        .filter(|hit| hit.shooter == entity)
+        .last()
+        .map(|hit| HitInput {
+            charge: hit.charge,
+            shockwave: hit.shockwave,
+            direction: hit.direction.to_array(),
+        });
+    let movement = std::mem::take(&mut *movement);
+    if client.id.is_none() {
+        return;
+    }
+    client.sequence += 1;
+    let sequence = client.sequence;
+    client.pending.push_back((sequence, movement.step));
+    if client.pending.len() > MAX_PENDING_INPUTS {
+        client.pending.pop_front();
+    }
+    let input = PlayerInput {
+        sequence,
+        seconds: movement.seconds,
+        axes: movement.input.direction.to_array(),
+        jump: movement.input.ascend,
+        jump_pressed: movement.input.ascend_just_pressed,
+        descend: movement.input.descend,
+        rotation: transform.rotation.to_array(),
+        hit,
+    };
+    socket.send(&ClientMessage::Input(input), client.server);
+}
+
+fn leave(mut exit: EventReader<AppExit>, client: Res<Client>, socket: Res<NetSocket>) {
+    if exit.iter().next().is_some() {
+        socket.send(&ClientMessage::Leave, client.server);
+    }
+}
+
+pub struct ClientPlugin;
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems((join, receive_messages).distributive_run_if(resource_exists::<Client>()))
+            .add_systems(
+                (
+                    apply_snapshot.after(receive_messages),
+                    interpolate.after(apply_snapshot),
+                    send_input,
+                )
+                    .distributive_run_if(resource_exists::<Client>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(
+                leave
+                    .run_if(resource_exists::<Client>())
+                    .in_base_set(CoreSet::Last),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::protocol::BallState;
+
+    fn part(tick: u32, part: u16, parts: u16) -> Snapshot {
+        Snapshot {
+            tick,
+            clock: tick as f32,
+            seed: 1,
+            terrain_height: 32,
+            round_time: 0.0,
+            hour: 12.0,
+            ack: 0,
+            part,
+            parts,
+            players: Vec::new(),
+            balls: vec![BallState {
+                id: part as u64,
+                blue: false,
+                position: [0.0; 3],
+            }],
+        }
+    }
+
+    fn ball_ids(snapshot: Option<Snapshot>) -> Option<Vec<u64>> {
+        snapshot.map(|snapshot| snapshot.balls.iter().map(|ball| ball.id).collect())
+    }
+
+    #[test]
+    fn joins_snapshot_parts() {
+        let mut parts = SnapshotParts::default();
+        assert_eq!(ball_ids(parts.add(part(1, 0, 1))), Some(vec![0]));
+        assert_eq!(ball_ids(parts.add(part(2, 1, 3))), None);
+        assert_eq!(ball_ids(parts.add(part(2, 1, 3))), None);
+        assert_eq!(ball_ids(parts.add(part(2, 0, 3))), None);
+        assert_eq!(ball_ids(parts.add(part(2, 2, 3))), Some(vec![1, 0, 2]));
+    }
+
+    #[test]
+    fn drops_stale_snapshot_parts() {
+        let mut parts = SnapshotParts::default();
+        assert_eq!(ball_ids(parts.add(part(3, 0, 2))), None);
+        // A newer snapshot replaces the unfinished one.
+        assert_eq!(ball_ids(parts.add(part(4, 0, 2))), None);
+        assert_eq!(ball_ids(parts.add(part(3, 1, 2))), None);
+        assert_eq!(ball_ids(parts.add(part(4, 1, 2))), Some(vec![0, 1]));
+        // Nothing older than the snapshot already returned is used.
+        assert_eq!(ball_ids(parts.add(part(4, 0, 1))), None);
+        assert_eq!(ball_ids(parts.add(part(2, 0, 1))), None);
+    }
+
+    fn interpolated(samples: &[(f32, f32)]) -> Interpolated {
+        let mut interpolated = Interpolated::default();
+        for &(clock, x) in samples {
+            interpolated.push(clock, Vec3::X * x, Quat::from_rotation_y(x));
+        }
+        interpolated
+    }
+
+    fn position(interpolated: &Interpolated, clock: f32) -> Option<f32> {
+        interpolated.sample(clock).map(|(position, _)| position.x)
+    }
+
+    #[test]
+    fn sample_without_positions() {
+        assert_eq!(position(&interpolated(&[]), 1.0), None);
+    }
+
+    #[test]
+    fn sample_with_one_position() {
+        let interpolated = interpolated(&[(1.0, 4.0)]);
+        assert_eq!(position(&interpolated, 0.0), Some(4.0));
+        assert_eq!(position(&interpolated, 2.0), Some(4.0));
+    }
+
+    #[test]
+    fn sample_between_positions() {
+        let interpolated = interpolated(&[(1.0, 0.0), (2.0, 1.0), (3.0, 3.0)]);
+        assert_eq!(position(&interpolated, 1.5), Some(0.5));
+        assert_eq!(position(&interpolated, 2.0), Some(1.0));
+        assert_eq!(position(&interpolated, 2.25), Some(1.5));
+        let (_, rotation) = interpolated.sample(2.5).unwrap();
+        assert!(rotation.angle_between(Quat::from_rotation_y(2.0)) < 1e-4);
+    }
+
+    #[test]
+    fn sample_outside_positions() {
+        let interpolated = interpolated(&[(1.0, 2.0), (2.0, 5.0)]);
+        assert_eq!(position(&interpolated, 0.0), Some(2.0));
+        assert_eq!(position(&interpolated, 9.0), Some(5.0));
+    }
+
+    #[test]
+    fn keeps_recent_positions() {
+        let samples: Vec<(f32, f32)> = (0..MAX_SAMPLES * 2)
+            .map(|i| (i as f32, i as f32))
+            .collect();
+        let interpolated = interpolated(&samples);
+        assert_eq!(interpolated.0.len(), MAX_SAMPLES);
+        // The first half were dropped.
+        assert_eq!(position(&interpolated, 0.0), Some(MAX_SAMPLES as f32));
+    }
+}
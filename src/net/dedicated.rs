@@ -0,0 +1,205 @@
+use crate::cli::{parse_mode, parse_player_limit, Args, ArgsError, LaunchOptions};
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+const CONFIG_PATH: &str = "server.ron";
+/// Terrain heights the map generator handles, from the lowest to the highest
+/// display quality.
+const TERRAIN_HEIGHTS: RangeInclusive<u32> = 8..=64;
+
+pub const USAGE: &str = "\
+Usage: sky-quest-server [OPTIONS]
+
+Options:
+  --config <file>         Read settings from the given file instead of server.ron
+  --port <port>           Listen on the given UDP port
+  --seed <n>              Start on the given map seed
+  --mode <mode>           Play classic or zen rounds
+  --max-players <n>       Limit how many players can join
+  --terrain-height <n>    Generate terrain this many blocks tall, 8 to 64
+  --tick-rate <hz>        Simulate this many frames a second
+  -h, --help              Print this help";
+
+/// Settings for the dedicated server, read from `server.ron`.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub port: u16,
+    /// The first round's map seed, random if unset. Later rounds always get
+    /// a fresh map.
+    pub seed: Option<u32>,
+    pub mode: GameMode,
+    pub max_players: usize,
+    /// How tall the terrain is generated, which every client uses too.
+    pub terrain_height: u32,
+    pub tick_rate: f64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            port: 7777,
+            seed: None,
+            mode: GameMode::Classic,
+            max_players: 8,
+            terrain_height: 32,
+            tick_rate: 60.0,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &PathBuf) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring invalid {}: {}", path.display(), err);
+                ServerConfig::default()
+            }),
+            Err(_) => ServerConfig::default(),
+        }
+    }
+
+    pub fn with_overrides(&self, options: &ServerOptions) -> ServerConfig {
+        ServerConfig {
+            port: options.port.unwrap_or(self.port),
+            seed: options.seed.or(self.seed),
+            mode: options.mode.unwrap_or(self.mode),
+            max_players: options.max_players.unwrap_or(self.max_players),
+            terrain_height: options.terrain_height.unwrap_or(self.terrain_height),
+            tick_rate: options.tick_rate.unwrap_or(self.tick_rate),
+        }
+    }
+
+    /// What the game is told to do, as if it had been launched to host.
+    pub fn launch_options(&self) -> LaunchOptions {
+        LaunchOptions {
+            seed: self.seed,
+            mode: Some(self.mode),
+            host: Some(self.port),
+            max_players: Some(self.max_players),
+            dedicated: true,
+            terrain_height: Some(
+                self.terrain_height
+                    .clamp(*TERRAIN_HEIGHTS.start(), *TERRAIN_HEIGHTS.end()),
+            ),
+            ..default()
+        }
+    }
+}
+
+/// Options given to the dedicated server on the command line, which take
+/// precedence over its config file.
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    pub config: PathBuf,
+    pub port: Option<u16>,
+    pub seed: Option<u32>,
+    pub mode: Option<GameMode>,
+    pub max_players: Option<usize>,
+    pub terrain_height: Option<u32>,
+    pub tick_rate: Option<f64>,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            config: CONFIG_PATH.into(),
+            port: None,
+            seed: None,
+            mode: None,
+            max_players: None,
+            terrain_height: None,
+            tick_rate: None,
+        }
+    }
+}
+
+impl ServerOptions {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut options = ServerOptions::default();
+        let mut args = Args(args.into_iter());
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => options.config = args.value(&arg)?.into(),
+                "--port" => options.port = Some(args.parse(&arg, "port")?),
+                "--seed" => options.seed = Some(args.parse(&arg, "seed")?),
+                "--mode" => options.mode = Some(parse_mode(&args.value(&arg)?)?),
+                "--max-players" => options.max_players = Some(parse_player_limit(&args.value(&arg)?)?),
+                "--terrain-height" => {
+                    let value = args.value(&arg)?;
+                    options.terrain_height = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|height| TERRAIN_HEIGHTS.contains(height))
+                            .ok_or_else(|| format!("invalid terrain height `{value}`"))?,
+                    );
+                }
+                "--tick-rate" => {
+                    let value = args.value(&arg)?;
+                    options.tick_rate = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+                            .ok_or_else(|| format!("invalid tick rate `{value}`"))?,
+                    );
+                }
+                "-h" | "--help" => return Err(ArgsError::Help),
+                _ => return Err(format!("unknown argument `{arg}`").into()),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ServerOptions, ArgsError> {
+        ServerOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse(&[
+            "--port",
+            "7000",
+            "--mode",
+            "zen",
+            "--max-players",
+            "4",
+            "--terrain-height",
+            "64",
+        ])
+        .unwrap();
+        assert_eq!(options.port, Some(7000));
+        assert_eq!(options.mode, Some(GameMode::Zen));
+        assert_eq!(options.max_players, Some(4));
+        assert_eq!(options.terrain_height, Some(64));
+        let config = ServerConfig::default().with_overrides(&options);
+        assert_eq!(config.launch_options().terrain_height, Some(64));
+    }
+
+    #[test]
+    fn asks_for_help() {
+        assert_eq!(parse(&["--port", "7000", "--help"]).unwrap_err(), ArgsError::Help);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for args in [
+            &["--terrain-height", "200"][..],
+            &["--tick-rate", "0"],
+            &["--max-players", "0"],
+            &["--port"],
+            &["--frobnicate"],
+        ] {
+            assert!(matches!(parse(args), Err(ArgsError::Invalid(_))), "{args:?}");
+        }
+    }
+}
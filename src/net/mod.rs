@@ -0,0 +1,6 @@
+pub mod client;
+pub mod dedicated;
+#[allow(clippy::module_inception)]
+pub mod net;
+pub mod protocol;
+pub mod server;
@@ -0,0 +1,85 @@
+use crate::cli::LaunchOptions;
+use crate::net::client::{Client, ClientPlugin};
+use crate::net::protocol::NetSocket;
+use crate::net::server::{Server, ServerPlugin};
+use crate::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::net::ToSocketAddrs;
+
+/// A ball standing in for another player, tinted by their id.
+pub fn avatar_bundle(
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    id: u32,
+    position: Vec3,
+) -> impl Bundle {
+    (
+        PbrBundle {
+            mesh: meshes.add(shape::UVSphere::default().into()),
+            material: materials.add(Color::hsl((id as f32 * 137.5) % 360.0, 0.7, 0.55).into()),
+            transform: Transform::from_translation(position),
+            ..default()
+        },
+        Collider::ball(1.0),
+        RemotePlayer { id },
+    )
+}
+
+pub fn round_result(winner: u32, me: u32, score: i32) -> String {
+    if winner == me {
+        format!("You won the round with {score} blue balls!")
+    } else {
+        format!("Player {winner} won the round with {score} blue balls")
+    }
+}
+
+/// Hosts or joins a game if asked to on the command line.
+fn open_socket(
+    mut commands: Commands,
+    options: Res<LaunchOptions>,
+    mut role: ResMut<NetRole>,
+    mut local_players: ResMut<LocalPlayers>,
+) {
+    if let Some(port) = options.host {
+        match NetSocket::bind(("0.0.0.0", port)) {
+            Ok(socket) => {
+                info!("Hosting on UDP port {port}");
+                commands.insert_resource(socket);
+                commands.insert_resource(Server::new(options.max_players));
+                *role = NetRole::Server;
+                *local_players = LocalPlayers(usize::from(!options.dedicated));
+            }
+            Err(err) => error!("Could not host on port {port}: {err}"),
+        }
+    } else if let Some(address) = &options.connect {
+        let server = address
+            .to_socket_addrs()
+            .map_err(|err| err.to_string())
+            .and_then(|mut addresses| {
+                addresses
+                    .next()
+                    .ok_or_else(|| "no address found".to_string())
+            });
+        let socket = NetSocket::bind(("0.0.0.0", 0)).map_err(|err| err.to_string());
+        match server.and_then(|server| Ok((server, socket?))) {
+            Ok((server, socket)) => {
+                info!("Connecting to {server}");
+                commands.insert_resource(socket);
+                commands.insert_resource(Client::new(server));
+                *role = NetRole::Client;
+                *local_players = LocalPlayers(1);
+            }
+            Err(err) => error!("Could not connect to {address}: {err}"),
+        }
+    }
+}
+
+pub struct NetPlugin;
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetRole>()
+            .add_startup_system(open_socket)
+            .add_plugin(ServerPlugin)
+            .add_plugin(ClientPlugin);
+    }
+}
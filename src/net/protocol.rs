@@ -0,0 +1,331 @@
+use crate::prelude::*;
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+
+/// Largest payload a UDP datagram can carry.
+const MAX_DATAGRAM: usize = 65_507;
+/// Largest message sent, so it fits in a single packet on a typical network.
+pub const MAX_MESSAGE: usize = 1200;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum ClientMessage {
+    /// Sent until the server answers with `Welcome`.
+    Join,
+    Input(PlayerInput),
+    Leave,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PlayerInput {
+    pub sequence: u32,
+    /// Seconds of play since the previous input.
+    pub seconds: f32,
+    /// The movement keys or stick, x to the right and y forwards.
+    pub axes: [f32; 2],
+    pub jump: bool,
+    /// Whether jump was pressed since the previous input, for the double jump.
+    pub jump_pressed: bool,
+    pub descend: bool,
+    pub rotation: [f32; 4],
+    pub hit: Option<HitInput>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct HitInput {
+    pub charge: f32,
+    pub shockwave: bool,
+    pub direction: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum ServerMessage {
+    Welcome {
+        id: u32,
+        mode: GameMode,
+        terrain_height: u32,
+    },
+    Snapshot(Snapshot),
+    RoundOver { winner: u32, score: i32 },
+    Notice(String),
+    /// The answer to `Join` when no more players fit.
+    Full,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    /// Seconds since the server started, used to interpolate between
+    /// snapshots.
+    pub clock: f32,
+    pub seed: u32,
+    /// The map's terrain height, which otherwise follows each player's own
+    /// display quality.
+    pub terrain_height: u32,
+    /// Seconds into the round, which drives the moving platforms.
+    pub round_time: f32,
+    pub hour: f32,
+    /// The receiving client's latest input that the server has applied.
+    pub ack: u32,
+    /// Snapshots too big for one message are split into parts, each with
+    /// some of the balls.
+    pub part: u16,
+    pub parts: u16,
+    pub players: Vec<PlayerState>,
+    pub balls: Vec<BallState>,
+}
+
+impl Snapshot {
+    /// Splits the balls over as many parts as it takes for each to fit in a
+    /// message.
+    pub fn split(mut self) -> Vec<Snapshot> {
+        let balls = std::mem::take(&mut self.balls);
+        // Leaves room for the message's tag and for the part numbers and ball
+        // count taking more bytes once filled in.
+        let room = MAX_MESSAGE.saturating_sub(encoded_size(&self) + 12);
+        let mut parts = vec![Vec::new()];
+        let mut used = 0;
+        for ball in balls {
+            let size = encoded_size(&ball);
+            let last = parts.last_mut().unwrap();
+            if used + size > room && !last.is_empty() {
+                parts.push(vec![ball]);
+                used = size;
+            } else {
+                last.push(ball);
+                used += size;
+            }
+        }
+        let count = parts.len() as u16;
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(part, balls)| Snapshot {
+                part: part as u16,
+                parts: count,
+                players: self.players.clone(),
+                balls,
+                ..self
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlayerState {
+    pub id: u32,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    pub score: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BallState {
+    /// The ball's entity on the server.
+    pub id: u64,
+    pub blue: bool,
+    pub position: [f32; 3],
+}
+
+fn encoding() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_DATAGRAM as u64)
+}
+
+fn encoded_size<T: Serialize>(value: &T) -> usize {
+    encoding().serialized_size(value).unwrap_or(u64::MAX) as usize
+}
+
+/// The non-blocking socket the game talks to its peers through. Messages are
+/// sent in bincode, one per datagram.
+#[derive(Resource)]
+pub struct NetSocket {
+    socket: UdpSocket,
+    /// Reused for every datagram received.
+    buffer: Vec<u8>,
+}
+
+impl NetSocket {
+    pub fn bind(address: (&str, u16)) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetSocket {
+            socket,
+            buffer: vec![0; MAX_DATAGRAM],
+        })
+    }
+
+    pub fn send<T: Serialize>(&self, message: &T, to: SocketAddr) {
+        let result = encoding()
+            .serialize(message)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| {
+                if bytes.len() > MAX_MESSAGE {
+                    return Err(format!("{} byte message is too big", bytes.len()));
+                }
+                self.socket
+                    .send_to(&bytes, to)
+                    .map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            debug!("Failed to send to {to}: {err}");
+        }
+    }
+
+    /// Reads every datagram waiting on the socket, skipping any that don't
+    /// parse.
+    pub fn receive<T: DeserializeOwned>(&mut self) -> Vec<(T, SocketAddr)> {
+        let mut messages = Vec::new();
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((len, from)) => match encoding().deserialize(&self.buffer[..len]) {
+                    Ok(message) => messages.push((message, from)),
+                    Err(err) => debug!("Ignoring malformed message from {from}: {err}"),
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // Windows reports an earlier send to a closed port here.
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    warn!("Failed to receive: {err}");
+                    break;
+                }
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn socket() -> (NetSocket, SocketAddr) {
+        let socket = NetSocket::bind(("127.0.0.1", 0)).unwrap();
+        let address = socket.socket.local_addr().unwrap();
+        (socket, address)
+    }
+
+    /// Waits a little for datagrams to arrive, since the sockets never block.
+    fn receive_some<T: DeserializeOwned>(socket: &mut NetSocket) -> Vec<(T, SocketAddr)> {
+        let start = Instant::now();
+        loop {
+            let messages = socket.receive();
+            if !messages.is_empty() || start.elapsed() > Duration::from_secs(2) {
+                return messages;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            tick: 7,
+            clock: 12.5,
+            seed: 42,
+            terrain_height: 32,
+            round_time: 3.25,
+            hour: 18.0,
+            ack: 3,
+            part: 0,
+            parts: 1,
+            players: vec![PlayerState {
+                id: 1,
+                position: [1.0, 2.0, 3.0],
+                rotation: [0.0, 0.0, 0.0, 1.0],
+                score: 2,
+            }],
+            balls: vec![BallState {
+                id: u64::MAX,
+                blue: true,
+                position: [-4.5, 0.5, 8.0],
+            }],
+        }
+    }
+
+    #[test]
+    fn input_round_trips() {
+        let (client, client_address) = socket();
+        let (mut server, server_address) = socket();
+        let input = ClientMessage::Input(PlayerInput {
+            sequence: 9,
+            seconds: 1.0 / 60.0,
+            axes: [0.5, -1.0],
+            jump: true,
+            jump_pressed: false,
+            descend: false,
+            rotation: [0.0, 0.6, 0.0, 0.8],
+            hit: Some(HitInput {
+                charge: 0.75,
+                shockwave: true,
+                direction: [0.0, 0.0, -1.0],
+            }),
+        });
+        client.send(&input, server_address);
+        let received = receive_some::<ClientMessage>(&mut server);
+        assert_eq!(received, vec![(input, client_address)]);
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        let (server, server_address) = socket();
+        let (mut client, client_address) = socket();
+        let messages = [
+            ServerMessage::Welcome {
+                id: 3,
+                mode: GameMode::default(),
+                terrain_height: 64,
+            },
+            ServerMessage::Snapshot(snapshot()),
+            ServerMessage::RoundOver {
+                winner: 0,
+                score: 5,
+            },
+            ServerMessage::Notice("Player 3 joined".into()),
+            ServerMessage::Full,
+        ];
+        for message in messages {
+            server.send(&message, client_address);
+            let received = receive_some::<ServerMessage>(&mut client);
+            assert_eq!(received, vec![(message, server_address)]);
+        }
+    }
+
+    #[test]
+    fn malformed_datagrams_are_skipped() {
+        let (client, client_address) = socket();
+        let (mut server, server_address) = socket();
+        client.socket.send_to(b"Input((sequence: ", server_address).unwrap();
+        client.socket.send_to(&[0xff, 0xfe], server_address).unwrap();
+        client.send(&ClientMessage::Leave, server_address);
+        let received = receive_some::<ClientMessage>(&mut server);
+        assert_eq!(received, vec![(ClientMessage::Leave, client_address)]);
+    }
+
+    #[test]
+    fn big_snapshots_are_split() {
+        let mut snapshot = snapshot();
+        snapshot.balls = (0..500)
+            .map(|id| BallState {
+                id,
+                blue: id % 50 == 0,
+                position: [id as f32, 1.0, -(id as f32)],
+            })
+            .collect();
+        let parts = snapshot.clone().split();
+        assert!(parts.len() > 1);
+        for (index, part) in parts.iter().enumerate() {
+            assert_eq!((part.part, part.parts), (index as u16, parts.len() as u16));
+            assert_eq!(part.players, snapshot.players);
+            assert!(encoded_size(&ServerMessage::Snapshot(part.clone())) <= MAX_MESSAGE);
+        }
+        let balls: Vec<BallState> = parts.into_iter().flat_map(|part| part.balls).collect();
+        assert_eq!(balls, snapshot.balls);
+    }
+
+    #[test]
+    fn small_snapshots_are_whole() {
+        let parts = snapshot().split();
+        assert_eq!(parts, vec![snapshot()]);
+    }
+}
@@ -0,0 +1,468 @@
+use crate::game::sky::TimeOfDay;
+use crate::net::net::{avatar_bundle, round_result};
+use crate::net::protocol::{
+    BallState, ClientMessage, NetSocket, PlayerState, ServerMessage, Snapshot,
+};
+use crate::player::player::{apply_movement, Mobility, MoveInput, MovementSettings};
+use crate::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+const SNAPSHOT_INTERVAL: f32 = 1.0 / 20.0;
+const CLIENT_TIMEOUT: f32 = 5.0;
+/// Most seconds of movement a client can bank between inputs, so sending
+/// extra inputs doesn't make it any faster.
+const MAX_INPUT_BUDGET: f32 = 0.25;
+/// The host always plays as this id.
+pub const HOST_ID: u32 = 0;
+
+struct Connection {
+    id: u32,
+    entity: Entity,
+    last_heard: f32,
+    ack: u32,
+    /// Seconds of movement the client's inputs may still use up.
+    budget: f32,
+    score: i32,
+}
+
+#[derive(Resource, Default)]
+pub struct Server {
+    connections: HashMap<SocketAddr, Connection>,
+    next_player_id: u32,
+    /// Most players that may be connected at once, if limited.
+    max_players: Option<usize>,
+    tick: u32,
+    seed: u32,
+    round: u32,
+    /// Whether this round's blue balls have shown up yet, so an empty map
+    /// right after regenerating doesn't end the round.
+    balls_spawned: bool,
+}
+
+impl Server {
+    pub fn new(max_players: Option<usize>) -> Self {
+        Server {
+            max_players,
+            ..default()
+        }
+    }
+
+    fn broadcast(&self, socket: &NetSocket, message: &ServerMessage) {
+        for address in self.connections.keys() {
+            socket.send(message, *address);
+        }
+    }
+
+    fn disconnect(&mut self, commands: &mut Commands, socket: &NetSocket, address: SocketAddr) {
+        let Some(connection) = self.connections.remove(&address) else {
+            return;
+        };
+        info!("Player {} left", connection.id);
+        commands.entity(connection.entity).despawn_recursive();
+        self.broadcast(
+            socket,
+            &ServerMessage::Notice(format!("Player {} left", connection.id)),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_messages(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut server: ResMut<Server>,
+    mut socket: ResMut<NetSocket>,
+    mode: Res<GameMode>,
+    world_seed: Res<WorldSeed>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<MovementSettings>,
+    mut players: Query<(&mut Transform, &InLiquid, &mut Jump), With<RemotePlayer>>,
+    mut fired: EventWriter<HitFired>,
+    mut notifications: EventWriter<Notification>,
+) {
+    let now = time.elapsed_seconds();
+    for connection in server.connections.values_mut() {
+        connection.budget = (connection.budget + time.delta_seconds()).min(MAX_INPUT_BUDGET);
+    }
+    let mobility = Mobility {
+        speed: settings.speed,
+        noclip: false,
+        double_jump: false,
+    };
+    for (message, address) in socket.receive::<ClientMessage>() {
+        match message {
+            ClientMessage::Join => {
+                if let Some(connection) = server.connections.get_mut(&address) {
+                    // The welcome got lost, so send it again.
+                    connection.last_heard = now;
+                    let id = connection.id;
+                    socket.send(
+                        &ServerMessage::Welcome {
+                            id,
+                            mode: *mode,
+                            terrain_height: world_seed.height,
+                        },
+                        address,
+                    );
+                    continue;
+                }
+                if server
+                    .max_players
+                    .is_some_and(|max| server.connections.len() >= max)
+                {
+                    info!("Turned away {address}, the server is full");
+                    socket.send(&ServerMessage::Full, address);
+                    continue;
+                }
+                server.next_player_id += 1;
+                let id = server.next_player_id;
+                let entity = commands
+                    .spawn(avatar_bundle(&mut meshes, &mut materials, id, SPAWN_POINT))
+                    .insert((
+                        RigidBody::Dynamic,
+                        LockedAxes::ROTATION_LOCKED,
+                        GravityScale(3.0),
+                        Velocity::default(),
+                        InLiquid::default(),
+                        Jump::default(),
+                        Health::default(),
+                        Ccd::enabled(),
+                    ))
+                    .id();
+                info!("Player {id} joined from {address}");
+                notifications.send(Notification(format!("Player {id} joined")));
+                server.broadcast(
+                    &socket,
+                    &ServerMessage::Notice(format!("Player {id} joined")),
+                );
+                server.connections.insert(
+                    address,
+                    Connection {
+                        id,
+                        entity,
+                        last_heard: now,
+                        ack: 0,
+                        budget: 0.0,
+                        score: 0,
+                    },
+                );
+                socket.send(
+                    &ServerMessage::Welcome {
+                        id,
+                        mode: *mode,
+                        terrain_height: world_seed.height,
+                    },
+                    address,
+                );
+            }
+            ClientMessage::Input(input) => {
+                let Some(connection) = server.connections.get_mut(&address) else {
+                    continue;
+                };
+                connection.last_heard = now;
+                if input.sequence <= connection.ack {
+                    continue;
+                }
+                connection.ack = input.sequence;
+                let Ok((mut transform, in_liquid, mut jump)) = players.get_mut(connection.entity)
+                else {
+                    continue;
+                };
+                let rotation = Quat::from_array(input.rotation);
+                if rotation.is_finite() && rotation.length_squared() > 0.0 {
+                    transform.rotation = rotation.normalize();
+                }
+                // The client only says what its controls did; the move itself
+                // happens here, in the server's world.
+                let seconds = if input.seconds.is_finite() {
+                    input.seconds.clamp(0.0, connection.budget)
+                } else {
+                    0.0
+                };
+                connection.budget -= seconds;
+                let axes = Vec2::from_array(input.axes);
+                let move_input = MoveInput {
+                    direction: if axes.is_finite() {
+                        axes.clamp_length_max(1.0)
+                    } else {
+                        Vec2::ZERO
+                    },
+                    ascend: input.jump,
+                    ascend_just_pressed: input.jump_pressed,
+                    descend: input.descend,
+                };
+                apply_movement(
+                    &mut transform,
+                    in_liquid,
+                    &mut jump,
+                    move_input,
+                    &mobility,
+                    seconds,
+                );
+                if let Some(hit) = input.hit {
+                    // Aimed through the third-person crosshair, or straight ahead.
+                    let direction = Vec3::from_array(hit.direction)
+                        .try_normalize()
+                        .unwrap_or_else(|| transform.forward());
+                    fired.send(HitFired {
+                        shooter: connection.entity,
+                        origin: transform.translation,
+                        direction,
+                        charge: hit.charge.clamp(0.0, 1.0),
+                        shockwave: hit.shockwave,
+                    });
+                }
+            }
+            ClientMessage::Leave => server.disconnect(&mut commands, &socket, address),
+        }
+    }
+
+    let timed_out: Vec<SocketAddr> = server
+        .connections
+        .iter()
+        .filter(|(_, connection)| now - connection.last_heard > CLIENT_TIMEOUT)
+        .map(|(address, _)| *address)
+        .collect();
+    for address in timed_out {
+        server.disconnect(&mut commands, &socket, address);
+    }
+}
+
+/// Remote players collect blue balls and get knocked out by red ones, the
+/// same as the host does in `collision_system`.
+#[allow(clippy::too_many_arguments)]
+fn remote_collisions(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut server: ResMut<Server>,
+    socket: Res<NetSocket>,
+    mut players: Query<(Entity, &RemotePlayer, &mut Health, &mut Jump)>,
+    cubes: Query<(), With<Cube>>,
+    enemies: Query<(), With<Enemy>>,
+    blue_balls: Query<&Transform, With<BlueBall>>,
+    mut sounds: EventWriter<SpatialSound>,
+    mut notifications: EventWriter<Notification>,
+    mut taken: ResMut<TakenBalls>,
+) {
+    for (entity, remote, mut health, mut jump) in &mut players {
+        jump.avalible = false;
+        for contact_pair in rapier_context.contacts_with(entity) {
+            if !contact_pair.has_any_active_contacts() {
+                continue;
+            }
+            let other = if contact_pair.collider1() == entity {
+                contact_pair.collider2()
+            } else {
+                contact_pair.collider1()
+            };
+            if cubes.contains(other) {
+                jump.avalible = true;
+            }
+            let Some((address, connection)) = server
+                .connections
+                .iter_mut()
+                .find(|(_, connection)| connection.entity == entity)
+            else {
+                continue;
+            };
+            if enemies.contains(other) && health.current > 0.0 {
+                health.current = 0.0;
+                info!("Player {} was hit by a red ball", remote.id);
+                socket.send(&ServerMessage::Notice("Hit by a red ball".into()), *address);
+            }
+            let Ok(ball_transform) = blue_balls.get(other) else {
+                continue;
+            };
+            if !taken.0.insert(other) {
+                continue;
+            }
+            connection.score += 1;
+            let notice = format!(
+                "Player {} took a blue ball ({})",
+                remote.id, connection.score
+            );
+            info!("{notice}");
+            commands.entity(other).despawn();
+            sounds.send(SpatialSound {
+                effect: SoundEffect::Score,
+                position: ball_transform.translation,
+                volume: 1.0,
+            });
+            notifications.send(Notification(notice.clone()));
+            server.broadcast(&socket, &ServerMessage::Notice(notice));
+        }
+    }
+}
+
+fn respawn_remote_players(
+    mut players: Query<(&mut Health, &mut Transform, &mut Velocity), With<RemotePlayer>>,
+) {
+    for (mut health, mut transform, mut velocity) in &mut players {
+        if health.current <= 0.0 {
+            *health = Health::default();
+            transform.translation = SPAWN_POINT;
+            *velocity = Velocity::zero();
+        }
+    }
+}
+
+/// Ends the round once every blue ball has been taken. Whoever collected the
+/// most wins, the host on a tie.
+fn contested_round(
+    mut server: ResMut<Server>,
+    socket: Res<NetSocket>,
+    blue_balls: Query<(), With<BlueBall>>,
+    mut game_info: ResMut<GameInfo>,
+    mut round_over: EventWriter<RoundOver>,
+    mut notifications: EventWriter<Notification>,
+) {
+    if game_info.is_won {
+        return;
+    }
+    if !blue_balls.is_empty() {
+        server.balls_spawned = true;
+        return;
+    }
+    if !std::mem::take(&mut server.balls_spawned) {
+        return;
+    }
+    let (winner, score) = server
+        .connections
+        .values()
+        .map(|connection| (connection.id, connection.score))
+        .fold((HOST_ID, game_info.collected), |best, next| {
+            if next.1 > best.1 {
+                next
+            } else {
+                best
+            }
+        });
+    for connection in server.connections.values_mut() {
+        connection.score = 0;
+    }
+    info!(
+        "Round {} over: player {winner} won with {score} blue balls",
+        server.round
+    );
+    server.broadcast(&socket, &ServerMessage::RoundOver { winner, score });
+    notifications.send(Notification(round_result(winner, HOST_ID, score)));
+
+    let won = winner == HOST_ID;
+    if won {
+        game_info.wins += 1;
+    }
+    game_info.collected = 0;
+    game_info.is_won = true;
+    round_over.send(RoundOver { won });
+}
+
+/// Sends everyone back to the spawn point when the map is regenerated.
+fn reset_on_new_map(
+    mut server: ResMut<Server>,
+    world_seed: Res<WorldSeed>,
+    mut players: Query<(&mut Health, &mut Transform, &mut Velocity), With<RemotePlayer>>,
+) {
+    if server.seed == world_seed.current {
+        return;
+    }
+    server.seed = world_seed.current;
+    server.round += 1;
+    info!("Round {} started on seed {}", server.round, server.seed);
+    for (mut health, mut transform, mut velocity) in &mut players {
+        *health = Health::default();
+        transform.translation = SPAWN_POINT;
+        *velocity = Velocity::zero();
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn send_snapshots(
+    time: Res<Time>,
+    mut since_last: Local<f32>,
+    mut server: ResMut<Server>,
+    socket: Res<NetSocket>,
+    world_seed: Res<WorldSeed>,
+    time_of_day: Res<TimeOfDay>,
+    game_info: Res<GameInfo>,
+    round_timer: Query<&FPSTimer>,
+    host: Query<&Transform, With<Player>>,
+    remote_players: Query<(&RemotePlayer, &Transform)>,
+    balls: Query<(Entity, &Transform, Option<&BlueBall>), Or<(With<BlueBall>, With<Enemy>)>>,
+) {
+    *since_last += time.delta_seconds();
+    if *since_last < SNAPSHOT_INTERVAL || server.connections.is_empty() {
+        return;
+    }
+    *since_last = 0.0;
+    server.tick += 1;
+
+    let player_state = |id, transform: &Transform, score| PlayerState {
+        id,
+        position: transform.translation.to_array(),
+        rotation: transform.rotation.to_array(),
+        score,
+    };
+    let mut players: Vec<PlayerState> = host
+        .get_single()
+        .map(|transform| player_state(HOST_ID, transform, game_info.collected))
+        .into_iter()
+        .collect();
+    for (remote, transform) in &remote_players {
+        let score = server
+            .connections
+            .values()
+            .find(|connection| connection.id == remote.id)
+            .map_or(0, |connection| connection.score);
+        players.push(player_state(remote.id, transform, score));
+    }
+    let balls = balls
+        .iter()
+        .map(|(entity, transform, blue)| BallState {
+            id: entity.to_bits(),
+            blue: blue.is_some(),
+            position: transform.translation.to_array(),
+        })
+        .collect();
+
+    let mut parts = Snapshot {
+        tick: server.tick,
+        clock: time.elapsed_seconds(),
+        seed: world_seed.current,
+        terrain_height: world_seed.height,
+        round_time: round_timer.get_single().map_or(0.0, |timer| timer.elapsed),
+        hour: time_of_day.0,
+        ack: 0,
+        part: 0,
+        parts: 1,
+        players,
+        balls,
+    }
+    .split();
+    for (address, connection) in &server.connections {
+        for part in &mut parts {
+            part.ack = connection.ack;
+            socket.send(&ServerMessage::Snapshot(part.clone()), *address);
+        }
+    }
+}
+
+pub struct ServerPlugin;
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+                (
+                    receive_messages,
+                    remote_collisions,
+                    respawn_remote_players.after(remote_collisions),
+                    contested_round,
+                    reset_on_new_map,
+                    send_snapshots,
+                )
+                    .distributive_run_if(resource_exists::<Server>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
@@ -1 +1,3 @@
-pub mod player;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod player;
+pub mod third_person;
\ No newline at end of file
@@ -1,7 +1,15 @@
+use crate::console::console::{console_closed, parse_arg, ConsoleAppExt};
+use crate::game::archetypes::{spawn_archetype, Archetype, Archetypes, PLAYER_LIGHT};
+use crate::game::spectator::not_spectating;
+use crate::player::third_person::ThirdPersonPlugin;
 use crate::prelude::*;
+use bevy::core_pipeline::clear_color::ClearColorConfig;
 use bevy::ecs::event::{Events, ManualEventReader};
+use bevy::ecs::system::SystemParam;
 use bevy::input::mouse::MouseMotion;
+use bevy::pbr::NotShadowCaster;
 use bevy::prelude::*;
+use bevy::render::camera::Viewport;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
 use bevy_rapier3d::prelude::*;
 
@@ -53,6 +61,155 @@ impl Default for KeyBindings {
 #[derive(Component)]
 pub struct FlyCam;
 
+/// Draws the HUD over every viewport in split-screen.
+#[derive(Component)]
+struct HudCamera;
+
+/// The colour of each local player's ball, seen from the third-person camera
+/// and by everyone else on the screen.
+const PLAYER_COLORS: [Color; LocalPlayers::MAX] = [
+    Color::rgb(0.95, 0.95, 0.95),
+    Color::rgb(0.3, 0.9, 0.3),
+    Color::rgb(0.9, 0.3, 0.9),
+    Color::rgb(0.3, 0.9, 0.9),
+];
+
+const STICK_DEADZONE: f32 = 0.15;
+/// Degrees per second at full right stick.
+const GAMEPAD_LOOK_SPEED: f32 = 150.0;
+
+/// Reads a local player's keyboard and mouse or gamepad.
+#[derive(SystemParam)]
+pub struct Controls<'w, 's> {
+    keys: Res<'w, Input<KeyCode>>,
+    key_bindings: Res<'w, KeyBindings>,
+    mouse_buttons: Res<'w, Input<MouseButton>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepad_axes: Res<'w, Axis<GamepadAxis>>,
+    windows: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+}
+
+impl Controls<'_, '_> {
+    fn gamepad(&self, device: InputDevice) -> Option<Gamepad> {
+        match device {
+            InputDevice::KeyboardMouse => None,
+            InputDevice::Gamepad(n) => self.gamepads.iter().nth(n),
+        }
+    }
+
+    /// The keyboard only steers the player while the cursor is grabbed.
+    fn keyboard(&self, device: InputDevice) -> bool {
+        device == InputDevice::KeyboardMouse
+            && self
+                .windows
+                .get_single()
+                .is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None)
+    }
+
+    fn stick(&self, gamepad: Gamepad, x: GamepadAxisType, y: GamepadAxisType) -> Vec2 {
+        let axis = |axis_type| {
+            self.gamepad_axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or_default()
+        };
+        let stick = Vec2::new(axis(x), axis(y));
+        if stick.length() < STICK_DEADZONE {
+            Vec2::ZERO
+        } else {
+            stick
+        }
+    }
+
+    fn button(&self, gamepad: Gamepad, button: GamepadButtonType) -> bool {
+        self.gamepad_buttons.pressed(GamepadButton::new(gamepad, button))
+    }
+
+    pub fn move_input(&self, device: InputDevice) -> MoveInput {
+        MoveInput {
+            direction: self.movement(device),
+            ascend: self.ascend(device),
+            ascend_just_pressed: self.ascend_just_pressed(device),
+            descend: self.descend(device),
+        }
+    }
+
+    /// Walking direction with x to the right and y forwards, at most 1 long.
+    fn movement(&self, device: InputDevice) -> Vec2 {
+        if let Some(gamepad) = self.gamepad(device) {
+            return self
+                .stick(gamepad, GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY)
+                .clamp_length_max(1.0);
+        }
+        if !self.keyboard(device) {
+            return Vec2::ZERO;
+        }
+        let key = |code| if self.keys.pressed(code) { 1.0 } else { 0.0 };
+        let bindings = &self.key_bindings;
+        Vec2::new(
+            key(bindings.move_right) - key(bindings.move_left),
+            key(bindings.move_forward) - key(bindings.move_backward),
+        )
+    }
+
+    fn ascend(&self, device: InputDevice) -> bool {
+        match self.gamepad(device) {
+            Some(gamepad) => self.button(gamepad, GamepadButtonType::South),
+            None => self.keyboard(device) && self.keys.pressed(self.key_bindings.move_ascend),
+        }
+    }
+
+    fn ascend_just_pressed(&self, device: InputDevice) -> bool {
+        match self.gamepad(device) {
+            Some(gamepad) => self
+                .gamepad_buttons
+                .just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South)),
+            None => self.keyboard(device) && self.keys.just_pressed(self.key_bindings.move_ascend),
+        }
+    }
+
+    fn descend(&self, device: InputDevice) -> bool {
+        match self.gamepad(device) {
+            Some(gamepad) => {
+                self.button(gamepad, GamepadButtonType::East)
+                    || self.button(gamepad, GamepadButtonType::LeftTrigger)
+            }
+            None => self.keyboard(device) && self.keys.pressed(self.key_bindings.move_descend),
+        }
+    }
+
+    /// The right stick, for players on a gamepad.
+    pub fn look(&self, device: InputDevice) -> Vec2 {
+        self.gamepad(device).map_or(Vec2::ZERO, |gamepad| {
+            self.stick(gamepad, GamepadAxisType::RightStickX, GamepadAxisType::RightStickY)
+        })
+    }
+
+    /// Whether a hit is being charged, and if so whether it is a shockwave.
+    pub fn hit(&self, device: InputDevice) -> Option<bool> {
+        let (ray, shockwave) = match self.gamepad(device) {
+            Some(gamepad) => (
+                self.button(gamepad, GamepadButtonType::RightTrigger2),
+                self.button(gamepad, GamepadButtonType::LeftTrigger2),
+            ),
+            None => (
+                self.mouse_buttons.pressed(MouseButton::Left),
+                self.mouse_buttons.pressed(MouseButton::Right),
+            ),
+        };
+        (ray || shockwave).then_some(shockwave)
+    }
+}
+
+/// What the primary player's controls asked for since a multiplayer client
+/// last sent its input, and how far that moved them here.
+#[derive(Resource, Default)]
+pub struct PlayerMovement {
+    pub input: MoveInput,
+    pub seconds: f32,
+    pub step: Vec3,
+}
+
 fn toggle_grab_cursor(window: &mut Window) {
     match window.cursor.grab_mode {
         CursorGrabMode::None => {
@@ -74,7 +231,7 @@ fn initial_grab_cursor(mut primary_window: Query<&mut Window, With<PrimaryWindow
     }
 }
 
-fn calculate_fov(value: f32) -> f32 {
+pub(crate) fn calculate_fov(value: f32) -> f32 {
     let input_min = 1.0;
     let input_max = 10.0;
     let output_min = 40.0;
@@ -86,118 +243,312 @@ fn calculate_fov(value: f32) -> f32 {
     output_value.round()
 }
 
-pub fn setup_player(mut commands: Commands, fov: Res<Fov>) {
-    commands
-        .spawn((
-            Camera3dBundle {
-                projection: PerspectiveProjection {
-                    fov: calculate_fov(fov.0 as f32).to_radians(),
+/// The part of the window a local player sees, as fractions of its size with
+/// y pointing down.
+pub fn player_screen(index: usize, count: usize) -> Rect {
+    let (x, y, width, height) = match (count, index) {
+        (2, _) => (index as f32 * 0.5, 0.0, 0.5, 1.0),
+        (3, 0) => (0.0, 0.0, 1.0, 0.5),
+        (3, _) => ((index - 1) as f32 * 0.5, 0.5, 0.5, 0.5),
+        (4, _) => ((index % 2) as f32 * 0.5, (index / 2) as f32 * 0.5, 0.5, 0.5),
+        _ => (0.0, 0.0, 1.0, 1.0),
+    };
+    Rect::new(x, y, x + width, y + height)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn setup_player(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    fov: Res<Fov>,
+    local_players: Res<LocalPlayers>,
+    archetypes: Res<Archetypes>,
+    archetype_assets: Res<Assets<Archetype>>,
+    spawn: Res<SpawnPoint>,
+) {
+    let mesh = meshes.add(shape::UVSphere::default().into());
+    let light = archetypes.get(PLAYER_LIGHT, &archetype_assets).unwrap_or_default();
+    for (index, color) in PLAYER_COLORS.into_iter().enumerate().take(local_players.0) {
+        let model = PbrBundle {
+            mesh: mesh.clone(),
+            material: materials.add(color.into()),
+            ..default()
+        };
+        let position = spawn.local(index);
+        let player = spawn_player(&mut commands, &fov, index, local_players.0, position, model);
+        let light = spawn_archetype(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            PLAYER_LIGHT,
+            &light,
+            Transform::default(),
+        )
+        .id();
+        commands.entity(player).add_child(light);
+    }
+    if local_players.0 > 1 {
+        commands.spawn((
+            Camera2dBundle {
+                camera: Camera {
+                    order: LocalPlayers::MAX as isize,
                     ..default()
-                }
-                .into(),
-                transform: Transform::from_xyz(0.0, 0.0, 0.0)
-                    .looking_at(Vec3::new(-1.0, -1.0, 0.0), Vec3::Y),
-                ..Default::default()
+                },
+                camera_2d: Camera2d {
+                    clear_color: ClearColorConfig::None,
+                },
+                ..default()
             },
-            FlyCam,
-        ))
-        .insert(PointLightBundle {
-            point_light: PointLight {
-                intensity: 3000.0,
-                color: Color::WHITE,
-                shadows_enabled: true,
+            HudCamera,
+        ));
+    }
+}
+
+fn spawn_player(
+    commands: &mut Commands,
+    fov: &Fov,
+    index: usize,
+    count: usize,
+    position: Vec3,
+    model: PbrBundle,
+) -> Entity {
+    let device = match index {
+        0 => InputDevice::KeyboardMouse,
+        _ => InputDevice::Gamepad(index - 1),
+    };
+    let mut player = commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                order: index as isize,
                 ..default()
             },
-            ..default()
-        })
+            projection: PerspectiveProjection {
+                fov: calculate_fov(fov.0 as f32).to_radians(),
+                ..default()
+            }
+            .into(),
+            transform: Transform::from_xyz(0.0, 0.0, 0.0)
+                .looking_at(Vec3::new(-1.0, -1.0, 0.0), Vec3::Y),
+            ..Default::default()
+        },
+        FlyCam,
+    ));
+    player
+        .insert(VisibilityBundle::default())
         .insert(RigidBody::Dynamic)
         .insert(LockedAxes::ROTATION_LOCKED)
         .insert(TransformBundle::from(
-            Transform::from_xyz(0.0, 80.0, 0.0).looking_at(Vec3::new(-1.0, -1.0, 0.0), Vec3::Y),
+            Transform::from_translation(position)
+                .looking_at(Vec3::new(-1.0, -1.0, 0.0), Vec3::Y),
         ))
         .insert(Player)
+        .insert(LocalPlayer { index, device })
+        .insert((Jump::default(), Hit::default(), HitTimer::default(), Score::default(), Aim::default()))
+        .insert(Health::default())
         .insert(GravityScale(3.0))
+        .insert(Velocity::default())
+        .insert(InLiquid::default())
         .insert(Ccd::enabled())
-        .insert(Collider::ball(1.0));
+        .insert(Collider::ball(1.0))
+        // The camera sits at the centre, where the ball's faces all point away
+        // and are culled, so it only shows from outside. It lets the ball's own
+        // light through.
+        .with_children(|parent| {
+            parent.spawn((model, NotShadowCaster));
+        });
+    if index == 0 {
+        player.insert(PrimaryPlayer);
+    }
+    if count > 1 {
+        player.insert(UiCameraConfig { show_ui: false });
+    }
+    player.id()
 }
 
-fn player_move(
-    keys: Res<Input<KeyCode>>,
-    time: Res<Time>,
+/// Fits each player's camera to their part of the window.
+fn update_viewports(
     primary_window: Query<&Window, With<PrimaryWindow>>,
-    settings: Res<MovementSettings>,
-    key_bindings: Res<KeyBindings>,
-    mut query: Query<(&FlyCam, &mut Transform)>,
-    mut jump: ResMut<Jump>,
+    local_players: Res<LocalPlayers>,
+    mut cameras: Query<(&mut Camera, &LocalPlayer)>,
 ) {
-    if let Ok(window) = primary_window.get_single() {
-        for (_camera, mut transform) in query.iter_mut() {
-            let mut velocity = Vec3::ZERO;
-            let local_z = transform.local_z();
-            let forward = -Vec3::new(local_z.x, 0., local_z.z);
-            let right = Vec3::new(local_z.z, 0., -local_z.x);
-            for key in keys.get_pressed() {
-                match window.cursor.grab_mode {
-                    CursorGrabMode::None => (),
-                    _ => {
-                        let key = *key;
-                        if key == key_bindings.move_forward {
-                            velocity += forward;
-                        } else if key == key_bindings.move_backward {
-                            velocity -= forward;
-                        } else if key == key_bindings.move_left {
-                            velocity -= right;
-                        } else if key == key_bindings.move_right {
-                            velocity += right;
-                        } else if key == key_bindings.move_ascend && jump.avalible {
-                            jump.jumping = true;
-                        }
-                    }
-                }
-            }
+    let Ok(window) = primary_window.get_single() else {
+        return;
+    };
+    if local_players.0 < 2 {
+        return;
+    }
+    let size = Vec2::new(
+        window.resolution.physical_width() as f32,
+        window.resolution.physical_height() as f32,
+    );
+    for (mut camera, local) in &mut cameras {
+        let screen = player_screen(local.index, local_players.0);
+        let physical_position = (screen.min * size).as_uvec2();
+        let physical_size = (screen.size() * size).as_uvec2().max(UVec2::ONE);
+        let current = camera
+            .viewport
+            .as_ref()
+            .map(|viewport| (viewport.physical_position, viewport.physical_size));
+        if current != Some((physical_position, physical_size)) {
+            camera.viewport = Some(Viewport {
+                physical_position,
+                physical_size,
+                ..default()
+            });
+        }
+    }
+}
 
-            velocity = velocity.normalize_or_zero();
-            velocity *= settings.speed;
+/// One frame of movement, from a player's controls or a bot.
+#[derive(Clone, Copy, Default)]
+pub struct MoveInput {
+    /// x to the right and y forwards, at most 1 long.
+    pub direction: Vec2,
+    pub ascend: bool,
+    /// Whether ascend was pressed this frame, for the double jump.
+    pub ascend_just_pressed: bool,
+    pub descend: bool,
+}
 
-            if jump.jumping {
-                velocity *= 1.3;
-                velocity.y = 40.0 * jump.elapsed;
-            }
+/// How fast and freely a body moves.
+pub struct Mobility {
+    pub speed: f32,
+    pub noclip: bool,
+    pub double_jump: bool,
+}
 
-            transform.translation += velocity * time.delta_seconds();
-        }
+/// Walks, swims, jumps or flies a body the way the movement keys do and
+/// returns how far it went.
+pub fn apply_movement(
+    transform: &mut Transform,
+    in_liquid: &InLiquid,
+    jump: &mut Jump,
+    input: MoveInput,
+    mobility: &Mobility,
+    delta_seconds: f32,
+) -> Vec3 {
+    let noclip = mobility.noclip;
+    let swimming = in_liquid.0.is_some() && !noclip;
+    let local_z = transform.local_z();
+    let forward = if noclip {
+        -local_z
     } else {
-        warn!("Primary window not found for `player_move`!");
+        -Vec3::new(local_z.x, 0., local_z.z)
+    };
+    let right = Vec3::new(local_z.z, 0., -local_z.x);
+    let mut velocity = right * input.direction.x + forward * input.direction.y;
+    if noclip || swimming {
+        if input.ascend {
+            velocity += Vec3::Y;
+        }
+        if input.descend {
+            velocity -= Vec3::Y;
+        }
+    } else if input.ascend && jump.avalible {
+        jump.jumping = true;
+    }
+
+    if jump.avalible {
+        jump.air_jumped = false;
+    } else if input.ascend_just_pressed
+        && !jump.jumping
+        && !jump.air_jumped
+        && !noclip
+        && mobility.double_jump
+    {
+        jump.jumping = true;
+        jump.air_jumped = true;
+        jump.elapsed = 0.5;
+    }
+
+    velocity = velocity.clamp_length_max(1.0) * mobility.speed;
+
+    if noclip {
+        velocity *= 2.0;
+    } else if let Some(liquid) = in_liquid.0 {
+        velocity *= liquid.speed_factor();
+    } else if jump.jumping {
+        velocity *= 1.3;
+        velocity.y = 40.0 * jump.elapsed;
+    }
+
+    let step = velocity * delta_seconds;
+    transform.translation += step;
+    step
+}
+
+fn player_move(
+    time: Res<Time>,
+    settings: Res<MovementSettings>,
+    controls: Controls,
+    mut query: Query<(&mut Transform, &InLiquid, &mut Jump, &LocalPlayer), With<FlyCam>>,
+    cheats: Res<Cheats>,
+    power_ups: Res<ActivePowerUps>,
+    mut movement: ResMut<PlayerMovement>,
+) {
+    let mobility = Mobility {
+        speed: settings.speed * power_ups.strength(PowerUpKind::SpeedBoost).unwrap_or(1.0),
+        noclip: cheats.noclip,
+        double_jump: power_ups.get(PowerUpKind::DoubleJump).is_some(),
+    };
+    for (mut transform, in_liquid, mut jump, local) in query.iter_mut() {
+        let input = controls.move_input(local.device);
+        let step = apply_movement(
+            &mut transform,
+            in_liquid,
+            &mut jump,
+            input,
+            &mobility,
+            time.delta_seconds(),
+        );
+        if local.index == 0 {
+            let pressed = movement.input.ascend_just_pressed;
+            movement.input = MoveInput {
+                ascend_just_pressed: input.ascend_just_pressed || pressed,
+                ..input
+            };
+            movement.seconds += time.delta_seconds();
+            movement.step += step;
+        }
     }
 }
 
 fn player_look(
+    time: Res<Time>,
     settings: Res<MovementSettings>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
+    controls: Controls,
     mut state: ResMut<InputState>,
     motion: Res<Events<MouseMotion>>,
-    mut query: Query<&mut Transform, With<FlyCam>>,
+    mut query: Query<(&mut Transform, &LocalPlayer), With<FlyCam>>,
 ) {
-    if let Ok(window) = primary_window.get_single() {
-        for mut transform in query.iter_mut() {
-            for ev in state.reader_motion.iter(&motion) {
-                let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
-                match window.cursor.grab_mode {
-                    CursorGrabMode::None => (),
-                    _ => {
-                        let window_scale = window.height().min(window.width());
-                        pitch -= (settings.sensitivity * ev.delta.y * window_scale).to_radians();
-                        yaw -= (settings.sensitivity * ev.delta.x * window_scale).to_radians();
-                    }
-                }
-
-                pitch = pitch.clamp(-1.54, 1.54);
-                transform.rotation =
-                    Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch);
+    let Ok(window) = primary_window.get_single() else {
+        warn!("Primary window not found for `player_look`!");
+        return;
+    };
+    let mouse: Vec2 = state.reader_motion.iter(&motion).map(|ev| ev.delta).sum();
+    let mouse = match window.cursor.grab_mode {
+        CursorGrabMode::None => Vec2::ZERO,
+        _ => mouse * settings.sensitivity * window.height().min(window.width()),
+    };
+    for (mut transform, local) in query.iter_mut() {
+        // Degrees to turn, x to the right and y downwards.
+        let turn = match local.device {
+            InputDevice::KeyboardMouse => mouse,
+            device => {
+                controls.look(device) * Vec2::new(1.0, -1.0) * GAMEPAD_LOOK_SPEED * time.delta_seconds()
             }
+        };
+        if turn == Vec2::ZERO {
+            continue;
         }
-    } else {
-        warn!("Primary window not found for `player_look`!");
+        let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        pitch -= turn.y.to_radians();
+        yaw -= turn.x.to_radians();
+        pitch = pitch.clamp(-1.54, 1.54);
+        transform.rotation =
+            Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch);
     }
 }
 
@@ -215,33 +566,91 @@ fn cursor_grab(
     }
 }
 
-fn jump_system(mut jump: ResMut<Jump>, time: Res<Time>) {
-    if jump.jumping {
-        jump.avalible = false;
-        jump.elapsed -= time.delta_seconds();
-        if jump.elapsed < 0.0 {
-            jump.jumping = false;
-            jump.elapsed = 0.5;
+fn jump_system(mut jumps: Query<&mut Jump>, time: Res<Time>) {
+    for mut jump in &mut jumps {
+        if jump.jumping {
+            jump.avalible = false;
+            jump.elapsed -= time.delta_seconds();
+            if jump.elapsed < 0.0 {
+                jump.jumping = false;
+                jump.elapsed = 0.5;
+            }
         }
     }
 }
 
+fn tp_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let position = Vec3::new(parse_arg(args, 0)?, parse_arg(args, 1)?, parse_arg(args, 2)?);
+    let mut players = world.query_filtered::<&mut Transform, With<Player>>();
+    for mut transform in players.iter_mut(world) {
+        transform.translation = position;
+    }
+    Ok(format!("teleported to {position}"))
+}
+
+fn noclip_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let noclip = {
+        let mut cheats = world.resource_mut::<Cheats>();
+        cheats.noclip = !cheats.noclip;
+        cheats.noclip
+    };
+    let players: Vec<Entity> = world
+        .query_filtered::<Entity, With<Player>>()
+        .iter(world)
+        .collect();
+    for player in players {
+        let mut player = world.entity_mut(player);
+        if noclip {
+            player.insert((RigidBody::KinematicPositionBased, ColliderDisabled));
+        } else {
+            player.insert(RigidBody::Dynamic).remove::<ColliderDisabled>();
+        }
+    }
+    Ok(format!("noclip {}", if noclip { "on" } else { "off" }))
+}
+
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<InputState>()
+        app.add_plugin(ThirdPersonPlugin)
+            .init_resource::<InputState>()
             .init_resource::<MovementSettings>()
             .init_resource::<KeyBindings>()
+            .init_resource::<PlayerMovement>()
+            .init_resource::<LocalPlayers>()
             .add_system(setup_player.in_schedule(OnEnter(GameState::InGame)))
-            .add_system(initial_grab_cursor.in_schedule(OnEnter(GameState::InGame)))
+            .add_system(initial_grab_cursor.run_if(has_primary_window).in_schedule(OnEnter(GameState::InGame)))
             .add_system(cleanup::<Player>.in_schedule(OnExit(GameState::InGame)))
+            .add_system(cleanup::<HudCamera>.in_schedule(OnExit(GameState::InGame)))
             .insert_resource(MovementSettings {
                 sensitivity: 0.00005, // default: 0.00012
                 speed: 12.0,          // default: 12.0
             })
             .add_system(jump_system.in_set(OnUpdate(GameState::InGame)))
-            .add_system(player_move.in_set(OnUpdate(GameState::InGame)))
-            .add_system(player_look.in_set(OnUpdate(GameState::InGame)))
-            .add_system(cursor_grab.in_set(OnUpdate(GameState::InGame)));
+            .add_system(
+                update_viewports
+                    .run_if(has_primary_window)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_systems(
+                (player_move, player_look)
+                    .distributive_run_if(console_closed)
+                    .distributive_run_if(has_primary_window)
+                    .distributive_run_if(not_spectating)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(
+                cursor_grab
+                    .run_if(console_closed)
+                    .run_if(has_primary_window)
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_console_command("tp", "tp <x> <y> <z>", &[], tp_command)
+            .add_console_command("noclip", "noclip", &[], noclip_command);
     }
 }
+
+/// Run condition that skips window-driven input when running headless.
+fn has_primary_window(windows: Query<(), With<PrimaryWindow>>) -> bool {
+    !windows.is_empty()
+}
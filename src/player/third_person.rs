@@ -0,0 +1,176 @@
+use crate::player::player::calculate_fov;
+use crate::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_rapier3d::prelude::*;
+
+/// Height above the ball that the camera orbits around.
+const PIVOT_HEIGHT: f32 = 1.5;
+const CAMERA_DISTANCE: f32 = 6.0;
+/// How close the camera may get to the terrain behind it.
+const CAMERA_CLEARANCE: f32 = 0.3;
+/// How quickly the camera catches up with the ball, higher is tighter.
+const FOLLOW_SHARPNESS: f32 = 12.0;
+/// How quickly the camera backs away again once the terrain is out of the way.
+const RETURN_SHARPNESS: f32 = 4.0;
+/// How far past the ball the crosshair is aimed when it points at open sky.
+const AIM_RANGE: f32 = 100.0;
+
+/// Follows a local player from behind, used instead of their own camera when
+/// the third-person view is selected.
+#[derive(Component)]
+pub struct ThirdPersonCamera {
+    player: Entity,
+    pivot: Vec3,
+    distance: f32,
+}
+
+fn spawn_cameras(
+    mut commands: Commands,
+    fov: Res<Fov>,
+    local_players: Res<LocalPlayers>,
+    players: Query<(Entity, &Transform, &LocalPlayer), Added<LocalPlayer>>,
+) {
+    for (player, transform, local) in &players {
+        let pivot = transform.translation + Vec3::Y * PIVOT_HEIGHT;
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    order: local.index as isize,
+                    is_active: false,
+                    ..default()
+                },
+                projection: PerspectiveProjection {
+                    fov: calculate_fov(fov.0 as f32).to_radians(),
+                    ..default()
+                }
+                .into(),
+                transform: Transform::from_translation(pivot + transform.back() * CAMERA_DISTANCE)
+                    .with_rotation(transform.rotation),
+                ..default()
+            },
+            UiCameraConfig {
+                show_ui: local_players.0 < 2,
+            },
+            ThirdPersonCamera {
+                player,
+                pivot,
+                distance: CAMERA_DISTANCE,
+            },
+        ));
+    }
+}
+
+/// Shows each player through the camera matching the chosen view, or neither
+/// while the spectator camera has taken over.
+#[allow(clippy::type_complexity)]
+fn switch_cameras(
+    view: Res<CameraView>,
+    spectator: Res<Spectator>,
+    mut players: Query<(&mut Camera, &mut Aim), (With<LocalPlayer>, Without<ThirdPersonCamera>)>,
+    mut cameras: Query<(&mut Camera, &ThirdPersonCamera)>,
+) {
+    let third_person = *view == CameraView::ThirdPerson;
+    for (mut camera, rig) in &mut cameras {
+        let Ok((mut player_camera, mut aim)) = players.get_mut(rig.player) else {
+            continue;
+        };
+        let active = !spectator.is_active();
+        if player_camera.is_active != (active && !third_person) {
+            player_camera.is_active = active && !third_person;
+        }
+        if camera.is_active != (active && third_person) {
+            camera.is_active = active && third_person;
+        }
+        let viewport = |camera: &Camera| {
+            camera
+                .viewport
+                .as_ref()
+                .map(|viewport| (viewport.physical_position, viewport.physical_size))
+        };
+        if viewport(&camera) != viewport(&player_camera) {
+            camera.viewport = player_camera.viewport.clone();
+        }
+        if !third_person {
+            aim.0 = None;
+        }
+    }
+}
+
+/// Eases each camera in behind its player, pulling it in front of any terrain
+/// in the way, and aims the player's hits at whatever is under the crosshair.
+#[allow(clippy::type_complexity)]
+fn follow_players(
+    time: Res<Time>,
+    view: Res<CameraView>,
+    rapier_context: Res<RapierContext>,
+    mut players: Query<(&Transform, &mut Aim), (With<LocalPlayer>, Without<ThirdPersonCamera>)>,
+    mut cameras: Query<(&mut Transform, &mut ThirdPersonCamera)>,
+) {
+    if *view != CameraView::ThirdPerson {
+        return;
+    }
+    let follow = 1.0 - (-FOLLOW_SHARPNESS * time.delta_seconds()).exp();
+    let back_off = 1.0 - (-RETURN_SHARPNESS * time.delta_seconds()).exp();
+    for (mut transform, mut rig) in &mut cameras {
+        let Ok((player, mut aim)) = players.get_mut(rig.player) else {
+            continue;
+        };
+        rig.pivot = rig.pivot.lerp(player.translation + Vec3::Y * PIVOT_HEIGHT, follow);
+        let behind = player.back();
+        let solid = QueryFilter::only_fixed().exclude_sensors();
+        let room = rapier_context
+            .cast_shape(
+                rig.pivot,
+                Quat::IDENTITY,
+                behind,
+                &Collider::ball(CAMERA_CLEARANCE),
+                CAMERA_DISTANCE,
+                solid,
+            )
+            .map_or(CAMERA_DISTANCE, |(_, toi)| toi.toi);
+        // Snap in straight away so the terrain never comes between the camera
+        // and the ball, but ease back out.
+        rig.distance = if room < rig.distance {
+            room
+        } else {
+            rig.distance + (room - rig.distance) * back_off
+        };
+        transform.rotation = player.rotation;
+        transform.translation = rig.pivot + behind * rig.distance;
+
+        // Start level with the ball so nothing between it and the camera is hit.
+        let look = transform.forward();
+        let start = transform.translation
+            + look * (player.translation - transform.translation).dot(look).max(0.0);
+        let target = rapier_context
+            .cast_ray(
+                start,
+                look,
+                AIM_RANGE,
+                true,
+                QueryFilter::default()
+                    .exclude_sensors()
+                    .exclude_rigid_body(rig.player)
+                    .exclude_collider(rig.player),
+            )
+            .map_or(start + look * AIM_RANGE, |(_, toi)| start + look * toi);
+        aim.0 = Some((target - player.translation).normalize_or_zero())
+            .filter(|direction| *direction != Vec3::ZERO);
+    }
+}
+
+pub struct ThirdPersonPlugin;
+impl Plugin for ThirdPersonPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(cleanup::<ThirdPersonCamera>.in_schedule(OnExit(GameState::InGame)))
+            // After physics has moved the balls, so the cameras never lag a frame behind.
+            .add_systems(
+                (spawn_cameras, switch_cameras, follow_players)
+                    .chain()
+                    .distributive_run_if(in_state(GameState::InGame))
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(PhysicsSet::Writeback)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
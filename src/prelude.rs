@@ -1,4 +1,5 @@
 pub use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Component)]
 pub struct Enemy;
@@ -6,6 +7,78 @@ pub struct Enemy;
 #[derive(Component)]
 pub struct Player;
 
+/// A player on this machine, numbered from 0 in split-screen order.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LocalPlayer {
+    pub index: usize,
+    pub device: InputDevice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputDevice {
+    KeyboardMouse,
+    /// The nth connected gamepad.
+    Gamepad(usize),
+}
+
+/// The local player the audio, minimap, music and ghost follow: the only one,
+/// or the first in split-screen.
+#[derive(Component)]
+pub struct PrimaryPlayer;
+
+/// Blue balls a local player has collected this round.
+#[derive(Component, Default)]
+pub struct Score(pub i32);
+
+/// How many players share this machine's screen.
+#[derive(Resource, Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalPlayers(pub usize);
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        LocalPlayers(1)
+    }
+}
+
+impl LocalPlayers {
+    pub const MAX: usize = 4;
+}
+
+/// A computer-controlled player competing for the blue balls.
+#[derive(Component)]
+pub struct Bot {
+    pub index: usize,
+}
+
+/// How many bots join a game on this machine.
+#[derive(Resource, Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BotCount(pub usize);
+
+impl BotCount {
+    pub const MAX: usize = 3;
+}
+
+#[derive(Resource, Component, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BotSkill {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// Whether players look out from their ball or follow it from behind.
+#[derive(Resource, Component, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraView {
+    #[default]
+    FirstPerson,
+    ThirdPerson,
+}
+
+/// The direction a player's hits go when it differs from where the ball is
+/// facing, as when aiming through the third-person crosshair.
+#[derive(Component, Default)]
+pub struct Aim(pub Option<Vec3>);
+
 #[derive(Component)]
 pub struct BlueBall;
 
@@ -14,15 +87,183 @@ pub struct GameInfo {
     pub is_won: bool,
     pub wins: i32,
     pub collected: i32,
+    /// Blue balls placed on the map this round.
+    pub total: i32,
+}
+#[derive(Resource, Default)]
+pub struct WorldSeed {
+    pub current: u32,
+    pub next: Option<u32>,
+    /// Terrain height of the current map.
+    pub height: u32,
+    /// Terrain height set by a multiplayer server, used instead of the one
+    /// for the display quality so everyone plays on the same map.
+    pub pinned_height: Option<u32>,
+}
+
+pub struct BallCollected;
+
+/// Blue balls taken this frame. Whatever takes a ball marks it here first, so
+/// a ball touched by several players at once only counts once.
+#[derive(Resource, Default)]
+pub struct TakenBalls(pub bevy::utils::HashSet<Entity>);
+
+/// A local player was knocked out by a red ball or lava.
+pub struct PlayerDied {
+    pub index: usize,
+}
+
+/// Which of the usual ways for a round to end still apply, so that scripts
+/// can replace them with their own.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoundRules {
+    /// Collecting every blue ball ends the round.
+    pub end_on_balls: bool,
+    /// Dying ends the round when playing alone.
+    pub end_on_death: bool,
+}
+
+impl Default for RoundRules {
+    fn default() -> Self {
+        RoundRules {
+            end_on_balls: true,
+            end_on_death: true,
+        }
+    }
+}
+
+pub struct RoundOver {
+    pub won: bool,
+}
+
+/// Event that plays a preloaded sound effect on its audio bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SoundEffect {
+    Hit,
+    Score,
+    Death,
+    Click,
+    Bounce,
+}
+
+/// Event that plays a sound effect at a point in the world, heard relative
+/// to the player camera.
+#[derive(Clone, Copy, Debug)]
+pub struct SpatialSound {
+    pub effect: SoundEffect,
+    pub position: Vec3,
+    /// Scales the effect's own volume, e.g. by impact speed.
+    pub volume: f32,
 }
+
+/// A released hit, pushing whatever is in front of the player who fired it.
+#[derive(Clone, Copy, Debug)]
+pub struct HitFired {
+    pub shooter: Entity,
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// How far the hit was charged, from 0 to 1.
+    pub charge: f32,
+    pub shockwave: bool,
+}
+
 #[derive(Component)]
 pub struct Reboot;
 
+/// Whether this game is played alone, hosts a multiplayer game or has joined
+/// one.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NetRole {
+    #[default]
+    Offline,
+    Server,
+    Client,
+}
+
+/// A player connected over the network, simulated by the server.
 #[derive(Component)]
-pub struct TextChanges;
+pub struct RemotePlayer {
+    pub id: u32,
+}
+
+/// What the spectator camera shows, if it has taken over from the players.
+#[derive(Resource, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum Spectator {
+    #[default]
+    Off,
+    /// Flies freely through the map, ignoring physics.
+    Free,
+    /// Orbits a player, bot or ghost, the first one found if `None`.
+    Follow(Option<Entity>),
+    /// Looks down on the whole map.
+    Overview,
+}
+
+impl Spectator {
+    pub fn is_active(&self) -> bool {
+        *self != Spectator::Off
+    }
+}
 
+/// Root of an in-game overlay, removed when returning to the menu.
 #[derive(Component)]
-pub struct Cursor;
+pub struct Hud;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HudWidget {
+    Objective,
+    Timer,
+    Health,
+    Crosshair,
+    Notifications,
+    Minimap,
+    Compass,
+    Ghost,
+    PowerUps,
+}
+
+impl HudWidget {
+    pub const ALL: [HudWidget; 9] = [
+        HudWidget::Objective,
+        HudWidget::Timer,
+        HudWidget::Health,
+        HudWidget::Crosshair,
+        HudWidget::Notifications,
+        HudWidget::Minimap,
+        HudWidget::Compass,
+        HudWidget::Ghost,
+        HudWidget::PowerUps,
+    ];
+}
+
+#[derive(Resource, Default)]
+pub struct HudSettings {
+    pub hidden: bevy::utils::HashSet<HudWidget>,
+}
+
+impl HudSettings {
+    pub fn is_visible(&self, widget: HudWidget) -> bool {
+        !self.hidden.contains(&widget)
+    }
+}
+
+/// A short message shown in the HUD notification area.
+pub struct Notification(pub String);
+
+#[derive(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            current: 100.0,
+            max: 100.0,
+        }
+    }
+}
 
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
 pub enum GameState {
@@ -45,22 +286,88 @@ pub fn cleanup<T: Component>(mut commands: Commands, query: Query<Entity, With<T
     }
 }
 
-#[derive(Resource)]
+#[derive(Component, Default)]
 pub struct Jump {
     pub jumping: bool,
     pub elapsed: f32,
     pub avalible: bool,
+    /// Whether the double jump power-up has been used since leaving the ground.
+    pub air_jumped: bool,
 }
 
 #[derive(Component)]
 pub struct Cube;
 
+/// Where players drop into the map at the start of a round.
+pub const SPAWN_POINT: Vec3 = Vec3::new(0.0, 80.0, 0.0);
+
+/// Where players drop into the map in play, which campaign levels move.
+#[derive(Resource, Clone, Copy)]
+pub struct SpawnPoint(pub Vec3);
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        SpawnPoint(SPAWN_POINT)
+    }
+}
+
+impl SpawnPoint {
+    /// Where a local player drops in, side by side so split-screen players
+    /// don't land on each other.
+    pub fn local(&self, index: usize) -> Vec3 {
+        self.0 + Vec3::X * 3.0 * index as f32
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum PowerUpKind {
+    SpeedBoost,
+    DoubleJump,
+    Invulnerability,
+    Magnet,
+    Radar,
+}
+
+pub struct ActivePowerUp {
+    pub kind: PowerUpKind,
+    pub remaining: f32,
+    pub stacks: u32,
+    pub strength: f32,
+}
+
+/// Event that gives the player a power-up as if they had picked it up.
+pub struct GrantPowerUp(pub PowerUpKind);
+
+/// Power-ups the player has picked up and that have not yet run out.
+#[derive(Resource, Default)]
+pub struct ActivePowerUps(pub Vec<ActivePowerUp>);
+
+impl ActivePowerUps {
+    pub fn get(&self, kind: PowerUpKind) -> Option<&ActivePowerUp> {
+        self.0.iter().find(|power_up| power_up.kind == kind)
+    }
+
+    /// Strength of an active power-up, scaled by its stacks.
+    pub fn strength(&self, kind: PowerUpKind) -> Option<f32> {
+        self.get(kind)
+            .map(|power_up| power_up.strength * power_up.stacks as f32)
+    }
+}
+
+/// Developer toggles set from the console.
+#[derive(Resource, Default)]
+pub struct Cheats {
+    pub god: bool,
+    pub noclip: bool,
+}
+
 #[derive(Resource)]
 pub struct Pause {
     pub paused: bool,
 }
 
 
+#[allow(dead_code)]
 #[derive(Component)]
 pub struct Visible {
     pub mesh: Handle<Mesh>,
@@ -71,15 +378,241 @@ pub struct Visible {
 #[derive(Component)]
 pub struct Renderable;
 
-#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
+/// How bouncy a body is out of water.
+#[derive(Component, Clone, Copy)]
+pub struct Bounciness(pub f32);
+
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum DisplayQuality {
     Low,
     Medium,
     High,
 }
 
-#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
-pub struct Volume(pub u32);
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// How far away the minimap reveals blue balls, if at all.
+    pub fn ball_detection_radius(&self) -> Option<f32> {
+        match self {
+            Difficulty::Easy => Some(24.0),
+            Difficulty::Normal => Some(12.0),
+            Difficulty::Hard => None,
+        }
+    }
+
+    pub fn enemy_detection_radius(&self) -> Option<f32> {
+        match self {
+            Difficulty::Easy => Some(24.0),
+            Difficulty::Normal => Some(16.0),
+            Difficulty::Hard => None,
+        }
+    }
+}
+
+/// The rule set for a round.
+#[derive(Resource, Debug, Component, PartialEq, Eq, Hash, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum GameMode {
+    /// Collect the blue balls while dodging red ones.
+    #[default]
+    Classic,
+    /// Collect the blue balls with no red balls on the map.
+    Zen,
+}
+
+/// Settings for generating the terrain, which mods can add more of.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainPreset {
+    pub name: String,
+    /// Overrides the height picked by the display quality.
+    pub height: Option<usize>,
+    /// How many voxels each bump of the noise spans, larger is smoother.
+    pub scale: f64,
+    /// Noise above this is solid, so higher leaves less terrain.
+    pub threshold: f64,
+}
+
+impl Default for TerrainPreset {
+    fn default() -> Self {
+        TerrainPreset {
+            name: String::new(),
+            height: None,
+            scale: 10.0,
+            threshold: 0.2,
+        }
+    }
+}
+
+/// A game mode offered in the main menu, either built in or added by a mod.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeDef {
+    pub name: String,
+    /// The built-in rules it varies, which decide whether red balls appear.
+    pub base: GameMode,
+    pub blue_balls: usize,
+    /// Chance out of 200 of a red ball on each open patch of ground.
+    pub red_balls: u32,
+    /// The name of the terrain preset to generate maps with.
+    pub terrain: Option<String>,
+    /// A rules script under the assets directory, run while the mode is played.
+    pub script: Option<String>,
+}
+
+impl Default for ModeDef {
+    fn default() -> Self {
+        ModeDef {
+            name: String::new(),
+            base: GameMode::Classic,
+            blue_balls: 5,
+            red_balls: 2,
+            terrain: None,
+            script: None,
+        }
+    }
+}
+
+/// Every game mode and terrain preset, the built-in ones first.
+#[derive(Resource, Debug, Clone)]
+pub struct GameModes {
+    pub modes: Vec<ModeDef>,
+    pub terrain: Vec<TerrainPreset>,
+}
+
+impl Default for GameModes {
+    fn default() -> Self {
+        GameModes {
+            modes: vec![
+                ModeDef {
+                    name: "Classic".to_string(),
+                    ..default()
+                },
+                ModeDef {
+                    name: "Zen".to_string(),
+                    base: GameMode::Zen,
+                    ..default()
+                },
+            ],
+            terrain: Vec::new(),
+        }
+    }
+}
+
+impl GameModes {
+    /// The index of the built-in mode for `mode`.
+    pub fn builtin(&self, mode: GameMode) -> usize {
+        self.modes
+            .iter()
+            .position(|def| def.base == mode)
+            .unwrap_or_default()
+    }
+
+    pub fn terrain(&self, name: &str) -> Option<&TerrainPreset> {
+        self.terrain.iter().find(|preset| preset.name == name)
+    }
+}
+
+/// The game mode picked in the main menu, as an index into `GameModes`.
+#[derive(Resource, Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModeChoice(pub usize);
+
+/// A mixer channel. Every sound plays on one bus and is scaled by both that
+/// bus and the master bus.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AudioBus {
+    Master,
+    Music,
+    Sfx,
+    Ui,
+}
+
+impl AudioBus {
+    pub const ALL: [AudioBus; 4] = [AudioBus::Master, AudioBus::Music, AudioBus::Sfx, AudioBus::Ui];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AudioBus::Master => "Master",
+            AudioBus::Music => "Music",
+            AudioBus::Sfx => "Effects",
+            AudioBus::Ui => "Interface",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct AudioChannel {
+    /// Volume from 0 to 9.
+    pub level: u32,
+    pub muted: bool,
+}
+
+impl AudioChannel {
+    fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.level as f32 / 9.0
+        }
+    }
+}
+
+#[derive(Resource, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: AudioChannel,
+    pub music: AudioChannel,
+    pub sfx: AudioChannel,
+    pub ui: AudioChannel,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        let full = AudioChannel {
+            level: 9,
+            muted: false,
+        };
+        AudioSettings {
+            master: AudioChannel { level: 7, ..full },
+            music: full,
+            sfx: full,
+            ui: full,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn channel(&self, bus: AudioBus) -> &AudioChannel {
+        match bus {
+            AudioBus::Master => &self.master,
+            AudioBus::Music => &self.music,
+            AudioBus::Sfx => &self.sfx,
+            AudioBus::Ui => &self.ui,
+        }
+    }
+
+    pub fn channel_mut(&mut self, bus: AudioBus) -> &mut AudioChannel {
+        match bus {
+            AudioBus::Master => &mut self.master,
+            AudioBus::Music => &mut self.music,
+            AudioBus::Sfx => &mut self.sfx,
+            AudioBus::Ui => &mut self.ui,
+        }
+    }
+
+    /// Output volume for a sound on `bus`, with the master bus applied.
+    pub fn volume(&self, bus: AudioBus) -> f32 {
+        match bus {
+            AudioBus::Master => self.master.gain(),
+            _ => self.master.gain() * self.channel(bus).gain(),
+        }
+    }
+}
 
 #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
 pub struct Fov(pub u32);
@@ -91,11 +624,16 @@ pub enum MenuState {
     SettingsDisplay,
     SettingsSound,
     SettingsFov,
+    SettingsDifficulty,
+    SettingsHud,
+    SettingsBots,
+    SettingsMods,
+    Campaign,
     #[default]
     Disabled,
 }
 
-#[derive(Resource, Deref, DerefMut)]
+#[derive(Component, Deref, DerefMut)]
 pub struct HitTimer(pub Timer);
 
 impl Default for HitTimer {
@@ -104,7 +642,55 @@ impl Default for HitTimer {
     }
 }
 
-#[derive(Resource)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum Liquid {
+    Water,
+    Lava,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Voxel {
+    #[default]
+    Empty,
+    Solid,
+    Liquid(Liquid),
+}
+
+impl Voxel {
+    pub fn is_solid(self) -> bool {
+        self == Voxel::Solid
+    }
+}
+
+/// The liquid a body is touching, if any.
+#[derive(Component, Default)]
+pub struct InLiquid(pub Option<Liquid>);
+
+/// The generated terrain, indexed as `[x][y][z]`.
+#[derive(Resource, Default)]
+pub struct VoxelMap(pub Vec<Vec<Vec<Voxel>>>);
+
+impl VoxelMap {
+    /// Height of the topmost solid voxel in the column, 0 when it is empty.
+    pub fn column_height(&self, x: usize, z: usize) -> usize {
+        crate::game::load::column_height(&self.0, x, z)
+    }
+
+    /// The liquid lying on top of the column, if any.
+    pub fn column_liquid(&self, x: usize, z: usize) -> Option<Liquid> {
+        match self.0[x].get(self.column_height(x, z)).map(|layer| layer[z]) {
+            Some(Voxel::Liquid(liquid)) => Some(liquid),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Component, Default)]
 pub struct Hit {
+    /// Whether the hit has recharged.
     pub hit: bool,
+    /// How far the next hit is charged, from 0 to 1.
+    pub charge: f32,
+    /// Whether the charging hit is a shockwave rather than a ray.
+    pub shockwave: bool,
 }
@@ -0,0 +1,907 @@
+use std::collections::HashMap;
+
+/// Statements one run of a script may take before it is stopped, so a runaway
+/// `repeat` cannot hang the game.
+const MAX_STEPS: usize = 100_000;
+
+/// How deeply blocks and expressions may nest. Parsing and running both
+/// recurse through them, so without a limit a script could overflow the stack.
+const MAX_DEPTH: usize = 100;
+
+const KEYWORDS: [&str; 11] = [
+    "let", "if", "else", "on", "repeat", "true", "false", "nil", "and", "or", "not",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "a boolean",
+            Value::Num(_) => "a number",
+            Value::Str(_) => "a string",
+        }
+    }
+
+    pub fn as_num(&self) -> Result<f64, String> {
+        match self {
+            Value::Num(value) => Ok(*value),
+            other => Err(format!("expected a number, found {}", other.type_name())),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, String> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            other => Err(format!("expected true or false, found {}", other.type_name())),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, String> {
+        match self {
+            Value::Str(value) => Ok(value),
+            other => Err(format!("expected a string, found {}", other.type_name())),
+        }
+    }
+
+    /// How the value reads when joined onto a string or printed.
+    pub fn text(&self) -> String {
+        match self {
+            Value::Nil => "nil".to_string(),
+            Value::Bool(value) => value.to_string(),
+            Value::Num(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+                format!("{}", *value as i64)
+            }
+            Value::Num(value) => format!("{value:.2}"),
+            Value::Str(value) => value.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    /// A name, keyword or symbol.
+    Word(String),
+    End,
+}
+
+fn lex(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(&c) = chars.peek() {
+        if c == '\n' {
+            line += 1;
+            chars.next();
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '/' && source_continues(&chars, "//") {
+            while chars.peek().is_some_and(|&c| c != '\n') {
+                chars.next();
+            }
+        } else if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                number.push(c);
+                chars.next();
+            }
+            let value = number
+                .parse()
+                .map_err(|_| format!("line {line}: invalid number `{number}`"))?;
+            tokens.push((Token::Num(value), line));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push((Token::Word(word), line));
+        } else if c == '"' {
+            chars.next();
+            let start = line;
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => text.push('\n'),
+                        Some(escaped @ ('"' | '\\')) => text.push(escaped),
+                        _ => return Err(format!("line {line}: unknown escape in string")),
+                    },
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        text.push(c);
+                    }
+                    None => return Err(format!("line {start}: unterminated string")),
+                }
+            }
+            tokens.push((Token::Str(text), start));
+        } else {
+            chars.next();
+            let pair: String = [c].into_iter().chain(chars.peek().copied()).collect();
+            let symbol = if ["==", "!=", "<=", ">="].contains(&pair.as_str()) {
+                chars.next();
+                pair
+            } else if "=<>+-*/%(){},;".contains(c) {
+                c.to_string()
+            } else {
+                return Err(format!("line {line}: unexpected `{c}`"));
+            };
+            tokens.push((Token::Word(symbol), line));
+        }
+    }
+    tokens.push((Token::End, line));
+    Ok(tokens)
+}
+
+fn source_continues(chars: &std::iter::Peekable<std::str::Chars>, text: &str) -> bool {
+    chars.clone().take(text.len()).eq(text.chars())
+}
+
+#[derive(Clone, Copy, Debug)]
+enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// Binary operators from loosest to tightest binding.
+const PRECEDENCE: [&[(&str, BinOp)]; 5] = [
+    &[("or", BinOp::Or)],
+    &[("and", BinOp::And)],
+    &[
+        ("==", BinOp::Eq),
+        ("!=", BinOp::Ne),
+        ("<", BinOp::Lt),
+        ("<=", BinOp::Le),
+        (">", BinOp::Gt),
+        (">=", BinOp::Ge),
+    ],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)],
+    &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+];
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Value(Value),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Vec<Line>, Vec<Line>),
+    Repeat(Expr, Vec<Line>),
+    Expr(Expr),
+}
+
+/// A statement and the line it starts on, for error messages.
+#[derive(Clone, Debug)]
+struct Line(usize, Stmt);
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error(&self, expected: &str) -> String {
+        let found = match self.peek() {
+            Token::Num(value) => Value::Num(*value).text(),
+            Token::Str(text) => format!("\"{text}\""),
+            Token::Word(word) => word.clone(),
+            Token::End => "the end of the script".to_string(),
+        };
+        format!("line {}: expected {expected}, found `{found}`", self.line())
+    }
+
+    fn is(&self, word: &str) -> bool {
+        matches!(self.peek(), Token::Word(found) if found == word)
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.is(word);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        if self.eat(word) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("`{word}`")))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.peek().clone() {
+            Token::Word(word)
+                if word.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                    && !KEYWORDS.contains(&word.as_str()) =>
+            {
+                self.advance();
+                Ok(word)
+            }
+            _ => Err(self.error("a name")),
+        }
+    }
+
+    /// Parses something nested one level deeper than what is around it.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("line {}: nested too deeply", self.line()));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn block(&mut self) -> Result<Vec<Line>, String> {
+        self.expect("{")?;
+        let mut lines = Vec::new();
+        while !self.eat("}") {
+            if *self.peek() == Token::End {
+                return Err(self.error("`}`"));
+            }
+            lines.push(self.nested(Self::statement)?);
+        }
+        Ok(lines)
+    }
+
+    fn statement(&mut self) -> Result<Line, String> {
+        let line = self.line();
+        let stmt = if self.eat("let") {
+            let name = self.name()?;
+            self.expect("=")?;
+            Stmt::Let(name, self.expr()?)
+        } else if self.eat("if") {
+            self.if_rest()?
+        } else if self.eat("repeat") {
+            let count = self.expr()?;
+            Stmt::Repeat(count, self.block()?)
+        } else if matches!(self.tokens.get(self.pos + 1), Some((Token::Word(word), _)) if word == "=") {
+            let name = self.name()?;
+            self.advance();
+            Stmt::Assign(name, self.expr()?)
+        } else {
+            Stmt::Expr(self.expr()?)
+        };
+        self.eat(";");
+        Ok(Line(line, stmt))
+    }
+
+    /// The rest of an `if` once the keyword itself has been read.
+    fn if_rest(&mut self) -> Result<Stmt, String> {
+        let condition = self.expr()?;
+        let then = self.block()?;
+        let otherwise = if !self.eat("else") {
+            Vec::new()
+        } else if self.is("if") {
+            let line = self.line();
+            self.advance();
+            vec![Line(line, self.nested(Self::if_rest)?)]
+        } else {
+            self.block()?
+        };
+        Ok(Stmt::If(condition, then, otherwise))
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        let depth = self.depth;
+        'operators: loop {
+            for (word, op) in *operators {
+                if self.eat(word) {
+                    // Each operator in a chain nests everything before it.
+                    self.depth += 1;
+                    let right = self.nested(|parser| parser.binary(level + 1))?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'operators;
+                }
+            }
+            self.depth = depth;
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.nested(Self::unary)?)))
+        } else if self.eat("not") {
+            Ok(Expr::Not(Box::new(self.nested(Self::unary)?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek().clone() {
+            Token::Num(value) => {
+                self.advance();
+                Ok(Expr::Value(Value::Num(value)))
+            }
+            Token::Str(text) => {
+                self.advance();
+                Ok(Expr::Value(Value::Str(text)))
+            }
+            _ if self.eat("true") => Ok(Expr::Value(Value::Bool(true))),
+            _ if self.eat("false") => Ok(Expr::Value(Value::Bool(false))),
+            _ if self.eat("nil") => Ok(Expr::Value(Value::Nil)),
+            _ if self.eat("(") => {
+                let inner = self.nested(Self::expr)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            _ => {
+                let name = self.name().map_err(|_| self.error("a value"))?;
+                if !self.eat("(") {
+                    return Ok(Expr::Var(name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.nested(Self::expr)?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+        }
+    }
+}
+
+/// What a script can see of and do to the game.
+pub trait Host {
+    /// A read-only value the game provides, such as `collected`.
+    fn get(&mut self, name: &str) -> Option<Value>;
+    /// Calls a game function, or returns `None` if there is no such function.
+    fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>>;
+}
+
+/// A parsed script: statements run once when it is loaded, which declare its
+/// variables, and the handlers run each time their event happens.
+pub struct Script {
+    init: Vec<Line>,
+    handlers: HashMap<String, Vec<Line>>,
+    globals: HashMap<String, Value>,
+}
+
+impl Script {
+    /// Parses a script whose handlers may only be for the given events.
+    pub fn parse(source: &str, events: &[&str]) -> Result<Script, String> {
+        let mut parser = Parser {
+            tokens: lex(source)?,
+            pos: 0,
+            depth: 0,
+        };
+        let mut script = Script {
+            init: Vec::new(),
+            handlers: HashMap::new(),
+            globals: HashMap::new(),
+        };
+        while *parser.peek() != Token::End {
+            if parser.eat("on") {
+                let line = parser.line();
+                let event = parser.name()?;
+                if !events.contains(&event.as_str()) {
+                    return Err(format!(
+                        "line {line}: unknown event `{event}`, expected one of {}",
+                        events.join(", ")
+                    ));
+                }
+                let body = parser.block()?;
+                script.handlers.entry(event).or_default().extend(body);
+            } else {
+                script.init.push(parser.statement()?);
+            }
+        }
+        Ok(script)
+    }
+
+    /// Runs the statements outside any handler.
+    pub fn start(&mut self, host: &mut dyn Host) -> Result<(), String> {
+        let init = std::mem::take(&mut self.init);
+        let result = Run::new(&mut self.globals, host).block(&init);
+        self.init = init;
+        result
+    }
+
+    pub fn handles(&self, event: &str) -> bool {
+        self.handlers.contains_key(event)
+    }
+
+    /// Runs the handlers for `event`, with `args` as local variables.
+    pub fn fire(
+        &mut self,
+        event: &str,
+        args: &[(&str, Value)],
+        host: &mut dyn Host,
+    ) -> Result<(), String> {
+        let Some(body) = self.handlers.get(event) else {
+            return Ok(());
+        };
+        let mut run = Run::new(&mut self.globals, host);
+        run.scopes.push(
+            args.iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        );
+        run.block(body).map_err(|err| format!("on {event}: {err}"))
+    }
+}
+
+/// A single run of some statements, from loading the script or an event.
+struct Run<'a> {
+    globals: &'a mut HashMap<String, Value>,
+    /// Variables declared inside handlers and blocks, innermost last.
+    scopes: Vec<HashMap<String, Value>>,
+    host: &'a mut dyn Host,
+    steps: usize,
+}
+
+impl<'a> Run<'a> {
+    fn new(globals: &'a mut HashMap<String, Value>, host: &'a mut dyn Host) -> Self {
+        Run {
+            globals,
+            scopes: Vec::new(),
+            host,
+            steps: 0,
+        }
+    }
+
+    fn block(&mut self, lines: &[Line]) -> Result<(), String> {
+        let scoped = !self.scopes.is_empty();
+        if scoped {
+            self.scopes.push(HashMap::new());
+        }
+        let result = lines.iter().try_for_each(|Line(line, stmt)| {
+            self.statement(stmt).map_err(|err| {
+                if err.starts_with("line ") {
+                    err
+                } else {
+                    format!("line {line}: {err}")
+                }
+            })
+        });
+        if scoped {
+            self.scopes.pop();
+        }
+        result
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return Err(format!("gave up after {MAX_STEPS} steps"));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<(), String> {
+        self.step()?;
+        match stmt {
+            Stmt::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                match self.scopes.last_mut() {
+                    Some(scope) => scope.insert(name.clone(), value),
+                    None => self.globals.insert(name.clone(), value),
+                };
+            }
+            Stmt::Assign(name, expr) => {
+                let value = self.eval(expr)?;
+                let slot = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(name))
+                    .or_else(|| self.globals.get_mut(name));
+                match slot {
+                    Some(slot) => *slot = value,
+                    None if self.host.get(name).is_some() => {
+                        return Err(format!("`{name}` is read-only"));
+                    }
+                    None => {
+                        return Err(format!("unknown variable `{name}`, declare it with `let` first"));
+                    }
+                }
+            }
+            Stmt::If(condition, then, otherwise) => {
+                if self.eval(condition)?.as_bool()? {
+                    self.block(then)?;
+                } else {
+                    self.block(otherwise)?;
+                }
+            }
+            Stmt::Repeat(count, body) => {
+                let count = self.eval(count)?.as_num()?;
+                // Each pass is a step, even with nothing in the body, so more
+                // than the limit would only be stopped anyway.
+                for _ in 0..count.clamp(0.0, MAX_STEPS as f64) as usize {
+                    self.step()?;
+                    self.block(body)?;
+                }
+            }
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Value(value) => Ok(value.clone()),
+            Expr::Var(name) => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .or_else(|| self.globals.get(name))
+                .cloned()
+                .or_else(|| self.host.get(name))
+                .ok_or_else(|| format!("unknown variable `{name}`")),
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                call_builtin(name, &args)
+                    .or_else(|| self.host.call(name, &args))
+                    .unwrap_or_else(|| Err(format!("unknown function `{name}`")))
+                    .map_err(|err| format!("{name}: {err}"))
+            }
+            Expr::Neg(inner) => Ok(Value::Num(-self.eval(inner)?.as_num()?)),
+            Expr::Not(inner) => Ok(Value::Bool(!self.eval(inner)?.as_bool()?)),
+            Expr::Binary(BinOp::And, left, right) => Ok(Value::Bool(
+                self.eval(left)?.as_bool()? && self.eval(right)?.as_bool()?,
+            )),
+            Expr::Binary(BinOp::Or, left, right) => Ok(Value::Bool(
+                self.eval(left)?.as_bool()? || self.eval(right)?.as_bool()?,
+            )),
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(*op, left, right)
+            }
+        }
+    }
+}
+
+fn binary(op: BinOp, left: Value, right: Value) -> Result<Value, String> {
+    match op {
+        BinOp::Eq => return Ok(Value::Bool(left == right)),
+        BinOp::Ne => return Ok(Value::Bool(left != right)),
+        // Adding to a string joins the other value onto it.
+        BinOp::Add if matches!(left, Value::Str(_)) || matches!(right, Value::Str(_)) => {
+            return Ok(Value::Str(left.text() + &right.text()));
+        }
+        _ => {}
+    }
+    let (a, b) = (left.as_num()?, right.as_num()?);
+    Ok(match op {
+        BinOp::Lt => Value::Bool(a < b),
+        BinOp::Le => Value::Bool(a <= b),
+        BinOp::Gt => Value::Bool(a > b),
+        BinOp::Ge => Value::Bool(a >= b),
+        BinOp::Add => Value::Num(a + b),
+        BinOp::Sub => Value::Num(a - b),
+        BinOp::Mul => Value::Num(a * b),
+        BinOp::Div | BinOp::Rem if b == 0.0 => return Err("division by zero".to_string()),
+        BinOp::Div => Value::Num(a / b),
+        BinOp::Rem => Value::Num(a % b),
+        BinOp::Eq | BinOp::Ne | BinOp::And | BinOp::Or => unreachable!(),
+    })
+}
+
+/// Functions that do not need the game.
+fn call_builtin(name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+    let number = |index: usize| {
+        args.get(index)
+            .ok_or_else(|| format!("missing argument {}", index + 1))
+            .and_then(Value::as_num)
+    };
+    Some(match name {
+        "abs" => number(0).map(|x| Value::Num(x.abs())),
+        "floor" => number(0).map(|x| Value::Num(x.floor())),
+        "min" => number(0).and_then(|a| Ok(Value::Num(a.min(number(1)?)))),
+        "max" => number(0).and_then(|a| Ok(Value::Num(a.max(number(1)?)))),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENTS: [&str; 2] = ["tick", "collect"];
+
+    /// A game with three blue balls collected, which can print.
+    struct TestHost {
+        printed: Vec<String>,
+    }
+
+    impl Host for TestHost {
+        fn get(&mut self, name: &str) -> Option<Value> {
+            (name == "collected").then_some(Value::Num(3.0))
+        }
+
+        fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+            if name != "print" {
+                return None;
+            }
+            self.printed.push(args.iter().map(Value::text).collect());
+            Some(Ok(Value::Nil))
+        }
+    }
+
+    fn host() -> TestHost {
+        TestHost {
+            printed: Vec::new(),
+        }
+    }
+
+    fn run(source: &str) -> Result<Script, String> {
+        let mut script = Script::parse(source, &EVENTS)?;
+        script.start(&mut host())?;
+        Ok(script)
+    }
+
+    fn error(source: &str) -> String {
+        run(source).err().expect("the script should fail")
+    }
+
+    fn global(script: &Script, name: &str) -> Value {
+        script.globals[name].clone()
+    }
+
+    fn eval(expr: &str) -> Value {
+        let script = run(&format!("let result = {expr}")).unwrap();
+        global(&script, "result")
+    }
+
+    fn word(word: &str) -> Token {
+        Token::Word(word.to_string())
+    }
+
+    #[test]
+    fn lexes_tokens_with_their_lines() {
+        let tokens = lex("let x = 1.5 // a note\n\"say \\\"hi\\\"\" <= >= != -y\n").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (word("let"), 1),
+                (word("x"), 1),
+                (word("="), 1),
+                (Token::Num(1.5), 1),
+                (Token::Str("say \"hi\"".to_string()), 2),
+                (word("<="), 2),
+                (word(">="), 2),
+                (word("!="), 2),
+                (word("-"), 2),
+                (word("y"), 2),
+                (Token::End, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_errors() {
+        assert_eq!(lex("let x = 1 # 2").unwrap_err(), "line 1: unexpected `#`");
+        assert_eq!(lex("\nlet s = \"abc").unwrap_err(), "line 2: unterminated string");
+        assert_eq!(lex("let n = 1.2.3").unwrap_err(), "line 1: invalid number `1.2.3`");
+        assert_eq!(lex("\"a\\q\"").unwrap_err(), "line 1: unknown escape in string");
+    }
+
+    #[test]
+    fn operator_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Value::Num(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Value::Num(9.0));
+        assert_eq!(eval("10 - 4 - 3"), Value::Num(3.0));
+        assert_eq!(eval("2 * 3 % 4"), Value::Num(2.0));
+        assert_eq!(eval("-2 * 3"), Value::Num(-6.0));
+        assert_eq!(eval("1 + 1 == 2 and not false"), Value::Bool(true));
+        assert_eq!(eval("true or false and false"), Value::Bool(true));
+        assert_eq!(eval("1 < 2 == true"), Value::Bool(true));
+        assert_eq!(eval("\"a\" + 1 + 2"), Value::Str("a12".to_string()));
+        assert_eq!(eval("1 + 2 + \"a\""), Value::Str("3a".to_string()));
+    }
+
+    #[test]
+    fn runtime_errors_name_the_line() {
+        assert_eq!(error("let a = 1\nlet b = a / 0"), "line 2: division by zero");
+        assert_eq!(
+            error("let a = 1 + true"),
+            "line 1: expected a number, found a boolean"
+        );
+        assert_eq!(error("launch()"), "line 1: launch: unknown function `launch`");
+        assert_eq!(error("let a = max(1)"), "line 1: max: missing argument 2");
+    }
+
+    #[test]
+    fn handler_variables_are_scoped() {
+        let mut script = run(
+            "let total = 0\n\
+             let seen = 0\n\
+             on tick {\n\
+                 let total = 10\n\
+                 if true { let inner = 1; seen = total + inner }\n\
+                 total = total + 1\n\
+             }",
+        )
+        .unwrap();
+        script.fire("tick", &[], &mut host()).unwrap();
+        assert_eq!(global(&script, "total"), Value::Num(0.0));
+        assert_eq!(global(&script, "seen"), Value::Num(11.0));
+
+        let mut script =
+            run("let last = nil\non collect { last = by }\non tick { last = by }").unwrap();
+        script
+            .fire("collect", &[("by", Value::Str("bot".to_string()))], &mut host())
+            .unwrap();
+        assert_eq!(global(&script, "last"), Value::Str("bot".to_string()));
+        assert_eq!(
+            script.fire("tick", &[], &mut host()).err().as_deref(),
+            Some("on tick: line 3: unknown variable `by`")
+        );
+    }
+
+    #[test]
+    fn let_and_assign_errors() {
+        assert_eq!(
+            error("count = 1"),
+            "line 1: unknown variable `count`, declare it with `let` first"
+        );
+        assert_eq!(error("let a = b"), "line 1: unknown variable `b`");
+        let mut script = run("on tick {\n  if true { let inner = 1 }\n  inner = 2\n}").unwrap();
+        assert_eq!(
+            script.fire("tick", &[], &mut host()).err().as_deref(),
+            Some("on tick: line 3: unknown variable `inner`, declare it with `let` first")
+        );
+        // Declaring a variable again just replaces it.
+        let script = run("let a = 1\nlet a = a + 1").unwrap();
+        assert_eq!(global(&script, "a"), Value::Num(2.0));
+    }
+
+    #[test]
+    fn host_variables_are_read_only() {
+        let script = run("let next = collected + 1").unwrap();
+        assert_eq!(global(&script, "next"), Value::Num(4.0));
+        assert_eq!(error("collected = 5"), "line 1: `collected` is read-only");
+        // The script's own variable of that name hides the game's.
+        let script = run("let collected = 1\ncollected = collected + 1").unwrap();
+        assert_eq!(global(&script, "collected"), Value::Num(2.0));
+    }
+
+    #[test]
+    fn host_functions_are_called() {
+        let mut script = Script::parse("print(\"took \" + collected)", &EVENTS).unwrap();
+        let mut host = host();
+        script.start(&mut host).unwrap();
+        assert_eq!(host.printed, ["took 3"]);
+    }
+
+    #[test]
+    fn repeat_runs_its_body() {
+        let script =
+            run("let n = 0\nrepeat 10 { n = n + 1 }\nrepeat -5 { n = n + 1 }").unwrap();
+        assert_eq!(global(&script, "n"), Value::Num(10.0));
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let gave_up = format!("line 1: gave up after {MAX_STEPS} steps");
+        assert_eq!(error("repeat 1000000000 { }"), gave_up);
+        assert_eq!(error("repeat 1000 { repeat 1000 { } }"), gave_up);
+        // Each run gets the full allowance.
+        let mut script = run("on tick { repeat 40000 { } }").unwrap();
+        for _ in 0..3 {
+            script.fire("tick", &[], &mut host()).unwrap();
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse_error = |source: &str| Script::parse(source, &EVENTS).err().unwrap();
+        assert_eq!(parse_error("let = 1"), "line 1: expected a name, found `=`");
+        assert_eq!(parse_error("let if = 3"), "line 1: expected a name, found `if`");
+        assert_eq!(
+            parse_error("let x = (1 + 2"),
+            "line 1: expected `)`, found `the end of the script`"
+        );
+        assert_eq!(
+            parse_error("let x = 1\nlet y = x +"),
+            "line 2: expected a value, found `the end of the script`"
+        );
+        assert_eq!(
+            parse_error("if true { print(\"x\")"),
+            "line 1: expected `}`, found `the end of the script`"
+        );
+        assert_eq!(
+            parse_error("repeat 3 print(1)"),
+            "line 1: expected `{`, found `print`"
+        );
+        assert_eq!(
+            parse_error("\non jump { }"),
+            "line 2: unknown event `jump`, expected one of tick, collect"
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let too_deep = [
+            format!("let x = {}1{}", "(".repeat(10_000), ")".repeat(10_000)),
+            format!("let x = {}1", "-".repeat(10_000)),
+            format!("let x = 1{}", " + 1".repeat(10_000)),
+            format!("let x = {}1{}", "abs(".repeat(10_000), ")".repeat(10_000)),
+            "if true { ".repeat(10_000),
+            format!("if true {{ }}{}", " else if true { }".repeat(10_000)),
+        ];
+        for source in too_deep {
+            assert_eq!(error(&source), "line 1: nested too deeply");
+        }
+        assert_eq!(
+            eval(&format!("{}1{}", "(".repeat(50), ")".repeat(50))),
+            Value::Num(1.0)
+        );
+        assert_eq!(eval(&["1"; 50].join(" + ")), Value::Num(50.0));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod lang;
+#[allow(clippy::module_inception)]
+pub mod script;
@@ -0,0 +1,325 @@
+use crate::console::console::{ConsoleAppExt, ConsoleState};
+use crate::game::gameplay::spawn_in_world;
+use crate::game::mods::Mods;
+use crate::prelude::*;
+use crate::script::lang::{Host, Script, Value};
+use rand::Rng;
+use std::fs;
+
+/// The events a script can handle.
+const EVENTS: [&str; 6] = ["start", "tick", "pickup", "death", "hit", "round_over"];
+
+/// The rules script in play, if any.
+#[derive(Resource, Default)]
+pub struct ScriptState {
+    script: Option<Script>,
+    /// The asset path the script was read from, kept after an error so it can
+    /// be reloaded once fixed.
+    path: Option<String>,
+    /// Whether the script came with the game mode rather than the console.
+    from_mode: bool,
+    /// Events waiting for the script, with the variables they give it.
+    queue: Vec<(&'static str, Vec<(&'static str, Value)>)>,
+    /// Set from the end of a round until the next map is ready, so ticks in
+    /// between don't see the old round.
+    between_rounds: bool,
+}
+
+/// Gives scripts access to the game.
+struct WorldHost<'w> {
+    world: &'w mut World,
+}
+
+impl Host for WorldHost<'_> {
+    fn get(&mut self, name: &str) -> Option<Value> {
+        let info = self.world.resource::<GameInfo>();
+        let number = |value: f32| Some(Value::Num(value as f64));
+        match name {
+            "collected" => number(info.collected as f32),
+            "total" => number(info.total as f32),
+            "wins" => number(info.wins as f32),
+            "seed" => number(self.world.resource::<WorldSeed>().current as f32),
+            "time" => {
+                let mut timers = self.world.query::<&FPSTimer>();
+                number(timers.iter(self.world).map(|timer| timer.elapsed).last().unwrap_or_default())
+            }
+            "mode" => {
+                let modes = self.world.resource::<GameModes>();
+                let choice = self.world.resource::<ModeChoice>();
+                Some(Value::Str(modes.modes.get(choice.0)?.name.clone()))
+            }
+            "health" | "player_x" | "player_y" | "player_z" => {
+                let mut players = self.world.query_filtered::<(&Transform, &Health), With<PrimaryPlayer>>();
+                let Some((transform, health)) = players.iter(self.world).next() else {
+                    return Some(Value::Nil);
+                };
+                match name {
+                    "health" => number(health.current),
+                    "player_x" => number(transform.translation.x),
+                    "player_y" => number(transform.translation.y),
+                    _ => number(transform.translation.z),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+        let arg = |index: usize| args.get(index).cloned().unwrap_or(Value::Nil);
+        let text = || args.iter().map(Value::text).collect::<Vec<_>>().join(" ");
+        let result = match name {
+            "print" => {
+                self.world.resource_mut::<ConsoleState>().print(text());
+                Ok(Value::Nil)
+            }
+            "notify" => {
+                self.world.send_event(Notification(text()));
+                Ok(Value::Nil)
+            }
+            "win" | "lose" => {
+                let won = name == "win";
+                let message = match arg(0) {
+                    Value::Nil if won => "You win!".to_string(),
+                    Value::Nil => "You lose".to_string(),
+                    message => message.text(),
+                };
+                finish_round(self.world, won, message);
+                Ok(Value::Nil)
+            }
+            "spawn" => arg(0).as_str().map(str::to_string).and_then(|archetype| {
+                let position = match args.len() {
+                    1 => None,
+                    4 => Some(Vec3::new(
+                        arg(1).as_num()? as f32,
+                        arg(2).as_num()? as f32,
+                        arg(3).as_num()? as f32,
+                    )),
+                    _ => return Err("expected an archetype name and optionally x, y and z".into()),
+                };
+                spawn_in_world(self.world, &archetype, position).map(|()| Value::Nil)
+            }),
+            "random" => arg(0).as_num().and_then(|min| {
+                let max = arg(1).as_num()?;
+                Ok(Value::Num(if max > min {
+                    rand::thread_rng().gen_range(min..max)
+                } else {
+                    min
+                }))
+            }),
+            "end_on_balls" => arg(0).as_bool().map(|enabled| {
+                self.world.resource_mut::<RoundRules>().end_on_balls = enabled;
+                Value::Nil
+            }),
+            "end_on_death" => arg(0).as_bool().map(|enabled| {
+                self.world.resource_mut::<RoundRules>().end_on_death = enabled;
+                Value::Nil
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+/// Ends the round the way running out of blue balls or dying would.
+fn finish_round(world: &mut World, won: bool, message: String) {
+    let mut info = world.resource_mut::<GameInfo>();
+    if info.is_won {
+        return;
+    }
+    info.is_won = true;
+    info.collected = 0;
+    if won {
+        info.wins += 1;
+    }
+    info!("Round over: {message}");
+    world.send_event(RoundOver { won });
+    world.send_event(Notification(message));
+}
+
+/// Shows a script error in the console rather than taking the game down with it.
+fn report_error(world: &mut World, message: String) {
+    warn!("{message}");
+    world.resource_mut::<ConsoleState>().print(message);
+    world.send_event(Notification("Script error, see the console".into()));
+}
+
+/// Runs `f` on the loaded script, stopping the script if it fails.
+fn with_script(
+    world: &mut World,
+    f: impl FnOnce(&mut Script, &mut WorldHost) -> Result<(), String>,
+) -> Result<(), String> {
+    let Some(mut script) = world.resource_mut::<ScriptState>().script.take() else {
+        return Ok(());
+    };
+    match f(&mut script, &mut WorldHost { world }) {
+        Ok(()) => {
+            world.resource_mut::<ScriptState>().script = Some(script);
+            Ok(())
+        }
+        Err(err) => {
+            let path = world.resource::<ScriptState>().path.clone().unwrap_or_default();
+            world.insert_resource(RoundRules::default());
+            Err(format!("script {path} stopped, {err}"))
+        }
+    }
+}
+
+/// Reads and starts the script at `path` under the assets directory, or the
+/// mod replacing it.
+fn load_script(world: &mut World, path: &str, from_mode: bool) -> Result<(), String> {
+    let file = world.resource::<Mods>().resolve(path);
+    let source = fs::read_to_string(&file).map_err(|err| format!("can't read {}: {err}", file.display()));
+    let parsed = source.and_then(|source| Script::parse(&source, &EVENTS));
+    world.insert_resource(RoundRules::default());
+    let mut state = world.resource_mut::<ScriptState>();
+    state.path = Some(path.to_string());
+    state.from_mode = from_mode;
+    state.queue.clear();
+    match parsed {
+        Ok(script) => {
+            state.script = Some(script);
+            info!("Loaded script {path}");
+            with_script(world, |script, host| script.start(host))
+        }
+        Err(err) => {
+            state.script = None;
+            Err(format!("script {path} not loaded, {err}"))
+        }
+    }
+}
+
+fn unload_script(world: &mut World) {
+    *world.resource_mut::<ScriptState>() = ScriptState::default();
+    world.insert_resource(RoundRules::default());
+}
+
+/// Loads the game mode's script when a map is generated, unless one was
+/// loaded from the console.
+fn sync_mode_script(world: &mut World) {
+    let modes = world.resource::<GameModes>();
+    let mode = *world.resource::<GameMode>();
+    let wanted = modes
+        .modes
+        .get(world.resource::<ModeChoice>().0)
+        .filter(|def| def.base == mode && *world.resource::<NetRole>() == NetRole::Offline)
+        .and_then(|def| def.script.clone());
+    let state = world.resource::<ScriptState>();
+    if state.path.is_some() && !state.from_mode || state.path == wanted {
+        return;
+    }
+    match wanted {
+        Some(path) => {
+            if let Err(message) = load_script(world, &path, true) {
+                report_error(world, message);
+            }
+        }
+        None => unload_script(world),
+    }
+}
+
+/// Collects what happened this frame for the script to react to.
+#[allow(clippy::too_many_arguments)]
+fn queue_events(
+    time: Res<Time>,
+    voxel_map: Res<VoxelMap>,
+    mut state: ResMut<ScriptState>,
+    mut collected: EventReader<BallCollected>,
+    mut died: EventReader<PlayerDied>,
+    mut fired: EventReader<HitFired>,
+    mut round_over: EventReader<RoundOver>,
+    players: Query<&LocalPlayer>,
+) {
+    let mut events = Vec::new();
+    if voxel_map.is_changed() {
+        events.push(("start", Vec::new()));
+        state.between_rounds = false;
+    }
+    events.extend(collected.iter().map(|_| ("pickup", Vec::new())));
+    events.extend(
+        died.iter()
+            .map(|death| ("death", vec![("player", Value::Num(death.index as f64 + 1.0))])),
+    );
+    events.extend(fired.iter().filter_map(|hit| {
+        let local = players.get(hit.shooter).ok()?;
+        Some((
+            "hit",
+            vec![
+                ("player", Value::Num(local.index as f64 + 1.0)),
+                ("charge", Value::Num(hit.charge as f64)),
+                ("shockwave", Value::Bool(hit.shockwave)),
+            ],
+        ))
+    }));
+    for round in round_over.iter() {
+        events.push(("round_over", vec![("won", Value::Bool(round.won))]));
+        state.between_rounds = true;
+    }
+    if !state.between_rounds {
+        events.push(("tick", vec![("dt", Value::Num(time.delta_seconds() as f64))]));
+    }
+    // A new map may bring a mode with its own script, so starts are always kept.
+    if state.script.is_some() || events.first().is_some_and(|(event, _)| *event == "start") {
+        state.queue.extend(events);
+    }
+}
+
+fn run_scripts(world: &mut World) {
+    let queue = std::mem::take(&mut world.resource_mut::<ScriptState>().queue);
+    if queue.first().is_some_and(|(event, _)| *event == "start") {
+        sync_mode_script(world);
+    }
+    for (event, args) in queue {
+        let handled = world
+            .resource::<ScriptState>()
+            .script
+            .as_ref()
+            .is_some_and(|script| script.handles(event));
+        if !handled {
+            continue;
+        }
+        if let Err(message) = with_script(world, |script, host| script.fire(event, &args, host)) {
+            report_error(world, message);
+            break;
+        }
+    }
+}
+
+fn script_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args.first() {
+        None => Ok(match &world.resource::<ScriptState>().path {
+            Some(path) => format!("script {path}"),
+            None => "no script loaded".into(),
+        }),
+        Some(&"off") => {
+            unload_script(world);
+            Ok("script unloaded".into())
+        }
+        Some(&"reload") => {
+            let state = world.resource::<ScriptState>();
+            let (path, from_mode) = (state.path.clone().ok_or("no script loaded")?, state.from_mode);
+            load_script(world, &path, from_mode)?;
+            Ok(format!("reloaded {path}"))
+        }
+        Some(path) => {
+            if *world.resource::<NetRole>() != NetRole::Offline {
+                return Err("scripts only run in offline games".into());
+            }
+            load_script(world, path, false)?;
+            Ok(format!("loaded {path}"))
+        }
+    }
+}
+
+pub struct ScriptPlugin;
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScriptState>()
+            .add_system(
+                queue_events
+                    .run_if(resource_exists::<VoxelMap>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(run_scripts.after(queue_events).in_set(OnUpdate(GameState::InGame)))
+            .add_console_command("script", "script [<path>|reload|off]", &["reload", "off"], script_command);
+    }
+}